@@ -0,0 +1,50 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use log::error;
+use sea_orm::DbErr;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{db_utils::DbUtilsError, response::internal_server_error_response};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("An error occurred while connecting to the database: {0}")]
+    DbErr(#[from] DbErr),
+    #[error(transparent)]
+    DbUtilsError(#[from] DbUtilsError),
+    #[error("An error occurred while accessing attachment storage: {0}")]
+    StorageError(#[from] std::io::Error),
+    #[error("Account {0} does not own transaction {1}")]
+    AccountDoesNotOwnTransaction(Uuid, i32),
+    #[error("Attachment with ID '{0}' not found")]
+    AttachmentNotFound(Uuid),
+    #[error("Attachment is {0} bytes, but the limit is {1} bytes")]
+    TooLarge(usize, usize),
+    #[error("Attachments of type '{0}' are not allowed")]
+    UnsupportedContentType(String),
+    #[error("File contents do not match the content type '{0}'")]
+    ContentTypeMismatch(String),
+    #[error("Could not read multipart form: {0}")]
+    MultipartError(#[from] axum::extract::multipart::MultipartError),
+    #[error("Invalid form field '{0}': {1}")]
+    InvalidField(&'static str, String),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> axum::response::Response {
+        return match self {
+            Error::DbErr(_) | Error::DbUtilsError(_) | Error::StorageError(_) => {
+                error!("{}", self);
+                internal_server_error_response()
+            },
+            Error::TooLarge(_, _) => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()).into_response(),
+            Error::UnsupportedContentType(_) | Error::ContentTypeMismatch(_) => (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string()).into_response(),
+            Error::AccountDoesNotOwnTransaction(_, _) | Error::AttachmentNotFound(_) |
+            Error::MultipartError(_) | Error::InvalidField(_, _) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            },
+        };
+    }
+}
@@ -0,0 +1,232 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use log::error;
+use schmeconomics_entities::{attachments, prelude::*, transactions};
+use sea_orm::{prelude::Uuid, ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, Set};
+use serde::Deserialize;
+use utils_rs::date_time_provider::DynDateTimeProvider;
+
+use crate::db_utils::{validate_user_account_role, Role};
+
+use {error::*, models::*, storage::*};
+
+pub mod error;
+pub mod models;
+pub mod routes;
+pub mod storage;
+
+#[cfg(test)]
+mod test;
+
+pub type DynAttachmentService = Arc<dyn AttachmentService + Send + Sync>;
+
+///
+/// Maximum length of an attachment's file name
+///
+const MAX_FILE_NAME_LEN: usize = 255;
+
+#[derive(Clone, Deserialize)]
+pub struct Config {
+    ///
+    /// Directory attachment files are stored in
+    ///
+    pub storage_dir: String,
+    ///
+    /// Largest attachment accepted, in bytes
+    ///
+    pub max_size_bytes: usize,
+    ///
+    /// MIME types attachments may have
+    ///
+    pub allowed_content_types: Vec<String>,
+}
+
+#[async_trait]
+pub trait AttachmentService {
+    async fn get_attachments(&self, user_id: Uuid, account_id: Uuid, tx_id: i32) -> Result<Vec<AttachmentModel>>;
+    async fn upload_attachment(&self, user_id: Uuid, upload: UploadAttachmentModel) -> Result<AttachmentModel>;
+    async fn download_attachment(&self, user_id: Uuid, account_id: Uuid, id: Uuid) -> Result<DownloadedAttachmentModel>;
+    async fn delete_attachment(&self, user_id: Uuid, account_id: Uuid, id: Uuid) -> Result<()>;
+    ///
+    /// Largest attachment accepted, in bytes
+    ///
+    fn max_size_bytes(&self) -> usize;
+}
+
+pub struct DbConnAttachmentService {
+    db: DbConn,
+    dt_provider: DynDateTimeProvider,
+    storage: DynAttachmentStorage,
+    config: Config,
+}
+
+#[async_trait]
+impl AttachmentService for DbConnAttachmentService {
+    async fn get_attachments(&self, user_id: Uuid, account_id: Uuid, tx_id: i32) -> Result<Vec<AttachmentModel>> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Read).await?;
+
+        Ok(
+            Attachments::find()
+                .filter(attachments::Column::AccountId.eq(account_id))
+                .filter(attachments::Column::TransactionId.eq(tx_id))
+                .order_by_asc(attachments::Column::CreatedOnUtc)
+                .all(&self.db).await?
+                .into_iter()
+                .map(AttachmentModel::from)
+                .collect()
+        )
+    }
+
+    async fn upload_attachment(&self, user_id: Uuid, upload: UploadAttachmentModel) -> Result<AttachmentModel> {
+        validate_user_account_role(&self.db, user_id, upload.account_id, Role::Write).await?;
+
+        if upload.contents.len() > self.config.max_size_bytes {
+            return Err(Error::TooLarge(upload.contents.len(), self.config.max_size_bytes));
+        }
+        let content_type = upload.content_type.trim().to_lowercase();
+        if !self.config.allowed_content_types.iter().any(|allowed| allowed.eq_ignore_ascii_case(&content_type)) {
+            return Err(Error::UnsupportedContentType(content_type));
+        }
+        // The declared type is served back on download, so it must match the contents
+        match sniff_content_type(&upload.contents) {
+            Some(sniffed) if sniffed == content_type => {},
+            None if !SNIFFED_CONTENT_TYPES.contains(&content_type.as_str()) => {},
+            _ => return Err(Error::ContentTypeMismatch(content_type)),
+        }
+        let file_name = sanitize_file_name(&upload.file_name)?;
+
+        Transactions::find_by_id(upload.tx_id)
+            .filter(transactions::Column::AccountId.eq(upload.account_id))
+            .filter(transactions::Column::DeleteOn.is_null())
+            .one(&self.db).await?
+            .ok_or(Error::AccountDoesNotOwnTransaction(upload.account_id, upload.tx_id))?;
+
+        // Store the file before the row, so a row never references a missing file
+        let id = Uuid::now_v7();
+        let size_bytes = upload.contents.len() as i64;
+        self.storage.put(&id.to_string(), upload.contents).await?;
+
+        let new_attachment = attachments::ActiveModel {
+            id:             Set(id),
+            account_id:     Set(upload.account_id),
+            transaction_id: Set(upload.tx_id),
+            file_name:      Set(file_name),
+            content_type:   Set(content_type),
+            size_bytes:     Set(size_bytes),
+            created_on_utc: Set(self.dt_provider.utc_now()),
+        };
+        match Attachments::insert(new_attachment).exec_with_returning(&self.db).await {
+            Ok(attachment) => Ok(attachment.into()),
+            Err(e) => {
+                delete_files(&self.storage, vec![id.to_string()]).await;
+                Err(e.into())
+            },
+        }
+    }
+
+    async fn download_attachment(&self, user_id: Uuid, account_id: Uuid, id: Uuid) -> Result<DownloadedAttachmentModel> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Read).await?;
+
+        let attachment = self.find_attachment(account_id, id).await?;
+        let contents = self.storage.get(&attachment.id.to_string()).await?;
+
+        Ok(DownloadedAttachmentModel { attachment: attachment.into(), contents })
+    }
+
+    async fn delete_attachment(&self, user_id: Uuid, account_id: Uuid, id: Uuid) -> Result<()> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Write).await?;
+
+        let attachment = self.find_attachment(account_id, id).await?;
+        Attachments::delete(attachment.into_active_model()).exec(&self.db).await?;
+        delete_files(&self.storage, vec![id.to_string()]).await;
+
+        Ok(())
+    }
+
+    fn max_size_bytes(&self) -> usize {
+        self.config.max_size_bytes
+    }
+}
+
+impl DbConnAttachmentService {
+    pub fn new_dyn(
+        db: DbConn,
+        dt_provider: DynDateTimeProvider,
+        storage: DynAttachmentStorage,
+        config: Config,
+    ) -> DynAttachmentService {
+        Arc::new(DbConnAttachmentService { db, dt_provider, storage, config })
+    }
+
+    async fn find_attachment(&self, account_id: Uuid, id: Uuid) -> Result<attachments::Model> {
+        Attachments::find_by_id(id)
+            .filter(attachments::Column::AccountId.eq(account_id))
+            .one(&self.db).await?
+            .ok_or(Error::AttachmentNotFound(id))
+    }
+}
+
+///
+/// Content types which `sniff_content_type` can recognize
+///
+const SNIFFED_CONTENT_TYPES: [&str; 6] = ["image/png", "image/jpeg", "image/gif", "image/webp", "image/heic", "application/pdf"];
+
+///
+/// Recognizes the content type of a file from its leading magic bytes
+///
+fn sniff_content_type(contents: &[u8]) -> Option<&'static str> {
+    return match contents {
+        [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [_, _, _, _, b'f', b't', b'y', b'p', brand @ ..] if [b"heic", b"heix", b"mif1", b"msf1"].iter().any(|b| brand.starts_with(*b)) => {
+            Some("image/heic")
+        },
+        [b'%', b'P', b'D', b'F', b'-', ..] => Some("application/pdf"),
+        _ => None,
+    };
+}
+
+///
+/// Strips any directories from an uploaded file name, ensuring a name remains
+///
+fn sanitize_file_name(file_name: &str) -> Result<String> {
+    let file_name = file_name.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+    if file_name.is_empty() || file_name.len() > MAX_FILE_NAME_LEN {
+        return Err(Error::InvalidField("file", format!("file name must be 1 - {} characters", MAX_FILE_NAME_LEN)));
+    }
+    Ok(file_name.to_string())
+}
+
+///
+/// Deletes the attachment rows of the transactions, returning the storage keys of their files.
+/// The files should be deleted with `delete_files` once the surrounding DB transaction commits.
+///
+pub(crate) async fn delete_tx_attachments(db: &impl ConnectionTrait, tx_ids: Vec<i32>) -> std::result::Result<Vec<String>, DbErr> {
+    let keys = Attachments::find()
+        .filter(attachments::Column::TransactionId.is_in(tx_ids.clone()))
+        .all(db).await?
+        .into_iter()
+        .map(|attachment| attachment.id.to_string())
+        .collect::<Vec<String>>();
+    if !keys.is_empty() {
+        Attachments::delete_many()
+            .filter(attachments::Column::TransactionId.is_in(tx_ids))
+            .exec(db).await?;
+    }
+    Ok(keys)
+}
+
+///
+/// Deletes the files from storage. Failures are logged rather than returned,
+/// as the rows referencing the files are already gone.
+///
+pub(crate) async fn delete_files(storage: &DynAttachmentStorage, keys: Vec<String>) {
+    for key in keys {
+        if let Err(e) = storage.delete(&key).await {
+            error!("Could not delete attachment file '{}': {}", key, e);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use sea_orm::prelude::{DateTimeUtc, Uuid};
+use serde::Serialize;
+
+use schmeconomics_entities::attachments;
+
+#[derive(Debug, Serialize)]
+pub struct AttachmentModel {
+    pub id: Uuid,
+    pub tx_id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub created_on_utc: DateTimeUtc,
+}
+
+impl From<attachments::Model> for AttachmentModel {
+    fn from(value: attachments::Model) -> Self {
+        AttachmentModel {
+            id: value.id,
+            tx_id: value.transaction_id,
+            file_name: value.file_name,
+            content_type: value.content_type,
+            size_bytes: value.size_bytes,
+            created_on_utc: value.created_on_utc,
+        }
+    }
+}
+
+pub struct UploadAttachmentModel {
+    pub account_id: Uuid,
+    pub tx_id: i32,
+    pub file_name: String,
+    pub content_type: String,
+    pub contents: Vec<u8>,
+}
+
+pub struct DownloadedAttachmentModel {
+    pub attachment: AttachmentModel,
+    pub contents: Vec<u8>,
+}
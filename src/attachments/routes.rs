@@ -0,0 +1,105 @@
+use axum::{extract::{DefaultBodyLimit, Multipart, Path, State}, http::header, response::IntoResponse, routing::{delete, get, post}, Json, Router};
+use uuid::Uuid;
+
+use crate::{auth::middleware::AuthUser, state::AppState};
+
+use super::{error::{Error, Result}, models::{AttachmentModel, UploadAttachmentModel}, DynAttachmentService};
+
+///
+/// Allowance for the multipart boundaries and non-file fields of an upload form
+///
+const FORM_OVERHEAD_BYTES: usize = 16 * 1024;
+
+pub fn routes(state: AppState) -> Router {
+    let body_limit = state.attachment_svc.max_size_bytes() + FORM_OVERHEAD_BYTES;
+    Router::new()
+        .route("/upload", post(upload_attachment).layer(DefaultBodyLimit::max(body_limit)))
+        .route("/{account_id}/transaction/{tx_id}", get(get_attachments))
+        .route("/{account_id}/{id}", get(download_attachment))
+        .route("/{account_id}/{id}", delete(delete_attachment))
+        .with_state(state)
+}
+
+pub async fn get_attachments(
+    State(attachment_svc): State<DynAttachmentService>,
+    Path((account_id, tx_id)): Path<(Uuid, i32)>,
+    user: AuthUser,
+) -> Result<Json<Vec<AttachmentModel>>> {
+    Ok(Json(attachment_svc.get_attachments(user.id, account_id, tx_id).await?))
+}
+
+///
+/// Uploads an attachment. Expects a multipart form with the fields
+/// `account_id`, `tx_id` and `file`, where `file` has a file name and content type
+///
+pub async fn upload_attachment(
+    State(attachment_svc): State<DynAttachmentService>,
+    user: AuthUser,
+    mut multipart: Multipart,
+) -> Result<Json<AttachmentModel>> {
+    let (mut account_id, mut tx_id, mut file) = (None, None, None);
+    while let Some(field) = multipart.next_field().await? {
+        match field.name() {
+            Some("account_id") => {
+                account_id = Some(
+                    field.text().await?.trim().parse::<Uuid>()
+                        .map_err(|e| Error::InvalidField("account_id", e.to_string()))?
+                );
+            },
+            Some("tx_id") => {
+                tx_id = Some(
+                    field.text().await?.trim().parse::<i32>()
+                        .map_err(|e| Error::InvalidField("tx_id", e.to_string()))?
+                );
+            },
+            Some("file") => {
+                let file_name = field.file_name().unwrap_or_default().to_string();
+                let content_type = field.content_type().unwrap_or_default().to_string();
+                file = Some((file_name, content_type, field.bytes().await?.to_vec()));
+            },
+            _ => { },
+        }
+    }
+
+    let (file_name, content_type, contents) = file.ok_or(Error::InvalidField("file", String::from("field is missing")))?;
+    let upload = UploadAttachmentModel {
+        account_id: account_id.ok_or(Error::InvalidField("account_id", String::from("field is missing")))?,
+        tx_id: tx_id.ok_or(Error::InvalidField("tx_id", String::from("field is missing")))?,
+        file_name,
+        content_type,
+        contents,
+    };
+
+    Ok(Json(attachment_svc.upload_attachment(user.id, upload).await?))
+}
+
+pub async fn download_attachment(
+    State(attachment_svc): State<DynAttachmentService>,
+    Path((account_id, id)): Path<(Uuid, Uuid)>,
+    user: AuthUser,
+) -> Result<impl IntoResponse> {
+    let downloaded = attachment_svc.download_attachment(user.id, account_id, id).await?;
+    // Only plain ASCII can be quoted in the header, so replace anything else
+    let file_name = downloaded.attachment.file_name.chars()
+        .map(|c| if (c.is_ascii_graphic() || c == ' ') && c != '"' && c != '\\' { c } else { '_' })
+        .collect::<String>();
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, downloaded.attachment.content_type),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", file_name)),
+            // Browsers must not render the file as anything other than its declared type
+            (header::X_CONTENT_TYPE_OPTIONS, String::from("nosniff")),
+        ],
+        downloaded.contents,
+    ))
+}
+
+pub async fn delete_attachment(
+    State(attachment_svc): State<DynAttachmentService>,
+    Path((account_id, id)): Path<(Uuid, Uuid)>,
+    user: AuthUser,
+) -> Result<()> {
+    attachment_svc.delete_attachment(user.id, account_id, id).await?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::{io, path::PathBuf, sync::Arc};
+
+use async_trait::async_trait;
+
+#[cfg(test)]
+use mockall::automock;
+
+pub type DynAttachmentStorage = Arc<dyn AttachmentStorage + Send + Sync>;
+
+///
+/// Backend storing the contents of attachment files, addressed by key
+///
+#[cfg_attr(test, automock)]
+#[async_trait]
+pub trait AttachmentStorage {
+    async fn put(&self, key: &str, contents: Vec<u8>) -> io::Result<()>;
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>>;
+    ///
+    /// Deletes the file stored under the key. Deleting a missing file is not an error
+    ///
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+///
+/// Stores attachments as files in a local directory
+///
+pub struct FsAttachmentStorage {
+    root: PathBuf,
+}
+
+impl FsAttachmentStorage {
+    pub fn new_dyn(root: impl Into<PathBuf>) -> DynAttachmentStorage {
+        Arc::new(FsAttachmentStorage { root: root.into() })
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        // Keys are generated by the server, but never let one escape the storage directory
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid attachment key '{}'", key)));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl AttachmentStorage for FsAttachmentStorage {
+    async fn put(&self, key: &str, contents: Vec<u8>) -> io::Result<()> {
+        let path = self.path(key)?;
+        tokio::fs::create_dir_all(&self.root).await?;
+        tokio::fs::write(path, contents).await
+    }
+
+    async fn get(&self, key: &str) -> io::Result<Vec<u8>> {
+        tokio::fs::read(self.path(key)?).await
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            res => res,
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use mockall::predicate::{always, eq};
+use sea_orm::{sea_query::TableCreateStatement, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, Schema, Set};
+use uuid::Uuid;
+
+use schmeconomics_entities::{account_users, accounts, prelude::*, transactions, users};
+use utils_rs::date_time_provider::MockDateTimeProvider;
+
+use crate::{attachments::{models::UploadAttachmentModel, storage::MockAttachmentStorage, AttachmentService, Config, Error}, db_utils::{DbUtilsError, Role}};
+
+use super::DbConnAttachmentService;
+
+lazy_static! {
+    static ref TEST_USER_1_ID: Uuid = Uuid::parse_str("be5ca263-2307-4e5a-acbd-3281fb81ea60").unwrap();
+    static ref TEST_USER_2_ID: Uuid = Uuid::parse_str("e8411903-c326-4ffe-9dd0-cb766b9299e4").unwrap();
+    static ref TEST_ACCOUNT_1_ID: Uuid = Uuid::parse_str("f017369e-9dd1-4434-b197-40361cc0dbcd").unwrap();
+
+    // 2024-11-10 12:03:34
+    static ref TEST_DT: DateTime<Utc> = DateTime::<Utc>::from_timestamp_millis(1731240214000).unwrap();
+}
+
+async fn create_test_db() -> anyhow::Result<DbConn> {
+    // In-memory Sqlite connection
+    let db = Database::connect("sqlite::memory:").await?;
+
+    // Schema and Tables SeaOrm statements
+    let schema = Schema::new(DbBackend::Sqlite);
+    let user_stmt: TableCreateStatement = schema.create_table_from_entity(Users);
+    let account_stmt: TableCreateStatement = schema.create_table_from_entity(Accounts);
+    let account_user_stmt: TableCreateStatement = schema.create_table_from_entity(AccountUsers);
+    let tx_stmt: TableCreateStatement = schema.create_table_from_entity(Transactions);
+    let attachment_stmt: TableCreateStatement = schema.create_table_from_entity(Attachments);
+
+    db.execute(db.get_database_backend().build(&user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_stmt)).await?;
+    db.execute(db.get_database_backend().build(&attachment_stmt)).await?;
+
+    // Insert test users, the 2nd of which can only read the account
+    for (id, email, role) in [(*TEST_USER_1_ID, "user1@mail.com", Role::Admin), (*TEST_USER_2_ID, "user2@mail.com", Role::Read)] {
+        let new_user = users::ActiveModel {
+            id: Set(id),
+            email: Set(String::from(email)),
+            email_verified: Set(true),
+            password_hash: Set(String::from("password")),
+            name: Set(String::from("tester")),
+            created_on_utc: Set(Utc::now()),
+            two_factor_enabled: Set(false),
+
+            ..Default::default()
+        };
+        Users::insert(new_user).exec(&db).await?;
+
+        if id == *TEST_USER_1_ID {
+            let account = accounts::ActiveModel {
+                id: Set(*TEST_ACCOUNT_1_ID),
+                ..Default::default()
+            };
+            Accounts::insert(account).exec(&db).await?;
+        }
+
+        let account_user = account_users::ActiveModel {
+            account_id: Set(*TEST_ACCOUNT_1_ID),
+            user_id: Set(id),
+            role: Set(role.to_string()),
+            verified: Set(true),
+            created_on: Set(Utc::now()),
+        };
+        AccountUsers::insert(account_user).exec(&db).await?;
+    }
+
+    Transactions::insert(
+        transactions::ActiveModel {
+            account_id: Set(*TEST_ACCOUNT_1_ID),
+            timestamp: Set(*TEST_DT),
+            amount: Set(-1000),
+            is_refill: Set(false),
+
+            ..Default::default()
+        }
+    ).exec(&db).await?;
+
+    Ok(db)
+}
+
+fn create_test_service(db: &DbConn, storage: MockAttachmentStorage) -> DbConnAttachmentService {
+    let mut mock_dt_service = MockDateTimeProvider::new();
+    mock_dt_service.expect_utc_now().returning(|| *TEST_DT);
+
+    DbConnAttachmentService {
+        db: db.clone(),
+        dt_provider: Arc::new(mock_dt_service),
+        storage: Arc::new(storage),
+        config: Config {
+            storage_dir: String::from("./attachments"),
+            max_size_bytes: 16,
+            allowed_content_types: vec![String::from("image/png"), String::from("application/pdf")],
+        },
+    }
+}
+
+const PNG_HEADER: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn upload(file_name: &str, content_type: &str, contents: &[u8]) -> UploadAttachmentModel {
+    UploadAttachmentModel {
+        account_id: *TEST_ACCOUNT_1_ID,
+        tx_id: 1,
+        file_name: String::from(file_name),
+        content_type: String::from(content_type),
+        contents: contents.to_vec(),
+    }
+}
+
+#[tokio::test]
+async fn test_upload_download_and_delete() -> anyhow::Result<()> {
+    let db = create_test_db().await?;
+
+    let mut mock_storage = MockAttachmentStorage::new();
+    mock_storage.expect_put().with(always(), eq(b"%PDF-1.7".to_vec())).times(1).returning(|_, _| Ok(()));
+    mock_storage.expect_get().returning(|_| Ok(b"%PDF-1.7".to_vec()));
+    mock_storage.expect_delete().times(1).returning(|_| Ok(()));
+    let svc = create_test_service(&db, mock_storage);
+
+    // Directories are stripped from the file name
+    let attachment = svc.upload_attachment(*TEST_USER_1_ID, upload("C:\\scans\\receipt.pdf", "Application/PDF", b"%PDF-1.7")).await?;
+    assert_eq!("receipt.pdf", attachment.file_name);
+    assert_eq!("application/pdf", attachment.content_type);
+    assert_eq!(8, attachment.size_bytes);
+
+    // Readers can list and download, but not delete
+    let attachments = svc.get_attachments(*TEST_USER_2_ID, *TEST_ACCOUNT_1_ID, 1).await?;
+    assert_eq!(1, attachments.len());
+    let downloaded = svc.download_attachment(*TEST_USER_2_ID, *TEST_ACCOUNT_1_ID, attachment.id).await?;
+    assert_eq!(b"%PDF-1.7".to_vec(), downloaded.contents);
+
+    let res = svc.delete_attachment(*TEST_USER_2_ID, *TEST_ACCOUNT_1_ID, attachment.id).await;
+    assert!(matches!(res, Err(Error::DbUtilsError(DbUtilsError::UserNotPartOfAccount(_, _)))));
+
+    svc.delete_attachment(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, attachment.id).await?;
+    assert!(Attachments::find().all(&db).await?.is_empty());
+
+    let res = svc.download_attachment(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, attachment.id).await;
+    assert!(matches!(res, Err(Error::AttachmentNotFound(id)) if id == attachment.id));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_upload_limits() -> anyhow::Result<()> {
+    let db = create_test_db().await?;
+    // Nothing is stored when an upload is rejected
+    let svc = create_test_service(&db, MockAttachmentStorage::new());
+
+    let res = svc.upload_attachment(*TEST_USER_1_ID, upload("receipt.png", "image/png", &[0; 17])).await;
+    assert!(matches!(res, Err(Error::TooLarge(17, 16))));
+
+    let res = svc.upload_attachment(*TEST_USER_1_ID, upload("receipt.exe", "application/x-msdownload", &[0; 8])).await;
+    assert!(matches!(res, Err(Error::UnsupportedContentType(_))));
+
+    let res = svc.upload_attachment(*TEST_USER_2_ID, upload("receipt.png", "image/png", &[0; 8])).await;
+    assert!(matches!(res, Err(Error::DbUtilsError(DbUtilsError::UserNotPartOfAccount(_, _)))));
+
+    // The contents must match the declared type
+    let res = svc.upload_attachment(*TEST_USER_1_ID, upload("receipt.png", "image/png", &[0; 8])).await;
+    assert!(matches!(res, Err(Error::ContentTypeMismatch(_))));
+    let res = svc.upload_attachment(*TEST_USER_1_ID, upload("receipt.png", "image/png", b"%PDF-1.7")).await;
+    assert!(matches!(res, Err(Error::ContentTypeMismatch(_))));
+
+    let res = svc.upload_attachment(*TEST_USER_1_ID, UploadAttachmentModel { tx_id: 2, ..upload("receipt.png", "image/png", PNG_HEADER) }).await;
+    assert!(matches!(res, Err(Error::AccountDoesNotOwnTransaction(_, 2))));
+
+    Ok(())
+}
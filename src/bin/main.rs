@@ -1,9 +1,9 @@
-use std::fs;
+use std::{fs, time::Duration};
 
 use axum::Router;
 use reqwest::Client;
 use schmeconomics_auth::auth_service::CoreAuthService;
-use schmeconomics_server::{accounts::{self, DbConnAccountService}, auth, categories::{self, DbConnCategoryService}, config::Config, currency_conv_provider::PaikamaCurrencyConversionProvider, state::AppState, transactions::{self, DbConnTransactionService}, users::{self, DbConnUserService}, validations::DbConnValidationService};
+use schmeconomics_server::{accounts::{self, DbConnAccountService}, attachments::{self, storage::FsAttachmentStorage, DbConnAttachmentService}, auth, categories::{self, DbConnCategoryService}, config::Config, currency_conv_provider::PaikamaCurrencyConversionProvider, payees::{self, DbConnPayeeService}, reconciliations::{self, DbConnReconciliationService}, jobs::spawn_interval_job, recurring::{self, DbConnRecurringService}, refills::{self, DbConnRefillService}, reports::{self, DbConnReportService}, rules::{self, DbConnRuleService}, state::AppState, tags::{self, DbConnTagService}, transactions::{self, DbConnTransactionService}, users::{self, DbConnUserService}, validations::DbConnValidationService};
 use sea_orm::Database;
 use send_email_rs::TerraLettreSendEmailService;
 use tokens_rs::{password_hasher::Argon2PasswordHasher, token_service::HmacSha256TokenService};
@@ -33,12 +33,63 @@ async fn main() -> anyhow::Result<()> {
         &env_provider.get_var("./email_templates/*")?,
     );
 
+    let idempotency_purge_interval_s = config.tx_svc_config.idempotency_purge_interval_s;
+    let trash_purge_interval_s = config.tx_svc_config.trash_purge_interval_s;
+    let attachment_storage = FsAttachmentStorage::new_dyn(&config.attachment_svc_config.storage_dir);
     let account_svc = DbConnAccountService::new_dyn(db.clone(), send_email_svc, validation_svc, time_provider.clone());
     let user_svc = DbConnUserService::new_dyn(db.clone(), password_hasher);
-    let cat_svc = DbConnCategoryService::new_dyn(db.clone());
-    let tx_svc = DbConnTransactionService::new_dyn(db, time_provider, cc_provider);
+    let cat_svc = DbConnCategoryService::new_dyn(db.clone(), time_provider.clone());
+    let tx_svc = DbConnTransactionService::new_dyn(db.clone(), time_provider.clone(), cc_provider.clone(), attachment_storage.clone(), config.tx_svc_config);
+    let refill_svc = DbConnRefillService::new_dyn(db.clone(), time_provider.clone());
+    let recurring_svc = DbConnRecurringService::new_dyn(db.clone(), time_provider.clone(), cc_provider);
+    let attachment_svc = DbConnAttachmentService::new_dyn(db.clone(), time_provider.clone(), attachment_storage, config.attachment_svc_config);
+    let reconciliation_svc = DbConnReconciliationService::new_dyn(db.clone(), time_provider.clone());
+    let tag_svc = DbConnTagService::new_dyn(db.clone());
+    let payee_svc = DbConnPayeeService::new_dyn(db.clone());
+    let report_svc = DbConnReportService::new_dyn(db.clone(), time_provider.clone());
+    let rule_svc = DbConnRuleService::new_dyn(db, time_provider);
 
-    let app_state = AppState { auth_svc, token_svc, cat_svc, tx_svc, account_svc, user_svc, };
+    let app_state = AppState { auth_svc, token_svc, cat_svc, tx_svc, account_svc, user_svc, refill_svc, recurring_svc, tag_svc, attachment_svc, payee_svc, rule_svc, reconciliation_svc, report_svc, };
+
+    let job_refill_svc = app_state.refill_svc.clone();
+    spawn_interval_job(
+        "refill_due_accounts", 
+        Duration::from_secs(config.refill_svc_config.run_interval_s),
+        move || { 
+            let refill_svc = job_refill_svc.clone();
+            async move { refill_svc.refill_due_accounts().await }
+        }
+    );
+
+    let job_tx_svc = app_state.tx_svc.clone();
+    spawn_interval_job(
+        "purge_idempotency_keys", 
+        Duration::from_secs(idempotency_purge_interval_s),
+        move || { 
+            let tx_svc = job_tx_svc.clone();
+            async move { tx_svc.purge_idempotency_keys().await }
+        }
+    );
+
+    let job_tx_svc = app_state.tx_svc.clone();
+    spawn_interval_job(
+        "purge_deleted_transactions", 
+        Duration::from_secs(trash_purge_interval_s),
+        move || { 
+            let tx_svc = job_tx_svc.clone();
+            async move { tx_svc.purge_trash().await }
+        }
+    );
+
+    let job_recurring_svc = app_state.recurring_svc.clone();
+    spawn_interval_job(
+        "post_due_recurring_transactions", 
+        Duration::from_secs(config.recurring_svc_config.run_interval_s),
+        move || { 
+            let recurring_svc = job_recurring_svc.clone();
+            async move { recurring_svc.post_due_transactions().await }
+        }
+    );
 
     let app = Router::new()
         .nest(
@@ -48,7 +99,15 @@ async fn main() -> anyhow::Result<()> {
                 .nest("/users", users::routes::routes(app_state.clone()))
                 .nest("/auth", auth::routes::routes(app_state.clone()))
                 .nest("/categories", categories::routes::routes(app_state.clone()))
-                .nest("/transactions", transactions::routes::routes(app_state))
+                .nest("/transactions", transactions::routes::routes(app_state.clone()))
+                .nest("/refills", refills::routes::routes(app_state.clone()))
+                .nest("/recurring", recurring::routes::routes(app_state.clone()))
+                .nest("/tags", tags::routes::routes(app_state.clone()))
+                .nest("/attachments", attachments::routes::routes(app_state.clone()))
+                .nest("/payees", payees::routes::routes(app_state.clone()))
+                .nest("/rules", rules::routes::routes(app_state.clone()))
+                .nest("/reconciliations", reconciliations::routes::routes(app_state.clone()))
+                .nest("/reports", reports::routes::routes(app_state))
         )
         .layer(TraceLayer::new_for_http());
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
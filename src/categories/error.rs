@@ -24,6 +24,12 @@ pub enum Error {
     OrderDuplicateId(Uuid),
     #[error("Duplicate Order Index: {0}")]
     OrderDuplicateIndex(i32),
+    #[error("Cannot transfer from category '{0}' to itself")]
+    TransferToSameCategory(Uuid),
+    #[error("Transfer amount must be positive, got {0}")]
+    InvalidTransferAmount(i64),
+    #[error("Category '{0}' is used by {1} transaction splits, and cannot be deleted")]
+    CategoryHasSplits(Uuid, u64),
 }
 
 impl IntoResponse for Error {
@@ -35,7 +41,8 @@ impl IntoResponse for Error {
             },
             Error::NameReuse(_) | Error::CategoryNotFound(_) |
             Error::OrderDuplicateId(_) | Error::OrderDuplicateIndex(_) | 
-            Error::UserDoesNotOwnAccount(_) => {
+            Error::UserDoesNotOwnAccount(_) | Error::TransferToSameCategory(_) |
+            Error::InvalidTransferAmount(_) | Error::CategoryHasSplits(_, _) => {
                 (StatusCode::BAD_REQUEST, self.to_string()).into_response()
             }
         };
@@ -1,10 +1,12 @@
-use std::{collections::HashSet, sync::Arc};
+use std::{collections::{HashMap, HashSet}, sync::Arc};
 
 use async_trait::async_trait;
-use schmeconomics_entities::{categories, prelude::*};
-use sea_orm::{prelude::{Expr, Uuid}, sea_query::{ExprTrait, Func}, ActiveValue::NotSet, ColumnTrait, Condition, ConnectionTrait, DbConn, EntityTrait, FromQueryResult, IntoActiveModel, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait};
+use schmeconomics_entities::{categories, category_ledger, payees, prelude::*, recurring_transactions, transaction_splits, transactions};
+use sea_orm::{prelude::{DateTimeUtc, Expr, Uuid}, sea_query::{ExprTrait, Func}, ActiveValue::NotSet, ColumnTrait, Condition, ConnectionTrait, DbConn, EntityTrait, FromQueryResult, IntoActiveModel, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, QueryTrait, Set, TransactionTrait};
 
-use crate::db_utils::{validate_user_account_role, Role};
+use utils_rs::date_time_provider::DynDateTimeProvider;
+
+use crate::db_utils::{adjust_category_balances, record_ledger_entry, validate_user_account_role, LedgerContext, LedgerSource, Role};
 
 use {error::*, models::*};
 
@@ -24,10 +26,27 @@ pub trait CategoryService {
     async fn update_cat(&self, user_id: Uuid, cat: UpdateCategoryModel) -> Result<GetCategoryModel>;
     async fn delete_cat(&self, user_id: Uuid, cat: DeleteCategoryModel) -> Result<()>;
     async fn order_cats(&self, user_id: Uuid, cats: OrderCategoriesModel) -> Result<()>;
+    ///
+    /// Moves money from one category to another in the same account, recording
+    /// a linked debit and credit transaction so the transfer shows in history
+    ///
+    async fn transfer(&self, user_id: Uuid, transfer: TransferModel) -> Result<TransferResultModel>;
+    ///
+    /// Lists every change made to the category's balance, oldest first
+    ///
+    async fn get_ledger(&self, user_id: Uuid, account_id: Uuid, cat_id: Uuid) -> Result<Vec<LedgerEntryModel>>;
+    ///
+    /// Compares each category's balance with the balance recomputed from its history,
+    /// returning the categories which do not match.
+    /// Categories created before balance changes were recorded have no opening balance in the ledger.
+    /// The first recompute records one for them, as their balance less their transactions.
+    ///
+    async fn recompute_balances(&self, user_id: Uuid, recompute: RecomputeBalancesModel) -> Result<Vec<BalanceMismatchModel>>;
 }
 
 pub struct DbConnCategoryService {
     db: DbConn,
+    dt_provider: DynDateTimeProvider,
 }
 
 #[async_trait]
@@ -82,7 +101,13 @@ impl CategoryService for DbConnCategoryService {
             order: Set(max_order + 1),
         };
 
-        Categories::insert(new_cat).exec(&tx).await?;
+        let new_cat = Categories::insert(new_cat).exec_with_returning(&tx).await?;
+        record_ledger_entry(
+            &tx,
+            &new_cat,
+            create_cat.init_bal,
+            &LedgerContext { source: LedgerSource::Opening, user_id: Some(user_id), timestamp: self.dt_provider.utc_now() }
+        ).await?;
         tx.commit().await?;
 
         Ok(
@@ -115,12 +140,22 @@ impl CategoryService for DbConnCategoryService {
             .one(&tx).await?;
 
         return if let Some(ex_cat) = ex_cat {
+            let bal_delta = cat.new_bal.map(|bal| bal - ex_cat.balance).unwrap_or(0);
+
             // Update the row with each value provided
             let mut ex_cat = ex_cat.into_active_model();
             ex_cat.name = if let Some(fmt_cat_name) = fmt_cat_name { Set(fmt_cat_name) } else { NotSet };
             ex_cat.refill_value = if let Some(refill_val) = cat.new_refill_val { Set(refill_val) } else { NotSet };
             ex_cat.balance = if let Some(bal) = cat.new_bal { Set(bal) } else { NotSet };
             let updated = Categories::update(ex_cat).exec(&tx).await?;
+            if bal_delta != 0 {
+                record_ledger_entry(
+                    &tx,
+                    &updated,
+                    bal_delta,
+                    &LedgerContext { source: LedgerSource::Manual, user_id: Some(user_id), timestamp: self.dt_provider.utc_now() }
+                ).await?;
+            }
             tx.commit().await?;
 
             Ok(GetCategoryModel {
@@ -146,9 +181,25 @@ impl CategoryService for DbConnCategoryService {
             .one(&tx).await?;
 
         return if let Some(cat) = cat {  
+            // A split must have a category, so the category cannot be deleted while any split uses it
+            let split_count = TransactionSplits::find()
+                .filter(transaction_splits::Column::CategoryId.eq(cat.id))
+                .count(&tx).await?;
+            if split_count > 0 {
+                return Err(Error::CategoryHasSplits(cat.id, split_count));
+            }
+
             Categories::update_many().filter(categories::Column::Order.gt(cat.order))
                 .col_expr(categories::Column::Order, Expr::col(categories::Column::Order).add(1))
                 .exec(&tx).await?;
+            // Payees no longer default to the deleted category
+            Payees::update_many().filter(payees::Column::DefaultCategoryId.eq(cat.id))
+                .col_expr(payees::Column::DefaultCategoryId, Expr::value(Option::<Uuid>::None))
+                .exec(&tx).await?;
+            // Recurring transactions are posted uncategorized from now on
+            RecurringTransactions::update_many().filter(recurring_transactions::Column::CategoryId.eq(cat.id))
+                .col_expr(recurring_transactions::Column::CategoryId, Expr::value(Option::<Uuid>::None))
+                .exec(&tx).await?;
             Categories::delete(cat.into_active_model()).exec(&tx).await?;
             tx.commit().await?;
             
@@ -187,12 +238,213 @@ impl CategoryService for DbConnCategoryService {
         tx.commit().await?;
         Ok(()) 
     }
+
+    async fn transfer(&self, user_id: Uuid, transfer: TransferModel) -> Result<TransferResultModel> {
+        validate_user_account_role(&self.db, user_id, transfer.account_id, Role::Write).await?;
+
+        if transfer.from_cat_id == transfer.to_cat_id {
+            return Err(Error::TransferToSameCategory(transfer.from_cat_id));
+        }
+        if transfer.am <= 0 {
+            return Err(Error::InvalidTransferAmount(transfer.am));
+        }
+
+        let tx = self.db.begin().await?;
+
+        // Both categories must belong to the account, so transfers cannot cross accounts
+        for cat_id in [transfer.from_cat_id, transfer.to_cat_id] {
+            Categories::find_by_id(cat_id)
+                .filter(categories::Column::AccountId.eq(transfer.account_id))
+                .one(&tx).await?
+                .ok_or(Error::CategoryNotFound(cat_id))?;
+        }
+
+        let transfer_id = Uuid::now_v7();
+        let now = self.dt_provider.utc_now();
+        Transactions::insert_many([
+            self.transfer_tx(&transfer, transfer_id, user_id, now, transfer.from_cat_id, -transfer.am),
+            self.transfer_tx(&transfer, transfer_id, user_id, now, transfer.to_cat_id, transfer.am),
+        ]).exec(&tx).await?;
+
+        adjust_category_balances(
+            &tx,
+            HashMap::from([
+                (Some(transfer.from_cat_id), -transfer.am),
+                (Some(transfer.to_cat_id), transfer.am),
+            ]),
+            &LedgerContext { source: LedgerSource::Transfer, user_id: Some(user_id), timestamp: now }
+        ).await?;
+
+        let from_cat = Categories::find_by_id(transfer.from_cat_id).one(&tx).await?
+            .ok_or(Error::CategoryNotFound(transfer.from_cat_id))?;
+        let to_cat = Categories::find_by_id(transfer.to_cat_id).one(&tx).await?
+            .ok_or(Error::CategoryNotFound(transfer.to_cat_id))?;
+        tx.commit().await?;
+
+        Ok(
+            TransferResultModel {
+                transfer_id,
+                from_cat: from_cat.into(),
+                to_cat: to_cat.into(),
+            }
+        )
+    }
+
+    async fn get_ledger(&self, user_id: Uuid, account_id: Uuid, cat_id: Uuid) -> Result<Vec<LedgerEntryModel>> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Read).await?;
+
+        Categories::find_by_id(cat_id)
+            .filter(categories::Column::AccountId.eq(account_id))
+            .one(&self.db).await?
+            .ok_or(Error::CategoryNotFound(cat_id))?;
+
+        CategoryLedger::find()
+            .filter(category_ledger::Column::CategoryId.eq(cat_id))
+            .order_by_asc(category_ledger::Column::Id)
+            .all(&self.db).await?
+            .into_iter()
+            .map(|entry| Ok(LedgerEntryModel {
+                id: entry.id,
+                delta: entry.delta,
+                balance: entry.balance,
+                source: entry.source.parse::<LedgerSource>()?,
+                user_id: entry.user_id,
+                created_on_utc: entry.created_on_utc,
+            }))
+            .collect()
+    }
+
+    async fn recompute_balances(&self, user_id: Uuid, recompute: RecomputeBalancesModel) -> Result<Vec<BalanceMismatchModel>> {
+        validate_user_account_role(&self.db, user_id, recompute.account_id, Role::Admin).await?;
+
+        #[derive(FromQueryResult)]
+        struct CategoryTotalQuery { category_id: Option<Uuid>, total: Option<i64> }
+
+        // The balances are read, compared and repaired in one DB transaction,
+        // so a concurrent balance change is not mistaken for drift
+        let tx = self.db.begin().await?;
+        let now = self.dt_provider.utc_now();
+
+        let cats = Categories::find()
+            .filter(categories::Column::AccountId.eq(recompute.account_id))
+            .order_by_asc(categories::Column::Order)
+            .all(&tx).await?;
+        let opened_cat_ids = CategoryLedger::find()
+            .filter(category_ledger::Column::AccountId.eq(recompute.account_id))
+            .filter(category_ledger::Column::Source.eq(LedgerSource::Opening.to_string()))
+            .all(&tx).await?
+            .into_iter()
+            .map(|entry| entry.category_id)
+            .collect::<HashSet<Uuid>>();
+
+        // Balances changed without a transaction, such as the opening balance, are only known from the ledger
+        let untracked_totals = CategoryLedger::find()
+            .filter(category_ledger::Column::AccountId.eq(recompute.account_id))
+            .filter(category_ledger::Column::Source.is_in(LedgerSource::UNTRACKED.map(|source| source.to_string())))
+            .select_only()
+            .column(category_ledger::Column::CategoryId)
+            .column_as(category_ledger::Column::Delta.sum(), "total")
+            .group_by(category_ledger::Column::CategoryId)
+            .into_model::<CategoryTotalQuery>()
+            .all(&tx).await?;
+
+        // Deleted transactions have already been taken out of their categories' balances
+        let live_txs = Transactions::find()
+            .filter(transactions::Column::AccountId.eq(recompute.account_id))
+            .filter(transactions::Column::DeleteOn.is_null());
+        let tx_totals = live_txs.clone()
+            .select_only()
+            .column(transactions::Column::CategoryId)
+            .column_as(transactions::Column::Amount.sum(), "total")
+            .group_by(transactions::Column::CategoryId)
+            .into_model::<CategoryTotalQuery>()
+            .all(&tx).await?;
+        let split_totals = TransactionSplits::find()
+            .filter(
+                transaction_splits::Column::TransactionId.in_subquery(
+                    live_txs.select_only().column(transactions::Column::Id).into_query()
+                )
+            )
+            .select_only()
+            .column(transaction_splits::Column::CategoryId)
+            .column_as(transaction_splits::Column::Amount.sum(), "total")
+            .group_by(transaction_splits::Column::CategoryId)
+            .into_model::<CategoryTotalQuery>()
+            .all(&tx).await?;
+
+        let mut expected = HashMap::new();
+        for total in untracked_totals.into_iter().chain(tx_totals).chain(split_totals) {
+            if let (Some(cat_id), Some(total)) = (total.category_id, total.total) {
+                *expected.entry(cat_id).or_insert(0i64) += total;
+            }
+        }
+
+        // Backfill the opening balance of categories created before the ledger
+        for cat in cats.iter().filter(|cat| !opened_cat_ids.contains(&cat.id)) {
+            let expected_balance = expected.entry(cat.id).or_insert(0i64);
+            record_ledger_entry(
+                &tx,
+                cat,
+                cat.balance - *expected_balance,
+                &LedgerContext { source: LedgerSource::Opening, user_id: None, timestamp: now }
+            ).await?;
+            *expected_balance = cat.balance;
+        }
+
+        let mismatches = cats.into_iter()
+            .filter_map(|cat| {
+                let expected_balance = expected.get(&cat.id).copied().unwrap_or(0);
+                (expected_balance != cat.balance).then(|| BalanceMismatchModel {
+                    cat_id: cat.id,
+                    name: cat.name,
+                    balance: cat.balance,
+                    expected_balance,
+                    repaired: recompute.repair,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if recompute.repair && !mismatches.is_empty() {
+            adjust_category_balances(
+                &tx,
+                mismatches.iter().map(|mismatch| (Some(mismatch.cat_id), mismatch.expected_balance - mismatch.balance)).collect(),
+                &LedgerContext { source: LedgerSource::Repair, user_id: Some(user_id), timestamp: now }
+            ).await?;
+        }
+        tx.commit().await?;
+
+        Ok(mismatches)
+    }
 }
 
 impl DbConnCategoryService {
-    pub fn new_dyn(db: DbConn) -> DynCategoryService {
-        Arc::new(DbConnCategoryService { db })
+    pub fn new_dyn(db: DbConn, dt_provider: DynDateTimeProvider) -> DynCategoryService {
+        Arc::new(DbConnCategoryService { db, dt_provider })
     }
+
+    fn transfer_tx(
+        &self,
+        transfer: &TransferModel,
+        transfer_id: Uuid,
+        user_id: Uuid,
+        timestamp: DateTimeUtc,
+        cat_id: Uuid,
+        am: i64,
+    ) -> transactions::ActiveModel {
+        transactions::ActiveModel {
+            account_id:     Set(transfer.account_id),
+            user_id:        Set(Some(user_id)),
+            category_id:    Set(Some(cat_id)),
+            timestamp:      Set(timestamp),
+            amount:         Set(am),
+            notes:          Set(Some(transfer.notes.clone().unwrap_or(String::from("Transfer")))),
+            is_refill:      Set(false),
+            transfer_id:    Set(Some(transfer_id)),
+
+            ..Default::default()
+        }
+    }
+
     async fn validate_cat_name(
         &self, 
         account_id: Uuid, 
@@ -214,4 +466,15 @@ impl DbConnCategoryService {
 
         Ok(())
     }    
-}
\ No newline at end of file
+}
+
+impl From<categories::Model> for GetCategoryModel {
+    fn from(value: categories::Model) -> Self {
+        GetCategoryModel {
+            id: value.id,
+            name: value.name,
+            balance: value.balance,
+            refill_val: value.refill_value,
+        }
+    }
+}
@@ -1,6 +1,8 @@
-use sea_orm::prelude::Uuid;
+use sea_orm::prelude::{DateTimeUtc, Uuid};
 use serde::{Deserialize, Serialize};
 
+use crate::db_utils::LedgerSource;
+
 #[derive(Debug, Serialize)]
 pub struct GetCategoryModel {
     pub id: Uuid,
@@ -32,8 +34,65 @@ pub struct DeleteCategoryModel {
     pub cat_id: Uuid,
 }
 
+///
+/// Moves `am` from one category's balance to another's, within an account
+/// 
+#[derive(Deserialize)]
+pub struct TransferModel {
+    pub account_id: Uuid,
+    pub from_cat_id: Uuid,
+    pub to_cat_id: Uuid,
+    pub am: i64,
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransferResultModel {
+    ///
+    /// Links the debit and credit transactions recorded for the transfer
+    /// 
+    pub transfer_id: Uuid,
+    pub from_cat: GetCategoryModel,
+    pub to_cat: GetCategoryModel,
+}
+
 #[derive(Deserialize)]
 pub struct OrderCategoriesModel {
     pub account_id: Uuid,
     pub orders: Vec<(Uuid, i32)>,
-}
\ No newline at end of file
+}
+#[derive(Debug, Serialize)]
+pub struct LedgerEntryModel {
+    pub id: i32,
+    pub delta: i64,
+    ///
+    /// The category's balance after the change
+    /// 
+    pub balance: i64,
+    pub source: LedgerSource,
+    pub user_id: Option<Uuid>,
+    pub created_on_utc: DateTimeUtc,
+}
+
+///
+/// Recomputes each category's balance in the account from its opening balance, manual
+/// balance changes and transactions. When `repair` is set, mismatched balances are corrected
+/// 
+#[derive(Deserialize)]
+pub struct RecomputeBalancesModel {
+    pub account_id: Uuid,
+    #[serde(default)]
+    pub repair: bool,
+}
+
+///
+/// A category whose stored balance does not match its recomputed balance
+/// 
+#[derive(Debug, Serialize)]
+pub struct BalanceMismatchModel {
+    pub cat_id: Uuid,
+    pub name: String,
+    pub balance: i64,
+    pub expected_balance: i64,
+    pub repaired: bool,
+}
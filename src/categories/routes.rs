@@ -3,7 +3,7 @@ use uuid::Uuid;
 
 use crate::{auth::middleware::AuthUser, categories::Result, state::AppState};
 
-use super::{models::DeleteCategoryModel, CreateCategoryModel, DynCategoryService, GetCategoryModel, UpdateCategoryModel};
+use super::{models::{BalanceMismatchModel, DeleteCategoryModel, LedgerEntryModel, RecomputeBalancesModel, TransferModel, TransferResultModel}, CreateCategoryModel, DynCategoryService, GetCategoryModel, UpdateCategoryModel};
 
 pub fn routes(state: AppState) -> Router {
     Router::new()
@@ -11,6 +11,9 @@ pub fn routes(state: AppState) -> Router {
         .route("/", post(post_category))  
         .route("/", put(update_category))
         .route("/", delete(delete_category))
+        .route("/transfer", post(transfer))
+        .route("/ledger/{account_id}/{cat_id}", get(get_ledger))
+        .route("/recompute", post(recompute_balances))
         .with_state(state)
 }
 
@@ -45,4 +48,28 @@ pub async fn delete_category(
 ) -> Result<()> {
     cat_svc.delete_cat(user.id, body).await?;
     Ok(())
+}
+
+pub async fn transfer(
+    State(cat_svc): State<DynCategoryService>,
+    user: AuthUser,
+    Json(body): Json<TransferModel>,
+) -> Result<Json<TransferResultModel>> {
+    Ok(Json(cat_svc.transfer(user.id, body).await?))
+}
+
+pub async fn get_ledger(
+    State(cat_svc): State<DynCategoryService>,
+    Path((account_id, cat_id)): Path<(Uuid, Uuid)>,
+    user: AuthUser,
+) -> Result<Json<Vec<LedgerEntryModel>>> {
+    Ok(Json(cat_svc.get_ledger(user.id, account_id, cat_id).await?))
+}
+
+pub async fn recompute_balances(
+    State(cat_svc): State<DynCategoryService>,
+    user: AuthUser,
+    Json(body): Json<RecomputeBalancesModel>,
+) -> Result<Json<Vec<BalanceMismatchModel>>> {
+    Ok(Json(cat_svc.recompute_balances(user.id, body).await?))
 }
\ No newline at end of file
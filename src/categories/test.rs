@@ -1,11 +1,14 @@
+use std::sync::Arc;
+
 use chrono::Utc;
 use lazy_static::lazy_static;
-use sea_orm::{sea_query::TableCreateStatement, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, Schema, Set};
+use sea_orm::{prelude::Expr, sea_query::TableCreateStatement, ColumnTrait, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, QueryFilter, Schema, Set};
 use uuid::Uuid;
 
-use schmeconomics_entities::{account_users, accounts, categories, prelude::*, users};
+use schmeconomics_entities::{account_users, accounts, categories, prelude::*, recurring_transactions, transaction_splits, transactions, users};
+use utils_rs::date_time_provider::MockDateTimeProvider;
 
-use crate::{categories::{models::DeleteCategoryModel, CategoryService, CreateCategoryModel, Error, UpdateCategoryModel}, db_utils::Role};
+use crate::{categories::{models::{DeleteCategoryModel, RecomputeBalancesModel, TransferModel}, CategoryService, CreateCategoryModel, Error, UpdateCategoryModel}, db_utils::{LedgerSource, Role}};
 
 use super::DbConnCategoryService;
 
@@ -32,12 +35,20 @@ async fn create_test_db(create_cats: bool) -> anyhow::Result<DbConn> {
     let account_user_stmt: TableCreateStatement = schema.create_table_from_entity(AccountUsers);
     let category_stmt: TableCreateStatement = schema.create_table_from_entity(Categories);
     let tx_stmt: TableCreateStatement = schema.create_table_from_entity(Transactions);
+    let split_stmt: TableCreateStatement = schema.create_table_from_entity(TransactionSplits);
+    let payee_stmt: TableCreateStatement = schema.create_table_from_entity(Payees);
+    let ledger_stmt: TableCreateStatement = schema.create_table_from_entity(CategoryLedger);
+    let recurring_stmt: TableCreateStatement = schema.create_table_from_entity(RecurringTransactions);
 
     db.execute(db.get_database_backend().build(&user_stmt)).await?;
     db.execute(db.get_database_backend().build(&account_stmt)).await?;
     db.execute(db.get_database_backend().build(&account_user_stmt)).await?;
     db.execute(db.get_database_backend().build(&category_stmt)).await?;
     db.execute(db.get_database_backend().build(&tx_stmt)).await?;
+    db.execute(db.get_database_backend().build(&split_stmt)).await?;
+    db.execute(db.get_database_backend().build(&payee_stmt)).await?;
+    db.execute(db.get_database_backend().build(&ledger_stmt)).await?;
+    db.execute(db.get_database_backend().build(&recurring_stmt)).await?;
 
     // Insert 1st test user
     let new_user = users::ActiveModel {
@@ -119,9 +130,14 @@ async fn create_test_db(create_cats: bool) -> anyhow::Result<DbConn> {
 async fn create_test_service(create_cats: bool) -> anyhow::Result<(DbConnCategoryService, DbConn)> {
     let db = create_test_db(create_cats).await?;
 
+    // DateTimeProvider
+    let mut mock_dt_service = MockDateTimeProvider::new();
+    mock_dt_service.expect_utc_now().returning(Utc::now);
+
     // Service
     let svc = DbConnCategoryService {
         db: db.clone(),
+        dt_provider: Arc::new(mock_dt_service),
     };
 
     Ok((svc, db))
@@ -352,6 +368,56 @@ async fn test_delete_cat_success() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_delete_cat_references() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service(true).await?;
+
+    let recurring_id = Uuid::now_v7();
+    RecurringTransactions::insert(recurring_transactions::ActiveModel {
+        id: Set(recurring_id),
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        user_id: Set(Some(*TEST_USER_1_ID)),
+        category_id: Set(Some(*TEST_CAT_1_ID)),
+        amount: Set(-1500),
+        currency_type: Set(String::from("USD")),
+        notes: Set(Some(String::from("Rent"))),
+        rrule: Set(String::from("FREQ=MONTHLY")),
+        start_utc: Set(Utc::now()),
+        end_utc: Set(None),
+        occurrence_idx: Set(0),
+        next_occurrence_utc: Set(Some(Utc::now())),
+        paused: Set(false),
+    }).exec(&db).await?;
+
+    let tx_id = Transactions::insert(transactions::ActiveModel {
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        timestamp: Set(Utc::now()),
+        amount: Set(-500),
+        is_refill: Set(false),
+
+        ..Default::default()
+    }).exec(&db).await?.last_insert_id;
+    TransactionSplits::insert(transaction_splits::ActiveModel {
+        transaction_id: Set(tx_id),
+        category_id: Set(*TEST_CAT_2_ID),
+        amount: Set(-500),
+
+        ..Default::default()
+    }).exec(&db).await?;
+
+    // Recurring transactions are left uncategorized
+    svc.delete_cat(*TEST_USER_1_ID, DeleteCategoryModel { account_id: *TEST_ACCOUNT_1_ID, cat_id: *TEST_CAT_1_ID }).await?;
+    let recurring = RecurringTransactions::find_by_id(recurring_id).one(&db).await?.unwrap();
+    assert_eq!(None, recurring.category_id);
+
+    // A category used by splits is kept
+    let res = svc.delete_cat(*TEST_USER_1_ID, DeleteCategoryModel { account_id: *TEST_ACCOUNT_1_ID, cat_id: *TEST_CAT_2_ID }).await;
+    assert!(matches!(res, Err(Error::CategoryHasSplits(id, 1)) if id == *TEST_CAT_2_ID));
+    assert_eq!(1, Categories::find().all(&db).await?.len());
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_delete_cat_does_not_exist_err() -> anyhow::Result<()> {
     let (svc, _db) = create_test_service(true).await?;
@@ -367,4 +433,155 @@ async fn test_delete_cat_does_not_exist_err() -> anyhow::Result<()> {
     assert!(matches!(res, Err(Error::CategoryNotFound(id)) if id == test_id));
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_transfer() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service(true).await?;
+
+    let res = svc.transfer(
+        *TEST_USER_1_ID,
+        TransferModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            from_cat_id: *TEST_CAT_2_ID,
+            to_cat_id: *TEST_CAT_1_ID,
+            am: 4000,
+            notes: None,
+        }
+    ).await?;
+
+    assert_eq!(*TEST_CAT_2_ORIG_BAL - 4000, res.from_cat.balance);
+    assert_eq!(*TEST_CAT_1_ORIG_BAL + 4000, res.to_cat.balance);
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL + 4000, cats[0].balance);
+    assert_eq!(*TEST_CAT_2_ORIG_BAL - 4000, cats[1].balance);
+
+    // The transfer is recorded as a linked debit and credit
+    let txs = Transactions::find().all(&db).await?;
+    assert_eq!(2, txs.len());
+    assert_eq!((Some(*TEST_CAT_2_ID), -4000), (txs[0].category_id, txs[0].amount));
+    assert_eq!((Some(*TEST_CAT_1_ID), 4000), (txs[1].category_id, txs[1].amount));
+    assert!(txs.iter().all(|tx| tx.transfer_id == Some(res.transfer_id)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_transfer_across_accounts_err() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service(true).await?;
+
+    let other_cat_id = Uuid::now_v7();
+    let other_cat = categories::ActiveModel {
+        id: Set(other_cat_id),
+        account_id: Set(*TEST_ACCOUNT_2_ID),
+        name: Set(String::from("OtherCat")),
+        balance: Set(0),
+        refill_value: Set(0),
+        order: Set(1),
+    };
+    Categories::insert(other_cat).exec(&db).await?;
+
+    let res = svc.transfer(
+        *TEST_USER_1_ID,
+        TransferModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            from_cat_id: *TEST_CAT_1_ID,
+            to_cat_id: other_cat_id,
+            am: 500,
+            notes: None,
+        }
+    ).await;
+    assert!(matches!(res, Err(Error::CategoryNotFound(id)) if id == other_cat_id));
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL, cats[0].balance);
+    assert_eq!(0, Transactions::find().all(&db).await?.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_recompute_balances() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service(false).await?;
+
+    let cat1 = svc.create_cat(
+        *TEST_USER_1_ID,
+        CreateCategoryModel { account_id: *TEST_ACCOUNT_1_ID, name: String::from("Cat1"), refill_val: 0, init_bal: 1000 }
+    ).await?;
+    let cat2 = svc.create_cat(
+        *TEST_USER_1_ID,
+        CreateCategoryModel { account_id: *TEST_ACCOUNT_1_ID, name: String::from("Cat2"), refill_val: 0, init_bal: 0 }
+    ).await?;
+    svc.update_cat(
+        *TEST_USER_1_ID,
+        UpdateCategoryModel { account_id: *TEST_ACCOUNT_1_ID, id: cat2.id, new_bal: Some(500), new_name: None, new_refill_val: None }
+    ).await?;
+    svc.transfer(
+        *TEST_USER_1_ID,
+        TransferModel { account_id: *TEST_ACCOUNT_1_ID, from_cat_id: cat1.id, to_cat_id: cat2.id, am: 300, notes: None }
+    ).await?;
+
+    // Every balance change is in the ledger, with the resulting balance
+    let ledger = svc.get_ledger(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, cat2.id).await?;
+    assert_eq!(
+        vec![(LedgerSource::Opening, 0, 0), (LedgerSource::Manual, 500, 500), (LedgerSource::Transfer, 300, 800)],
+        ledger.iter().map(|entry| (entry.source, entry.delta, entry.balance)).collect::<Vec<_>>()
+    );
+
+    let recompute = |repair| RecomputeBalancesModel { account_id: *TEST_ACCOUNT_1_ID, repair };
+    assert!(svc.recompute_balances(*TEST_USER_1_ID, recompute(false)).await?.is_empty());
+
+    // Change a balance without going through the service, so it drifts from its history
+    Categories::update_many()
+        .col_expr(categories::Column::Balance, Expr::value(10000))
+        .filter(categories::Column::Id.eq(cat1.id))
+        .exec(&db).await?;
+
+    let mismatches = svc.recompute_balances(*TEST_USER_1_ID, recompute(false)).await?;
+    assert_eq!(1, mismatches.len());
+    assert_eq!((cat1.id, 10000, 700, false), (mismatches[0].cat_id, mismatches[0].balance, mismatches[0].expected_balance, mismatches[0].repaired));
+    assert_eq!(10000, Categories::find_by_id(cat1.id).one(&db).await?.unwrap().balance);
+
+    let mismatches = svc.recompute_balances(*TEST_USER_1_ID, recompute(true)).await?;
+    assert!(mismatches[0].repaired);
+    assert_eq!(700, Categories::find_by_id(cat1.id).one(&db).await?.unwrap().balance);
+
+    let repair = svc.get_ledger(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, cat1.id).await?.pop().unwrap();
+    assert_eq!((LedgerSource::Repair, -9300, 700), (repair.source, repair.delta, repair.balance));
+    assert!(svc.recompute_balances(*TEST_USER_1_ID, recompute(false)).await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_recompute_balances_backfills_opening() -> anyhow::Result<()> {
+    // The test categories are inserted directly, as if created before the ledger
+    let (svc, db) = create_test_service(true).await?;
+    svc.transfer(
+        *TEST_USER_1_ID,
+        TransferModel { account_id: *TEST_ACCOUNT_1_ID, from_cat_id: *TEST_CAT_2_ID, to_cat_id: *TEST_CAT_1_ID, am: 300, notes: None }
+    ).await?;
+
+    // The first recompute records their opening balances instead of reporting a mismatch
+    let recompute = |repair| RecomputeBalancesModel { account_id: *TEST_ACCOUNT_1_ID, repair };
+    assert!(svc.recompute_balances(*TEST_USER_1_ID, recompute(false)).await?.is_empty());
+
+    let ledger = svc.get_ledger(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, *TEST_CAT_2_ID).await?;
+    assert_eq!(
+        vec![(LedgerSource::Transfer, -300, *TEST_CAT_2_ORIG_BAL - 300), (LedgerSource::Opening, *TEST_CAT_2_ORIG_BAL, *TEST_CAT_2_ORIG_BAL - 300)],
+        ledger.iter().map(|entry| (entry.source, entry.delta, entry.balance)).collect::<Vec<_>>()
+    );
+
+    // Later drift is still found, and the opening balance is only recorded once
+    Categories::update_many()
+        .col_expr(categories::Column::Balance, Expr::value(0))
+        .filter(categories::Column::Id.eq(*TEST_CAT_1_ID))
+        .exec(&db).await?;
+    let mismatches = svc.recompute_balances(*TEST_USER_1_ID, recompute(false)).await?;
+    assert_eq!(1, mismatches.len());
+    assert_eq!((*TEST_CAT_1_ID, 0, *TEST_CAT_1_ORIG_BAL + 300), (mismatches[0].cat_id, mismatches[0].balance, mismatches[0].expected_balance));
+    assert_eq!(2, svc.get_ledger(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, *TEST_CAT_1_ID).await?.len());
+
+    Ok(())
+}
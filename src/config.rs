@@ -1,10 +1,14 @@
 use serde::Deserialize;
 use tokens_rs::token_service::config::TokenServiceConfig;
 
-use crate::validations;
+use crate::{attachments, recurring, refills, transactions, validations};
 
 #[derive(Deserialize)]
 pub struct Config {
     pub token_svc_config: TokenServiceConfig,
     pub validation_svc_config: validations::Config,
+    pub refill_svc_config: refills::Config,
+    pub recurring_svc_config: recurring::Config,
+    pub tx_svc_config: transactions::Config,
+    pub attachment_svc_config: attachments::Config,
 }
\ No newline at end of file
@@ -1,18 +1,20 @@
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use chrono::NaiveDate;
 
 #[cfg(test)]
 use mockall::automock;
 
 use error::*;
 use models::ResponseModel;
-use reqwest::Client;
+use reqwest::{Client, StatusCode};
 
 pub mod error;
 pub mod models;
 
 const PAIKAMA_BASE_URL: &'static str = "https://hexarate.paikama.co/api/rates/latest/";
+const PAIKAMA_HISTORICAL_URL: &'static str = "https://hexarate.paikama.co/api/rates";
 pub const USD_CURRENCY_TYPE: &'static str = "USD";
 
 pub type DynCurrencyConversionProvider = Arc<dyn CurrencyConversionProvider + Send + Sync>;
@@ -23,9 +25,31 @@ pub trait CurrencyConversionProvider {
     async fn convert(&self, from_type: &str, to_type: &str, am: i64) -> Result<i64> {
         if from_type == to_type { return Ok(am); }
         let conv = self.get_conversion(&from_type, &to_type).await?;
-        Ok(f64::floor(conv * am as f64) as i64)
+        Ok(apply_rate(am, conv))
+    }
+    ///
+    /// Converts the amount, also returning the exchange rate used
+    ///
+    async fn convert_with_rate(&self, from_type: &str, to_type: &str, am: i64) -> Result<(i64, f64)> {
+        if from_type == to_type { return Ok((am, 1.0)); }
+        let conv = self.get_conversion(&from_type, &to_type).await?;
+        Ok((apply_rate(am, conv), conv))
     }
     async fn get_conversion(&self, from_currency_type: &str, to_currency_type: &str) -> Result<f64>;
+    ///
+    /// Fetches the exchange rate on a past date.
+    /// Returns `None` when the provider has no rate for the date
+    ///
+    async fn get_historical_conversion(&self, _from_currency_type: &str, _to_currency_type: &str, _date: NaiveDate) -> Result<Option<f64>> {
+        Ok(None)
+    }
+}
+
+///
+/// Converts an amount at the given rate, rounding down to the nearest minor unit
+///
+pub fn apply_rate(am: i64, rate: f64) -> i64 {
+    f64::floor(rate * am as f64) as i64
 }
 
 pub struct PaikamaCurrencyConversionProvider {
@@ -52,4 +76,22 @@ impl CurrencyConversionProvider for PaikamaCurrencyConversionProvider {
         let body = serde_json::from_str::<ResponseModel>(&res.text().await?)?;
         Ok(body.data.mid)
     }
+
+    async fn get_historical_conversion(&self, from_currency_type: &str, to_currency_type: &str, date: NaiveDate) -> Result<Option<f64>> {
+        if from_currency_type == to_currency_type { return Ok(Some(1.0)); }
+        let res = self.client.get(
+            format!("{}/{}/{}/{}", PAIKAMA_HISTORICAL_URL, from_currency_type, to_currency_type, date.format("%Y-%m-%d"))
+        ).send().await?;
+
+        // No rate was published for the date
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !res.status().is_success() {
+            return Err(Error::StatusCodeFetchError(res.status(), res.text().await.unwrap_or(String::new())));
+        }
+
+        let body = serde_json::from_str::<ResponseModel>(&res.text().await?)?;
+        Ok(Some(body.data.mid))
+    }
 }
\ No newline at end of file
@@ -1,7 +1,7 @@
-use std::str::FromStr;
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
-use schmeconomics_entities::prelude::AccountUsers;
-use sea_orm::{ConnectionTrait, EntityTrait};
+use schmeconomics_entities::{categories, category_ledger, prelude::{AccountUsers, Categories, CategoryLedger}};
+use sea_orm::{prelude::{DateTimeUtc, Expr}, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, Set};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -57,6 +57,131 @@ pub async fn validate_user_account_role(
     Err(DbUtilsError::UserNotPartOfAccount(user_id, account_id))
 }
 
+///
+/// What caused a category balance to change, recorded with each ledger entry
+/// 
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum LedgerSource {
+    ///
+    /// The balance the category was created with
+    /// 
+    Opening,
+    ///
+    /// A balance set directly on the category
+    /// 
+    Manual,
+    Transaction,
+    Update,
+    Delete,
+    Restore,
+    Transfer,
+    Refill,
+    Recurring,
+    Rule,
+    Reconciliation,
+    ///
+    /// A correction made after recomputing the balance from its transactions
+    /// 
+    Repair,
+}
+
+impl LedgerSource {
+    ///
+    /// Sources which change a balance without a transaction behind them,
+    /// so they count towards the balance a category is recomputed from
+    /// 
+    pub const UNTRACKED: [LedgerSource; 2] = [LedgerSource::Opening, LedgerSource::Manual];
+}
+
+impl FromStr for LedgerSource {
+    type Err = DbUtilsError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Opening" => Ok(Self::Opening),
+            "Manual" => Ok(Self::Manual),
+            "Transaction" => Ok(Self::Transaction),
+            "Update" => Ok(Self::Update),
+            "Delete" => Ok(Self::Delete),
+            "Restore" => Ok(Self::Restore),
+            "Transfer" => Ok(Self::Transfer),
+            "Refill" => Ok(Self::Refill),
+            "Recurring" => Ok(Self::Recurring),
+            "Rule" => Ok(Self::Rule),
+            "Reconciliation" => Ok(Self::Reconciliation),
+            "Repair" => Ok(Self::Repair),
+            _ => Err(DbUtilsError::CouldNotParseLedgerSource(s.to_string())),
+        }
+    }
+}
+
+impl Display for LedgerSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+///
+/// Who changed a set of category balances, when, and why
+/// 
+pub struct LedgerContext {
+    pub source: LedgerSource,
+    pub user_id: Option<Uuid>,
+    pub timestamp: DateTimeUtc,
+}
+
+///
+/// Adds each total in `totals` to the balance of its category, recording each change in the ledger.
+/// Totals keyed by `None` belong to uncategorized transactions and are skipped.
+/// 
+pub async fn adjust_category_balances(
+    tx: &impl ConnectionTrait,
+    totals: HashMap<Option<Uuid>, i64>,
+    ctx: &LedgerContext,
+) -> Result<(), DbUtilsError> {
+    for (cat_id, total) in totals {
+        if let Some(cat_id) = cat_id {
+            if total == 0 {
+                continue;
+            }
+            Categories::update_many()
+                .filter(categories::Column::Id.eq(cat_id))
+                .col_expr(
+                    categories::Column::Balance, 
+                    Expr::col(categories::Column::Balance).add(total)
+                )
+                .exec(tx).await?;
+
+            if let Some(cat) = Categories::find_by_id(cat_id).one(tx).await? {
+                record_ledger_entry(tx, &cat, total, ctx).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+///
+/// Records a change of `delta` to the category's balance, where `cat` holds the resulting balance
+/// 
+pub async fn record_ledger_entry(
+    tx: &impl ConnectionTrait,
+    cat: &categories::Model,
+    delta: i64,
+    ctx: &LedgerContext,
+) -> Result<(), DbUtilsError> {
+    CategoryLedger::insert(category_ledger::ActiveModel {
+        account_id:     Set(cat.account_id),
+        category_id:    Set(cat.id),
+        delta:          Set(delta),
+        balance:        Set(cat.balance),
+        source:         Set(ctx.source.to_string()),
+        user_id:        Set(ctx.user_id),
+        created_on_utc: Set(ctx.timestamp),
+
+        ..Default::default()
+    }).exec(tx).await?;
+    Ok(())
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum DbUtilsError {
     #[error("Database error occurred: {0}")]
@@ -65,6 +190,8 @@ pub enum DbUtilsError {
     UserNotPartOfAccount(Uuid, Uuid),
     #[error("Could not parse Role from string {0}")]
     CouldNotParseRole(String),
+    #[error("Could not parse LedgerSource from string {0}")]
+    CouldNotParseLedgerSource(String),
     #[error("Could not parse ValidationType from string {0}")]
     CouldNotParseValidationType(String),
 }
\ No newline at end of file
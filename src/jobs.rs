@@ -0,0 +1,26 @@
+use std::{fmt::Display, future::Future, time::Duration};
+
+use log::{error, info};
+
+///
+/// Spawns a background task which invokes `job` once every `period`.
+/// Errors returned by the job are logged, and the job is retried on the next tick.
+///
+pub fn spawn_interval_job<F, Fut, E>(name: &'static str, period: Duration, job: F)
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<u64, E>> + Send,
+    E: Display,
+{
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            match job().await {
+                Ok(0) => { },
+                Ok(count) => info!("Job '{}' processed {} item(s)", name, count),
+                Err(e) => error!("Job '{}' failed: {}", name, e),
+            }
+        }
+    });
+}
@@ -1,11 +1,20 @@
 pub mod accounts;
+pub mod attachments;
 pub mod auth;
 pub mod categories;
 pub mod currency_conv_provider;
+pub mod payees;
+pub mod reconciliations;
+pub mod recurring;
+pub mod refills;
+pub mod reports;
+pub mod rules;
+pub mod tags;
 pub mod transactions;
 pub mod users;
 pub mod validations;
 pub mod config;
 pub mod db_utils;
+pub mod jobs;
 pub mod response;
 pub mod state;
@@ -0,0 +1,42 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use log::error;
+use sea_orm::DbErr;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{db_utils::DbUtilsError, response::internal_server_error_response};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("An error occurred while connecting to the database: {0}")]
+    DbErr(#[from] DbErr),
+    #[error(transparent)]
+    DbUtilsError(#[from] DbUtilsError),
+    #[error("Payee name '{0}' already taken in account")]
+    NameReuse(String),
+    #[error("Payee name cannot be empty")]
+    EmptyName,
+    #[error("Payee with ID '{0}' not found")]
+    PayeeNotFound(Uuid),
+    #[error("Category with ID '{0}' not found in account")]
+    CategoryNotFound(Uuid),
+    #[error("Cannot merge payee '{0}' into itself")]
+    MergeIntoSelf(Uuid),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> axum::response::Response {
+        return match self {
+            Error::DbErr(_) | Error::DbUtilsError(_) => { 
+                error!("{}", self);
+                internal_server_error_response()
+            },
+            Error::NameReuse(_) | Error::EmptyName | Error::PayeeNotFound(_) |
+            Error::CategoryNotFound(_) | Error::MergeIntoSelf(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+        };
+    }
+}
@@ -0,0 +1,199 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use schmeconomics_entities::{categories, payees, prelude::*, transactions};
+use sea_orm::{prelude::{Expr, Uuid}, sea_query::{ExprTrait, Func}, ActiveValue::NotSet, ColumnTrait, Condition, ConnectionTrait, DbConn, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, Set, TransactionTrait};
+
+use crate::db_utils::{validate_user_account_role, Role};
+
+use {error::*, models::*};
+
+pub mod error;
+pub mod models;
+pub mod routes;
+
+#[cfg(test)]
+mod test;
+
+pub type DynPayeeService = Arc<dyn PayeeService + Send + Sync>;
+
+///
+/// Merchants and other parties an account's transactions are with
+/// 
+#[async_trait]
+pub trait PayeeService {
+    async fn get_payees(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<PayeeModel>>;
+    async fn create_payee(&self, user_id: Uuid, payee: CreatePayeeModel) -> Result<PayeeModel>;
+    async fn update_payee(&self, user_id: Uuid, payee: UpdatePayeeModel) -> Result<PayeeModel>;
+    ///
+    /// Deletes the payee. Its transactions are kept, without a payee
+    /// 
+    async fn delete_payee(&self, user_id: Uuid, payee: DeletePayeeModel) -> Result<()>;
+    ///
+    /// Merges duplicate payees into one, returning the remaining payee
+    /// 
+    async fn merge_payees(&self, user_id: Uuid, merge: MergePayeesModel) -> Result<PayeeModel>;
+}
+
+pub struct DbConnPayeeService {
+    db: DbConn,
+}
+
+#[async_trait]
+impl PayeeService for DbConnPayeeService {
+    async fn get_payees(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<PayeeModel>> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Read).await?;
+
+        Ok(
+            Payees::find().filter(payees::Column::AccountId.eq(account_id))
+                .order_by_asc(payees::Column::Name)
+                .all(&self.db).await?
+                .into_iter()
+                .map(PayeeModel::from)
+                .collect()
+        )
+    }
+
+    async fn create_payee(&self, user_id: Uuid, create_payee: CreatePayeeModel) -> Result<PayeeModel> {
+        validate_user_account_role(&self.db, user_id, create_payee.account_id, Role::Write).await?;
+
+        let tx = self.db.begin().await?;
+        let fmt_name = self.validate_payee_name(create_payee.account_id, &create_payee.name, None, &tx).await?;
+        if let Some(cat_id) = create_payee.default_cat_id {
+            self.validate_category(create_payee.account_id, cat_id, &tx).await?;
+        }
+
+        let new_payee = payees::ActiveModel {
+            id:                  Set(Uuid::now_v7()),
+            account_id:          Set(create_payee.account_id),
+            name:                Set(fmt_name),
+            default_category_id: Set(create_payee.default_cat_id),
+        };
+        let payee = Payees::insert(new_payee).exec_with_returning(&tx).await?;
+        tx.commit().await?;
+
+        Ok(payee.into())
+    }
+
+    async fn update_payee(&self, user_id: Uuid, update_payee: UpdatePayeeModel) -> Result<PayeeModel> {
+        validate_user_account_role(&self.db, user_id, update_payee.account_id, Role::Write).await?;
+
+        let tx = self.db.begin().await?;
+        let ex_payee = self.find_payee(update_payee.account_id, update_payee.id, &tx).await?;
+
+        let fmt_name = match update_payee.new_name {
+            Some(name) => Some(self.validate_payee_name(update_payee.account_id, &name, Some(ex_payee.id), &tx).await?),
+            None => None,
+        };
+        if let Some(cat_id) = update_payee.new_default_cat_id {
+            self.validate_category(update_payee.account_id, cat_id, &tx).await?;
+        }
+
+        let mut ex_payee = ex_payee.into_active_model();
+        ex_payee.name = if let Some(fmt_name) = fmt_name { Set(fmt_name) } else { NotSet };
+        ex_payee.default_category_id = if update_payee.clear_default_cat {
+            Set(None)
+        } else if let Some(cat_id) = update_payee.new_default_cat_id {
+            Set(Some(cat_id))
+        } else {
+            NotSet
+        };
+        let updated = Payees::update(ex_payee).exec(&tx).await?;
+        tx.commit().await?;
+
+        Ok(updated.into())
+    }
+
+    async fn delete_payee(&self, user_id: Uuid, delete_payee: DeletePayeeModel) -> Result<()> {
+        validate_user_account_role(&self.db, user_id, delete_payee.account_id, Role::Write).await?;
+
+        let tx = self.db.begin().await?;
+        let payee = self.find_payee(delete_payee.account_id, delete_payee.payee_id, &tx).await?;
+
+        Transactions::update_many()
+            .filter(transactions::Column::PayeeId.eq(payee.id))
+            .col_expr(transactions::Column::PayeeId, Expr::value(Option::<Uuid>::None))
+            .exec(&tx).await?;
+        Payees::delete(payee.into_active_model()).exec(&tx).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn merge_payees(&self, user_id: Uuid, merge: MergePayeesModel) -> Result<PayeeModel> {
+        validate_user_account_role(&self.db, user_id, merge.account_id, Role::Write).await?;
+
+        if merge.from_ids.contains(&merge.into_id) {
+            return Err(Error::MergeIntoSelf(merge.into_id));
+        }
+
+        let tx = self.db.begin().await?;
+        let into_payee = self.find_payee(merge.account_id, merge.into_id, &tx).await?;
+        for from_id in &merge.from_ids {
+            self.find_payee(merge.account_id, *from_id, &tx).await?;
+        }
+
+        if !merge.from_ids.is_empty() {
+            Transactions::update_many()
+                .filter(transactions::Column::AccountId.eq(merge.account_id))
+                .filter(transactions::Column::PayeeId.is_in(merge.from_ids.clone()))
+                .col_expr(transactions::Column::PayeeId, Expr::value(Some(into_payee.id)))
+                .exec(&tx).await?;
+            Payees::delete_many()
+                .filter(payees::Column::Id.is_in(merge.from_ids))
+                .exec(&tx).await?;
+        }
+        tx.commit().await?;
+
+        Ok(into_payee.into())
+    }
+}
+
+impl DbConnPayeeService {
+    pub fn new_dyn(db: DbConn) -> DynPayeeService {
+        Arc::new(DbConnPayeeService { db })
+    }
+
+    async fn find_payee(&self, account_id: Uuid, id: Uuid, tx: &impl ConnectionTrait) -> Result<payees::Model> {
+        Payees::find_by_id(id)
+            .filter(payees::Column::AccountId.eq(account_id))
+            .one(tx).await?
+            .ok_or(Error::PayeeNotFound(id))
+    }
+
+    async fn validate_category(&self, account_id: Uuid, cat_id: Uuid, tx: &impl ConnectionTrait) -> Result<()> {
+        Categories::find_by_id(cat_id)
+            .filter(categories::Column::AccountId.eq(account_id))
+            .one(tx).await?
+            .ok_or(Error::CategoryNotFound(cat_id))?;
+        Ok(())
+    }
+
+    ///
+    /// Trims the payee name, ensuring it is not empty or already used in the account
+    /// by a payee other than `payee_id`, so a payee's name can be recased
+    /// 
+    async fn validate_payee_name(
+        &self,
+        account_id: Uuid,
+        payee_name: &str,
+        payee_id: Option<Uuid>,
+        tx: &impl ConnectionTrait,
+    ) -> Result<String> {
+        let fmt_name = payee_name.trim().to_string();
+        if fmt_name.is_empty() {
+            return Err(Error::EmptyName);
+        }
+
+        let existing_payee = Payees::find().filter(
+            Condition::all()
+                .add(payees::Column::AccountId.eq(account_id))
+                .add(Func::lower(Expr::col(payees::Column::Name)).eq(fmt_name.to_lowercase()))
+            ).one(tx).await?;
+        if existing_payee.is_some_and(|payee| Some(payee.id) != payee_id) {
+            return Err(Error::NameReuse(fmt_name));
+        }
+
+        Ok(fmt_name)
+    }
+}
@@ -0,0 +1,57 @@
+use sea_orm::prelude::Uuid;
+use serde::{Deserialize, Serialize};
+
+use schmeconomics_entities::payees;
+
+#[derive(Debug, Serialize)]
+pub struct PayeeModel {
+    pub id: Uuid,
+    pub name: String,
+    ///
+    /// Category given to new transactions with this payee, when no category is supplied
+    /// 
+    pub default_cat_id: Option<Uuid>,
+}
+
+impl From<payees::Model> for PayeeModel {
+    fn from(value: payees::Model) -> Self {
+        PayeeModel { id: value.id, name: value.name, default_cat_id: value.default_category_id }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreatePayeeModel {
+    pub account_id: Uuid,
+    pub name: String,
+    pub default_cat_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct UpdatePayeeModel {
+    pub account_id: Uuid,
+    pub id: Uuid,
+    pub new_name: Option<String>,
+    pub new_default_cat_id: Option<Uuid>,
+    ///
+    /// Removes the payee's default category. Takes precedence over `new_default_cat_id`
+    /// 
+    #[serde(default)]
+    pub clear_default_cat: bool,
+}
+
+#[derive(Deserialize)]
+pub struct DeletePayeeModel {
+    pub account_id: Uuid,
+    pub payee_id: Uuid,
+}
+
+///
+/// Moves every transaction of the `from_ids` payees to the `into_id` payee,
+/// then deletes the `from_ids` payees
+/// 
+#[derive(Deserialize)]
+pub struct MergePayeesModel {
+    pub account_id: Uuid,
+    pub into_id: Uuid,
+    pub from_ids: Vec<Uuid>,
+}
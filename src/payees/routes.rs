@@ -0,0 +1,57 @@
+use axum::{extract::{Path, State}, routing::{delete, get, post, put}, Json, Router};
+use uuid::Uuid;
+
+use crate::{auth::middleware::AuthUser, state::AppState};
+
+use super::{error::Result, models::{CreatePayeeModel, DeletePayeeModel, MergePayeesModel, PayeeModel, UpdatePayeeModel}, DynPayeeService};
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route("/{account_id}", get(get_payees))
+        .route("/", post(post_payee))
+        .route("/", put(update_payee))
+        .route("/", delete(delete_payee))
+        .route("/merge", post(merge_payees))
+        .with_state(state)
+}
+
+pub async fn get_payees(
+    State(payee_svc): State<DynPayeeService>,
+    Path(account_id): Path<Uuid>,
+    user: AuthUser,
+) -> Result<Json<Vec<PayeeModel>>> {
+    Ok(Json(payee_svc.get_payees(user.id, account_id).await?))
+}
+
+pub async fn post_payee(
+    State(payee_svc): State<DynPayeeService>,
+    user: AuthUser,
+    Json(body): Json<CreatePayeeModel>,
+) -> Result<Json<PayeeModel>> {
+    Ok(Json(payee_svc.create_payee(user.id, body).await?))
+}
+
+pub async fn update_payee(
+    State(payee_svc): State<DynPayeeService>,
+    user: AuthUser,
+    Json(body): Json<UpdatePayeeModel>,
+) -> Result<Json<PayeeModel>> {
+    Ok(Json(payee_svc.update_payee(user.id, body).await?))
+}
+
+pub async fn delete_payee(
+    State(payee_svc): State<DynPayeeService>,
+    user: AuthUser,
+    Json(body): Json<DeletePayeeModel>,
+) -> Result<()> {
+    payee_svc.delete_payee(user.id, body).await?;
+    Ok(())
+}
+
+pub async fn merge_payees(
+    State(payee_svc): State<DynPayeeService>,
+    user: AuthUser,
+    Json(body): Json<MergePayeesModel>,
+) -> Result<Json<PayeeModel>> {
+    Ok(Json(payee_svc.merge_payees(user.id, body).await?))
+}
@@ -0,0 +1,193 @@
+use chrono::Utc;
+use lazy_static::lazy_static;
+use sea_orm::{sea_query::TableCreateStatement, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, Schema, Set};
+use uuid::Uuid;
+
+use schmeconomics_entities::{account_users, accounts, categories, prelude::*, transactions, users};
+
+use crate::{db_utils::Role, payees::{models::{CreatePayeeModel, DeletePayeeModel, MergePayeesModel, UpdatePayeeModel}, Error, PayeeService}};
+
+use super::DbConnPayeeService;
+
+lazy_static! {
+    static ref TEST_USER_1_ID: Uuid = Uuid::parse_str("be5ca263-2307-4e5a-acbd-3281fb81ea60").unwrap();
+    static ref TEST_ACCOUNT_1_ID: Uuid = Uuid::parse_str("f017369e-9dd1-4434-b197-40361cc0dbcd").unwrap();
+    static ref TEST_ACCOUNT_2_ID: Uuid = Uuid::parse_str("2dd9ffbe-5d15-401f-b637-7f3e2de9bf1f").unwrap();
+    static ref TEST_CAT_1_ID: Uuid = Uuid::parse_str("c8be0f8e-629e-46ce-9e76-e691caa0714b").unwrap();
+    static ref TEST_CAT_2_ID: Uuid = Uuid::parse_str("0fd2a2ce-cce1-43c4-a69d-8b1b523f0127").unwrap();
+}
+
+async fn create_test_db() -> anyhow::Result<DbConn> {
+    // In-memory Sqlite connection
+    let db = Database::connect("sqlite::memory:").await?;
+
+    // Schema and Tables SeaOrm statements
+    let schema = Schema::new(DbBackend::Sqlite);
+    let user_stmt: TableCreateStatement = schema.create_table_from_entity(Users);
+    let account_stmt: TableCreateStatement = schema.create_table_from_entity(Accounts);
+    let account_user_stmt: TableCreateStatement = schema.create_table_from_entity(AccountUsers);
+    let category_stmt: TableCreateStatement = schema.create_table_from_entity(Categories);
+    let tx_stmt: TableCreateStatement = schema.create_table_from_entity(Transactions);
+    let payee_stmt: TableCreateStatement = schema.create_table_from_entity(Payees);
+
+    db.execute(db.get_database_backend().build(&user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&category_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_stmt)).await?;
+    db.execute(db.get_database_backend().build(&payee_stmt)).await?;
+
+    // Insert test user
+    let new_user = users::ActiveModel {
+        id: Set(*TEST_USER_1_ID),
+        email: Set(String::from("user1@mail.com")),
+        email_verified: Set(true),
+        password_hash: Set(String::from("password")),
+        name: Set(String::from("tester 1")),
+        created_on_utc: Set(Utc::now()),
+        two_factor_enabled: Set(false),
+
+        ..Default::default()
+    };
+    Users::insert(new_user).exec(&db).await?;
+
+    // Create test accounts, only the 1st of which the user is part of
+    for account_id in [*TEST_ACCOUNT_1_ID, *TEST_ACCOUNT_2_ID] {
+        let account = accounts::ActiveModel {
+            id: Set(account_id),
+            ..Default::default()
+        };
+        Accounts::insert(account).exec(&db).await?;
+    }
+
+    let account_user = account_users::ActiveModel {
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        user_id: Set(*TEST_USER_1_ID),
+        role: Set(Role::Admin.to_string()),
+        verified: Set(true),
+        created_on: Set(Utc::now()),
+    };
+    AccountUsers::insert(account_user).exec(&db).await?;
+
+    let cat1 = categories::ActiveModel {
+        id: Set(*TEST_CAT_1_ID),
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        name: Set(String::from("Groceries")),
+        balance: Set(0),
+        refill_value: Set(0),
+        order: Set(1),
+    };
+    let cat2 = categories::ActiveModel {
+        id: Set(*TEST_CAT_2_ID),
+        account_id: Set(*TEST_ACCOUNT_2_ID),
+        name: Set(String::from("Other Account")),
+        balance: Set(0),
+        refill_value: Set(0),
+        order: Set(1),
+    };
+    Categories::insert_many(vec![cat1, cat2]).exec(&db).await?;
+
+    Ok(db)
+}
+
+async fn create_test_service() -> anyhow::Result<(DbConnPayeeService, DbConn)> {
+    let db = create_test_db().await?;
+    Ok((DbConnPayeeService { db: db.clone() }, db))
+}
+
+async fn create_payee(svc: &DbConnPayeeService, name: &str) -> anyhow::Result<Uuid> {
+    let payee = svc.create_payee(
+        *TEST_USER_1_ID,
+        CreatePayeeModel { account_id: *TEST_ACCOUNT_1_ID, name: String::from(name), default_cat_id: None }
+    ).await?;
+    Ok(payee.id)
+}
+
+async fn create_tx(db: &DbConn, payee_id: Uuid) -> anyhow::Result<()> {
+    Transactions::insert(
+        transactions::ActiveModel {
+            account_id: Set(*TEST_ACCOUNT_1_ID),
+            timestamp: Set(Utc::now()),
+            amount: Set(-1000),
+            is_refill: Set(false),
+            payee_id: Set(Some(payee_id)),
+
+            ..Default::default()
+        }
+    ).exec(db).await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_and_update_payee() -> anyhow::Result<()> {
+    let (svc, _db) = create_test_service().await?;
+
+    let payee = svc.create_payee(
+        *TEST_USER_1_ID,
+        CreatePayeeModel { account_id: *TEST_ACCOUNT_1_ID, name: String::from(" costco "), default_cat_id: Some(*TEST_CAT_1_ID) }
+    ).await?;
+    assert_eq!("costco", payee.name);
+    assert_eq!(Some(*TEST_CAT_1_ID), payee.default_cat_id);
+
+    let res = svc.create_payee(
+        *TEST_USER_1_ID,
+        CreatePayeeModel { account_id: *TEST_ACCOUNT_1_ID, name: String::from("COSTCO"), default_cat_id: None }
+    ).await;
+    assert!(matches!(res, Err(Error::NameReuse(_))));
+
+    // Default categories must belong to the payee's account
+    let res = svc.create_payee(
+        *TEST_USER_1_ID,
+        CreatePayeeModel { account_id: *TEST_ACCOUNT_1_ID, name: String::from("Safeway"), default_cat_id: Some(*TEST_CAT_2_ID) }
+    ).await;
+    assert!(matches!(res, Err(Error::CategoryNotFound(id)) if id == *TEST_CAT_2_ID));
+
+    // A payee can be recased without conflicting with itself
+    let updated = svc.update_payee(
+        *TEST_USER_1_ID,
+        UpdatePayeeModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            id: payee.id,
+            new_name: Some(String::from("Costco")),
+            new_default_cat_id: None,
+            clear_default_cat: true,
+        }
+    ).await?;
+    assert_eq!("Costco", updated.name);
+    assert_eq!(None, updated.default_cat_id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_merge_and_delete_payees() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let costco_id = create_payee(&svc, "Costco").await?;
+    let costco_123_id = create_payee(&svc, "COSTCO #123").await?;
+    create_tx(&db, costco_id).await?;
+    create_tx(&db, costco_123_id).await?;
+
+    let res = svc.merge_payees(
+        *TEST_USER_1_ID,
+        MergePayeesModel { account_id: *TEST_ACCOUNT_1_ID, into_id: costco_id, from_ids: vec![costco_id] }
+    ).await;
+    assert!(matches!(res, Err(Error::MergeIntoSelf(id)) if id == costco_id));
+
+    let merged = svc.merge_payees(
+        *TEST_USER_1_ID,
+        MergePayeesModel { account_id: *TEST_ACCOUNT_1_ID, into_id: costco_id, from_ids: vec![costco_123_id] }
+    ).await?;
+    assert_eq!(costco_id, merged.id);
+    assert_eq!(1, svc.get_payees(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID).await?.len());
+    assert!(Transactions::find().all(&db).await?.iter().all(|tx| tx.payee_id == Some(costco_id)));
+
+    // Deleting a payee keeps its transactions
+    svc.delete_payee(*TEST_USER_1_ID, DeletePayeeModel { account_id: *TEST_ACCOUNT_1_ID, payee_id: costco_id }).await?;
+    assert!(Payees::find().all(&db).await?.is_empty());
+    let txs = Transactions::find().all(&db).await?;
+    assert_eq!(2, txs.len());
+    assert!(txs.iter().all(|tx| tx.payee_id.is_none()));
+
+    Ok(())
+}
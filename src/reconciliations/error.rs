@@ -0,0 +1,49 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use log::error;
+use sea_orm::{prelude::DateTimeUtc, DbErr};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{db_utils::DbUtilsError, response::internal_server_error_response};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("An error occurred while connecting to the database: {0}")]
+    DbErr(#[from] DbErr),
+    #[error(transparent)]
+    DbUtilsError(#[from] DbUtilsError),
+    #[error("Reconciliation with ID '{0}' not found")]
+    ReconciliationNotFound(Uuid),
+    #[error("Reconciliation '{0}' is already in progress for the account")]
+    ReconciliationInProgress(Uuid),
+    #[error("Reconciliation '{0}' is already completed")]
+    AlreadyCompleted(Uuid),
+    #[error("Statement end must be after the previous statement end, {0}")]
+    StatementEndNotAfterPrevious(DateTimeUtc),
+    #[error("Cleared transactions differ from the statement balance by {0}")]
+    Unbalanced(i64),
+    #[error("Category with ID '{0}' not found in account")]
+    CategoryNotFound(Uuid),
+    #[error("Transaction {0} not found in account")]
+    TransactionNotFound(i32),
+    #[error("Transaction {0} is reconciled, and cannot be uncleared")]
+    ReconciledTransaction(i32),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> axum::response::Response {
+        return match self {
+            Error::DbErr(_) | Error::DbUtilsError(_) => { 
+                error!("{}", self);
+                internal_server_error_response()
+            },
+            Error::ReconciliationNotFound(_) | Error::ReconciliationInProgress(_) | Error::AlreadyCompleted(_) |
+            Error::StatementEndNotAfterPrevious(_) | Error::Unbalanced(_) | Error::CategoryNotFound(_) |
+            Error::TransactionNotFound(_) | Error::ReconciledTransaction(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+        };
+    }
+}
@@ -0,0 +1,329 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use schmeconomics_entities::{categories, prelude::*, reconciliations, transactions};
+use sea_orm::{prelude::{Expr, Uuid}, sea_query::Query, ColumnTrait, Condition, ConnectionTrait, DbConn, EntityTrait, FromQueryResult, IntoActiveModel, QueryFilter, QueryOrder, QuerySelect, Set, TransactionTrait};
+use utils_rs::date_time_provider::DynDateTimeProvider;
+
+use crate::{currency_conv_provider::USD_CURRENCY_TYPE, db_utils::{adjust_category_balances, validate_user_account_role, LedgerContext, LedgerSource, Role}, transactions::with_details};
+
+use {error::*, models::*};
+
+pub mod error;
+pub mod models;
+pub mod routes;
+
+#[cfg(test)]
+mod test;
+
+pub type DynReconciliationService = Arc<dyn ReconciliationService + Send + Sync>;
+
+///
+/// Notes of the transaction created to cover a difference from the statement balance
+/// 
+const ADJUSTMENT_NOTES: &str = "Reconciliation adjustment";
+
+///
+/// Confirms an account's cleared transactions match a bank statement. Transfers and refills
+/// only move money between categories, so they are never part of a reconciliation.
+///
+/// Transactions may still be created or backdated into an already reconciled period. They are
+/// not locked by the completed reconciliation and do not change its totals, but are locked by
+/// the next reconciliation to finish once they are cleared.
+/// 
+#[async_trait]
+pub trait ReconciliationService {
+    async fn get_reconciliations(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<ReconciliationModel>>;
+    async fn get_reconciliation(&self, user_id: Uuid, account_id: Uuid, id: Uuid) -> Result<ReconciliationReportModel>;
+    ///
+    /// Starts reconciling the account against a statement. Only one reconciliation
+    /// may be in progress for an account at a time.
+    /// 
+    async fn start_reconciliation(&self, user_id: Uuid, start_req: StartReconciliationModel) -> Result<ReconciliationReportModel>;
+    async fn cancel_reconciliation(&self, user_id: Uuid, cancel_req: CancelReconciliationModel) -> Result<()>;
+    ///
+    /// Marks transactions as cleared or uncleared. Reconciled transactions cannot be uncleared.
+    /// 
+    async fn set_cleared(&self, user_id: Uuid, cleared_req: SetClearedModel) -> Result<()>;
+    ///
+    /// Completes the reconciliation, locking every cleared transaction dated up to the statement end.
+    /// Fails if the cleared total differs from the statement balance, unless an adjustment is requested.
+    /// 
+    async fn finish_reconciliation(&self, user_id: Uuid, finish_req: FinishReconciliationModel) -> Result<ReconciliationModel>;
+}
+
+pub struct DbConnReconciliationService {
+    db: DbConn,
+    dt_provider: DynDateTimeProvider,
+}
+
+#[async_trait]
+impl ReconciliationService for DbConnReconciliationService {
+    async fn get_reconciliations(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<ReconciliationModel>> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Read).await?;
+
+        let mut models = vec![];
+        for reconciliation in Reconciliations::find()
+            .filter(reconciliations::Column::AccountId.eq(account_id))
+            .order_by_desc(reconciliations::Column::StatementEndUtc)
+            .all(&self.db).await?
+        {
+            let cleared_total = cleared_total(&self.db, &reconciliation).await?;
+            models.push(ReconciliationModel::new(reconciliation, cleared_total));
+        }
+        Ok(models)
+    }
+
+    async fn get_reconciliation(&self, user_id: Uuid, account_id: Uuid, id: Uuid) -> Result<ReconciliationReportModel> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Read).await?;
+
+        let reconciliation = self.find_reconciliation(account_id, id).await?;
+        self.report(reconciliation).await
+    }
+
+    async fn start_reconciliation(&self, user_id: Uuid, start_req: StartReconciliationModel) -> Result<ReconciliationReportModel> {
+        validate_user_account_role(&self.db, user_id, start_req.account_id, Role::Write).await?;
+
+        // The open reconciliation check is made in the same DB transaction as the insert,
+        // so two concurrent starts cannot both open a reconciliation
+        let db_tx = self.db.begin().await?;
+        let ex_reconciliations = Reconciliations::find()
+            .filter(reconciliations::Column::AccountId.eq(start_req.account_id))
+            .all(&db_tx).await?;
+        if let Some(open) = ex_reconciliations.iter().find(|reconciliation| reconciliation.completed_on_utc.is_none()) {
+            return Err(Error::ReconciliationInProgress(open.id));
+        }
+        if let Some(prev_end) = ex_reconciliations.iter().map(|reconciliation| reconciliation.statement_end_utc).max() {
+            if start_req.statement_end_utc <= prev_end {
+                return Err(Error::StatementEndNotAfterPrevious(prev_end));
+            }
+        }
+
+        let new_reconciliation = reconciliations::ActiveModel {
+            id:                Set(Uuid::now_v7()),
+            account_id:        Set(start_req.account_id),
+            statement_end_utc: Set(start_req.statement_end_utc),
+            statement_balance: Set(start_req.statement_balance),
+            adjustment_tx_id:  Set(None),
+            created_on_utc:    Set(self.dt_provider.utc_now()),
+            completed_on_utc:  Set(None),
+        };
+        let reconciliation = Reconciliations::insert(new_reconciliation).exec_with_returning(&db_tx).await?;
+        db_tx.commit().await?;
+
+        self.report(reconciliation).await
+    }
+
+    async fn cancel_reconciliation(&self, user_id: Uuid, cancel_req: CancelReconciliationModel) -> Result<()> {
+        validate_user_account_role(&self.db, user_id, cancel_req.account_id, Role::Write).await?;
+
+        let reconciliation = self.find_reconciliation(cancel_req.account_id, cancel_req.id).await?;
+        if reconciliation.completed_on_utc.is_some() {
+            return Err(Error::AlreadyCompleted(reconciliation.id));
+        }
+        Reconciliations::delete(reconciliation.into_active_model()).exec(&self.db).await?;
+
+        Ok(())
+    }
+
+    async fn set_cleared(&self, user_id: Uuid, cleared_req: SetClearedModel) -> Result<()> {
+        validate_user_account_role(&self.db, user_id, cleared_req.account_id, Role::Write).await?;
+
+        // The transactions are checked in the same DB transaction as they are updated,
+        // so a reconciliation finished meanwhile cannot have its transactions uncleared
+        let db_tx = self.db.begin().await?;
+        let txs = Transactions::find()
+            .filter(reconcilable(cleared_req.account_id))
+            .filter(transactions::Column::Id.is_in(cleared_req.tx_ids.clone()))
+            .all(&db_tx).await?;
+        if let Some(tx_id) = cleared_req.tx_ids.iter().find(|id| !txs.iter().any(|tx| tx.id == **id)) {
+            return Err(Error::TransactionNotFound(*tx_id));
+        }
+        if !cleared_req.is_cleared {
+            if let Some(tx) = txs.iter().find(|tx| tx.reconciliation_id.is_some()) {
+                return Err(Error::ReconciledTransaction(tx.id));
+            }
+        }
+
+        let mut update = Transactions::update_many()
+            .col_expr(transactions::Column::IsCleared, Expr::value(cleared_req.is_cleared))
+            .filter(transactions::Column::Id.is_in(cleared_req.tx_ids));
+        if !cleared_req.is_cleared {
+            update = update.filter(transactions::Column::ReconciliationId.is_null());
+        }
+        let updated = update.exec_with_returning(&db_tx).await?;
+        if let Some(tx) = txs.iter().find(|tx| !updated.iter().any(|updated| updated.id == tx.id)) {
+            return Err(Error::ReconciledTransaction(tx.id));
+        }
+        db_tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn finish_reconciliation(&self, user_id: Uuid, finish_req: FinishReconciliationModel) -> Result<ReconciliationModel> {
+        validate_user_account_role(&self.db, user_id, finish_req.account_id, Role::Write).await?;
+
+        // The difference is computed in the same DB transaction as the transactions are locked,
+        // so a transaction cleared meanwhile cannot be locked without being balanced
+        let db_tx = self.db.begin().await?;
+        let reconciliation = find_reconciliation(&db_tx, finish_req.account_id, finish_req.id).await?;
+        if reconciliation.completed_on_utc.is_some() {
+            return Err(Error::AlreadyCompleted(reconciliation.id));
+        }
+        let statement_end = reconciliation.statement_end_utc;
+        let difference = reconciliation.statement_balance - cleared_total(&db_tx, &reconciliation).await?;
+        if difference != 0 && !finish_req.create_adjustment {
+            return Err(Error::Unbalanced(difference));
+        }
+        if let Some(cat_id) = finish_req.adjustment_cat_id {
+            Categories::find_by_id(cat_id)
+                .filter(categories::Column::AccountId.eq(finish_req.account_id))
+                .one(&db_tx).await?
+                .ok_or(Error::CategoryNotFound(cat_id))?;
+        }
+
+        let now = self.dt_provider.utc_now();
+        let adjustment_tx_id = if difference != 0 {
+            let adjustment = transactions::ActiveModel {
+                account_id:         Set(finish_req.account_id),
+                user_id:            Set(Some(user_id)),
+                category_id:        Set(finish_req.adjustment_cat_id),
+                timestamp:          Set(statement_end),
+                amount:             Set(difference),
+                notes:              Set(Some(String::from(ADJUSTMENT_NOTES))),
+                is_refill:          Set(false),
+                is_cleared:         Set(true),
+                orig_currency_type: Set(Some(USD_CURRENCY_TYPE.to_string())),
+                orig_amount:        Set(Some(difference)),
+                exchange_rate:      Set(Some(1.0)),
+
+                ..Default::default()
+            };
+            let adjustment = Transactions::insert(adjustment).exec_with_returning(&db_tx).await?;
+            adjust_category_balances(
+                &db_tx,
+                HashMap::from([(finish_req.adjustment_cat_id, difference)]),
+                &LedgerContext { source: LedgerSource::Reconciliation, user_id: Some(user_id), timestamp: now }
+            ).await?;
+            Some(adjustment.id)
+        } else {
+            None
+        };
+
+        // Lock every cleared transaction the statement covers
+        Transactions::update_many()
+            .col_expr(transactions::Column::ReconciliationId, Expr::value(reconciliation.id))
+            .filter(reconcilable(finish_req.account_id))
+            .filter(transactions::Column::IsCleared.eq(true))
+            .filter(transactions::Column::ReconciliationId.is_null())
+            .filter(transactions::Column::Timestamp.lte(statement_end))
+            .exec(&db_tx).await?;
+
+        let mut completed = reconciliation.into_active_model();
+        completed.adjustment_tx_id = Set(adjustment_tx_id);
+        completed.completed_on_utc = Set(Some(now));
+        let completed = Reconciliations::update(completed).exec(&db_tx).await?;
+        let cleared_total = cleared_total(&db_tx, &completed).await?;
+        db_tx.commit().await?;
+
+        Ok(ReconciliationModel::new(completed, cleared_total))
+    }
+}
+
+impl DbConnReconciliationService {
+    pub fn new_dyn(db: DbConn, dt_provider: DynDateTimeProvider) -> DynReconciliationService {
+        Arc::new(DbConnReconciliationService { db, dt_provider })
+    }
+
+    async fn find_reconciliation(&self, account_id: Uuid, id: Uuid) -> Result<reconciliations::Model> {
+        find_reconciliation(&self.db, account_id, id).await
+    }
+
+    async fn report(&self, reconciliation: reconciliations::Model) -> Result<ReconciliationReportModel> {
+        let account_id = reconciliation.account_id;
+        let statement_end = reconciliation.statement_end_utc;
+
+        let uncleared = Transactions::find()
+            .filter(reconcilable(account_id))
+            .filter(transactions::Column::IsCleared.eq(false))
+            .filter(transactions::Column::Timestamp.lte(statement_end))
+            .order_by_asc(transactions::Column::Timestamp)
+            .order_by_asc(transactions::Column::Id)
+            .all(&self.db).await?;
+        let cleared_after_end = Transactions::find()
+            .filter(reconcilable(account_id))
+            .filter(transactions::Column::IsCleared.eq(true))
+            .filter(transactions::Column::ReconciliationId.is_null())
+            .filter(transactions::Column::Timestamp.gt(statement_end))
+            .order_by_asc(transactions::Column::Timestamp)
+            .order_by_asc(transactions::Column::Id)
+            .all(&self.db).await?;
+        let cleared_total = cleared_total(&self.db, &reconciliation).await?;
+
+        Ok(
+            ReconciliationReportModel {
+                reconciliation: ReconciliationModel::new(reconciliation, cleared_total),
+                uncleared: with_details(&self.db, uncleared).await?,
+                cleared_after_end: with_details(&self.db, cleared_after_end).await?,
+            }
+        )
+    }
+}
+
+///
+/// Transactions of the account which appear on bank statements
+/// 
+fn reconcilable(account_id: Uuid) -> Condition {
+    Condition::all()
+        .add(transactions::Column::AccountId.eq(account_id))
+        .add(transactions::Column::DeleteOn.is_null())
+        .add(transactions::Column::TransferId.is_null())
+        .add(transactions::Column::IsRefill.eq(false))
+}
+
+async fn find_reconciliation(db: &impl ConnectionTrait, account_id: Uuid, id: Uuid) -> Result<reconciliations::Model> {
+    Reconciliations::find_by_id(id)
+        .filter(reconciliations::Column::AccountId.eq(account_id))
+        .one(db).await?
+        .ok_or(Error::ReconciliationNotFound(id))
+}
+
+///
+/// Sums the account's cleared transactions dated up to the statement end. Once completed,
+/// only the transactions locked by this or an earlier reconciliation are summed, so the
+/// total does not change when transactions are later backdated into the period.
+/// 
+async fn cleared_total(db: &impl ConnectionTrait, reconciliation: &reconciliations::Model) -> Result<i64> {
+    #[derive(FromQueryResult)]
+    struct SumQuery { total: Option<i64> }
+
+    let cond = if reconciliation.completed_on_utc.is_some() {
+        Condition::all()
+            .add(
+                transactions::Column::ReconciliationId.in_subquery(
+                    Query::select()
+                        .column(reconciliations::Column::Id)
+                        .from(Reconciliations)
+                        .and_where(reconciliations::Column::AccountId.eq(reconciliation.account_id))
+                        .and_where(reconciliations::Column::StatementEndUtc.lte(reconciliation.statement_end_utc))
+                        .to_owned()
+                )
+            )
+    } else {
+        Condition::all()
+            .add(transactions::Column::IsCleared.eq(true))
+            .add(transactions::Column::Timestamp.lte(reconciliation.statement_end_utc))
+    };
+
+    Ok(
+        Transactions::find()
+            .filter(reconcilable(reconciliation.account_id))
+            .filter(cond)
+            .select_only()
+            .column_as(transactions::Column::Amount.sum(), "total")
+            .into_model::<SumQuery>()
+            .one(db).await?
+            .and_then(|sum| sum.total)
+            .unwrap_or(0)
+    )
+}
@@ -0,0 +1,94 @@
+use sea_orm::prelude::{DateTimeUtc, Uuid};
+use serde::{Deserialize, Serialize};
+
+use schmeconomics_entities::reconciliations;
+
+use crate::transactions::models::TransactionModel;
+
+#[derive(Debug, Serialize)]
+pub struct ReconciliationModel {
+    pub id: Uuid,
+    pub account_id: Uuid,
+    pub statement_end_utc: DateTimeUtc,
+    pub statement_balance: i64,
+    ///
+    /// Sum of the cleared transactions dated up to the statement end
+    /// 
+    pub cleared_total: i64,
+    ///
+    /// `statement_balance - cleared_total`. Must be 0, or adjusted for, to finish the reconciliation
+    /// 
+    pub difference: i64,
+    pub adjustment_tx_id: Option<i32>,
+    pub created_on_utc: DateTimeUtc,
+    ///
+    /// `None` while the reconciliation is in progress
+    /// 
+    pub completed_on_utc: Option<DateTimeUtc>,
+}
+
+impl ReconciliationModel {
+    pub fn new(value: reconciliations::Model, cleared_total: i64) -> Self {
+        ReconciliationModel {
+            id: value.id,
+            account_id: value.account_id,
+            statement_end_utc: value.statement_end_utc,
+            statement_balance: value.statement_balance,
+            cleared_total,
+            difference: value.statement_balance - cleared_total,
+            adjustment_tx_id: value.adjustment_tx_id,
+            created_on_utc: value.created_on_utc,
+            completed_on_utc: value.completed_on_utc,
+        }
+    }
+}
+
+///
+/// A reconciliation, with the transactions which may explain a difference from the statement
+/// 
+#[derive(Serialize)]
+pub struct ReconciliationReportModel {
+    #[serde(flatten)]
+    pub reconciliation: ReconciliationModel,
+    ///
+    /// Uncleared transactions dated up to the statement end, which may be missing from the statement
+    /// 
+    pub uncleared: Vec<TransactionModel>,
+    ///
+    /// Unreconciled, cleared transactions dated after the statement end, which may be misdated
+    /// 
+    pub cleared_after_end: Vec<TransactionModel>,
+}
+
+#[derive(Deserialize)]
+pub struct StartReconciliationModel {
+    pub account_id: Uuid,
+    pub statement_end_utc: DateTimeUtc,
+    pub statement_balance: i64,
+}
+
+#[derive(Deserialize)]
+pub struct SetClearedModel {
+    pub account_id: Uuid,
+    pub tx_ids: Vec<i32>,
+    pub is_cleared: bool,
+}
+
+#[derive(Deserialize)]
+pub struct FinishReconciliationModel {
+    pub account_id: Uuid,
+    pub id: Uuid,
+    ///
+    /// Creates a cleared transaction for any difference from the statement balance,
+    /// instead of failing to finish
+    /// 
+    #[serde(default)]
+    pub create_adjustment: bool,
+    pub adjustment_cat_id: Option<Uuid>,
+}
+
+#[derive(Deserialize)]
+pub struct CancelReconciliationModel {
+    pub account_id: Uuid,
+    pub id: Uuid,
+}
@@ -0,0 +1,67 @@
+use axum::{extract::{Path, State}, routing::{delete, get, post, put}, Json, Router};
+use uuid::Uuid;
+
+use crate::{auth::middleware::AuthUser, state::AppState};
+
+use super::{error::Result, models::{CancelReconciliationModel, FinishReconciliationModel, ReconciliationModel, ReconciliationReportModel, SetClearedModel, StartReconciliationModel}, DynReconciliationService};
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route("/{account_id}", get(get_reconciliations))
+        .route("/{account_id}/{id}", get(get_reconciliation))
+        .route("/", post(start_reconciliation))
+        .route("/", delete(cancel_reconciliation))
+        .route("/cleared", put(set_cleared))
+        .route("/finish", post(finish_reconciliation))
+        .with_state(state)
+}
+
+pub async fn get_reconciliations(
+    State(reconciliation_svc): State<DynReconciliationService>,
+    Path(account_id): Path<Uuid>,
+    user: AuthUser,
+) -> Result<Json<Vec<ReconciliationModel>>> {
+    Ok(Json(reconciliation_svc.get_reconciliations(user.id, account_id).await?))
+}
+
+pub async fn get_reconciliation(
+    State(reconciliation_svc): State<DynReconciliationService>,
+    Path((account_id, id)): Path<(Uuid, Uuid)>,
+    user: AuthUser,
+) -> Result<Json<ReconciliationReportModel>> {
+    Ok(Json(reconciliation_svc.get_reconciliation(user.id, account_id, id).await?))
+}
+
+pub async fn start_reconciliation(
+    State(reconciliation_svc): State<DynReconciliationService>,
+    user: AuthUser,
+    Json(body): Json<StartReconciliationModel>,
+) -> Result<Json<ReconciliationReportModel>> {
+    Ok(Json(reconciliation_svc.start_reconciliation(user.id, body).await?))
+}
+
+pub async fn cancel_reconciliation(
+    State(reconciliation_svc): State<DynReconciliationService>,
+    user: AuthUser,
+    Json(body): Json<CancelReconciliationModel>,
+) -> Result<()> {
+    reconciliation_svc.cancel_reconciliation(user.id, body).await?;
+    Ok(())
+}
+
+pub async fn set_cleared(
+    State(reconciliation_svc): State<DynReconciliationService>,
+    user: AuthUser,
+    Json(body): Json<SetClearedModel>,
+) -> Result<()> {
+    reconciliation_svc.set_cleared(user.id, body).await?;
+    Ok(())
+}
+
+pub async fn finish_reconciliation(
+    State(reconciliation_svc): State<DynReconciliationService>,
+    user: AuthUser,
+    Json(body): Json<FinishReconciliationModel>,
+) -> Result<Json<ReconciliationModel>> {
+    Ok(Json(reconciliation_svc.finish_reconciliation(user.id, body).await?))
+}
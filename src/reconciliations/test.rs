@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use sea_orm::{sea_query::TableCreateStatement, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, Schema, Set};
+use uuid::Uuid;
+
+use schmeconomics_entities::{account_users, accounts, categories, prelude::*, transactions, users};
+use utils_rs::date_time_provider::MockDateTimeProvider;
+
+use crate::{db_utils::Role, reconciliations::{models::{CancelReconciliationModel, FinishReconciliationModel, SetClearedModel, StartReconciliationModel}, Error, ReconciliationService}};
+
+use super::DbConnReconciliationService;
+
+lazy_static! {
+    static ref TEST_USER_1_ID: Uuid = Uuid::parse_str("be5ca263-2307-4e5a-acbd-3281fb81ea60").unwrap();
+    static ref TEST_ACCOUNT_1_ID: Uuid = Uuid::parse_str("f017369e-9dd1-4434-b197-40361cc0dbcd").unwrap();
+    static ref TEST_CAT_1_ID: Uuid = Uuid::parse_str("c8be0f8e-629e-46ce-9e76-e691caa0714b").unwrap();
+
+    // 2024-11-10 12:03:34
+    static ref TEST_DT: DateTime<Utc> = DateTime::<Utc>::from_timestamp_millis(1731240214000).unwrap();
+}
+
+async fn create_test_db() -> anyhow::Result<DbConn> {
+    // In-memory Sqlite connection
+    let db = Database::connect("sqlite::memory:").await?;
+
+    // Schema and Tables SeaOrm statements
+    let schema = Schema::new(DbBackend::Sqlite);
+    let user_stmt: TableCreateStatement = schema.create_table_from_entity(Users);
+    let account_stmt: TableCreateStatement = schema.create_table_from_entity(Accounts);
+    let account_user_stmt: TableCreateStatement = schema.create_table_from_entity(AccountUsers);
+    let category_stmt: TableCreateStatement = schema.create_table_from_entity(Categories);
+    let tx_stmt: TableCreateStatement = schema.create_table_from_entity(Transactions);
+    let split_stmt: TableCreateStatement = schema.create_table_from_entity(TransactionSplits);
+    let tx_tag_stmt: TableCreateStatement = schema.create_table_from_entity(TransactionTags);
+    let reconciliation_stmt: TableCreateStatement = schema.create_table_from_entity(Reconciliations);
+    let ledger_stmt: TableCreateStatement = schema.create_table_from_entity(CategoryLedger);
+
+    db.execute(db.get_database_backend().build(&user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&category_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_stmt)).await?;
+    db.execute(db.get_database_backend().build(&split_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_tag_stmt)).await?;
+    db.execute(db.get_database_backend().build(&reconciliation_stmt)).await?;
+    db.execute(db.get_database_backend().build(&ledger_stmt)).await?;
+
+    // Insert test user
+    let new_user = users::ActiveModel {
+        id: Set(*TEST_USER_1_ID),
+        email: Set(String::from("user1@mail.com")),
+        email_verified: Set(true),
+        password_hash: Set(String::from("password")),
+        name: Set(String::from("tester 1")),
+        created_on_utc: Set(Utc::now()),
+        two_factor_enabled: Set(false),
+
+        ..Default::default()
+    };
+    Users::insert(new_user).exec(&db).await?;
+
+    // Create test account
+    let account = accounts::ActiveModel {
+        id: Set(*TEST_ACCOUNT_1_ID),
+        ..Default::default()
+    };
+    Accounts::insert(account).exec(&db).await?;
+
+    let account_user = account_users::ActiveModel {
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        user_id: Set(*TEST_USER_1_ID),
+        role: Set(Role::Admin.to_string()),
+        verified: Set(true),
+        created_on: Set(Utc::now()),
+    };
+    AccountUsers::insert(account_user).exec(&db).await?;
+
+    let cat = categories::ActiveModel {
+        id: Set(*TEST_CAT_1_ID),
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        name: Set(String::from("Cat1")),
+        balance: Set(0),
+        refill_value: Set(0),
+        order: Set(1),
+    };
+    Categories::insert(cat).exec(&db).await?;
+
+    // Two transactions before the statement end, one after it, and a refill which is never reconciled
+    for (amount, days_ago, is_refill) in [(-1000, 10, false), (-2500, 5, false), (-400, 1, false), (5000, 10, true)] {
+        Transactions::insert(
+            transactions::ActiveModel {
+                account_id: Set(*TEST_ACCOUNT_1_ID),
+                category_id: Set(Some(*TEST_CAT_1_ID)),
+                timestamp: Set(*TEST_DT - Duration::days(days_ago)),
+                amount: Set(amount),
+                is_refill: Set(is_refill),
+
+                ..Default::default()
+            }
+        ).exec(&db).await?;
+    }
+
+    Ok(db)
+}
+
+async fn create_test_service() -> anyhow::Result<(DbConnReconciliationService, DbConn)> {
+    let db = create_test_db().await?;
+
+    let mut mock_dt_service = MockDateTimeProvider::new();
+    mock_dt_service.expect_utc_now().returning(|| *TEST_DT);
+
+    Ok((DbConnReconciliationService { db: db.clone(), dt_provider: Arc::new(mock_dt_service) }, db))
+}
+
+fn set_cleared(tx_ids: Vec<i32>, is_cleared: bool) -> SetClearedModel {
+    SetClearedModel { account_id: *TEST_ACCOUNT_1_ID, tx_ids, is_cleared }
+}
+
+fn finish(id: Uuid, create_adjustment: bool) -> FinishReconciliationModel {
+    FinishReconciliationModel { account_id: *TEST_ACCOUNT_1_ID, id, create_adjustment, adjustment_cat_id: Some(*TEST_CAT_1_ID) }
+}
+
+#[tokio::test]
+async fn test_reconcile() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let report = svc.start_reconciliation(
+        *TEST_USER_1_ID,
+        StartReconciliationModel { account_id: *TEST_ACCOUNT_1_ID, statement_end_utc: *TEST_DT - Duration::days(3), statement_balance: -3500 }
+    ).await?;
+    let id = report.reconciliation.id;
+    assert_eq!(0, report.reconciliation.cleared_total);
+    assert_eq!(vec![1, 2], report.uncleared.iter().map(|tx| tx.id).collect::<Vec<i32>>());
+
+    // Only one reconciliation may be in progress
+    let res = svc.start_reconciliation(
+        *TEST_USER_1_ID,
+        StartReconciliationModel { account_id: *TEST_ACCOUNT_1_ID, statement_end_utc: *TEST_DT, statement_balance: 0 }
+    ).await;
+    assert!(matches!(res, Err(Error::ReconciliationInProgress(open_id)) if open_id == id));
+
+    // Refills never appear on a statement
+    let res = svc.set_cleared(*TEST_USER_1_ID, set_cleared(vec![1, 4], true)).await;
+    assert!(matches!(res, Err(Error::TransactionNotFound(4))));
+
+    svc.set_cleared(*TEST_USER_1_ID, set_cleared(vec![1, 3], true)).await?;
+    let report = svc.get_reconciliation(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, id).await?;
+    assert_eq!(-1000, report.reconciliation.cleared_total);
+    assert_eq!(-2500, report.reconciliation.difference);
+    assert_eq!(vec![2], report.uncleared.iter().map(|tx| tx.id).collect::<Vec<i32>>());
+    assert_eq!(vec![3], report.cleared_after_end.iter().map(|tx| tx.id).collect::<Vec<i32>>());
+
+    let res = svc.finish_reconciliation(*TEST_USER_1_ID, finish(id, false)).await;
+    assert!(matches!(res, Err(Error::Unbalanced(-2500))));
+
+    svc.set_cleared(*TEST_USER_1_ID, set_cleared(vec![2], true)).await?;
+    let completed = svc.finish_reconciliation(*TEST_USER_1_ID, finish(id, false)).await?;
+    assert_eq!(0, completed.difference);
+    assert_eq!(None, completed.adjustment_tx_id);
+    assert_eq!(Some(*TEST_DT), completed.completed_on_utc);
+
+    // Only the cleared transactions up to the statement end are locked
+    let txs = Transactions::find().all(&db).await?;
+    assert_eq!(Some(id), txs[0].reconciliation_id);
+    assert_eq!(Some(id), txs[1].reconciliation_id);
+    assert_eq!(None, txs[2].reconciliation_id);
+    assert_eq!(None, txs[3].reconciliation_id);
+
+    // A transaction cleared and backdated into the reconciled period does not change its totals
+    Transactions::insert(
+        transactions::ActiveModel {
+            account_id: Set(*TEST_ACCOUNT_1_ID),
+            category_id: Set(Some(*TEST_CAT_1_ID)),
+            timestamp: Set(*TEST_DT - Duration::days(7)),
+            amount: Set(-300),
+            is_cleared: Set(true),
+
+            ..Default::default()
+        }
+    ).exec(&db).await?;
+    let report = svc.get_reconciliation(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, id).await?;
+    assert_eq!(-3500, report.reconciliation.cleared_total);
+    assert_eq!(0, report.reconciliation.difference);
+
+    let res = svc.set_cleared(*TEST_USER_1_ID, set_cleared(vec![1], false)).await;
+    assert!(matches!(res, Err(Error::ReconciledTransaction(1))));
+    let res = svc.cancel_reconciliation(*TEST_USER_1_ID, CancelReconciliationModel { account_id: *TEST_ACCOUNT_1_ID, id }).await;
+    assert!(matches!(res, Err(Error::AlreadyCompleted(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reconcile_with_adjustment() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let report = svc.start_reconciliation(
+        *TEST_USER_1_ID,
+        StartReconciliationModel { account_id: *TEST_ACCOUNT_1_ID, statement_end_utc: *TEST_DT - Duration::days(3), statement_balance: -1200 }
+    ).await?;
+    let id = report.reconciliation.id;
+    svc.set_cleared(*TEST_USER_1_ID, set_cleared(vec![1], true)).await?;
+
+    // The bank charged a fee which was never recorded
+    let completed = svc.finish_reconciliation(*TEST_USER_1_ID, finish(id, true)).await?;
+    assert_eq!(0, completed.difference);
+    assert_eq!(-1200, completed.cleared_total);
+
+    let adjustment = Transactions::find_by_id(completed.adjustment_tx_id.unwrap()).one(&db).await?.unwrap();
+    assert_eq!(-200, adjustment.amount);
+    assert_eq!(*TEST_DT - Duration::days(3), adjustment.timestamp);
+    assert!(adjustment.is_cleared);
+    assert_eq!(Some(id), adjustment.reconciliation_id);
+    assert_eq!(-200, Categories::find_by_id(*TEST_CAT_1_ID).one(&db).await?.unwrap().balance);
+
+    // The next statement must start after this one
+    let res = svc.start_reconciliation(
+        *TEST_USER_1_ID,
+        StartReconciliationModel { account_id: *TEST_ACCOUNT_1_ID, statement_end_utc: *TEST_DT - Duration::days(4), statement_balance: 0 }
+    ).await;
+    assert!(matches!(res, Err(Error::StatementEndNotAfterPrevious(_))));
+
+    Ok(())
+}
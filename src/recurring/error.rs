@@ -0,0 +1,42 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use log::error;
+use sea_orm::DbErr;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{currency_conv_provider, db_utils::DbUtilsError, response::internal_server_error_response};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("An error occurred while invoking the CurrencyConversionProvider: {0}")]
+    CurrencyConversionProviderError(#[from] currency_conv_provider::error::Error),
+    #[error("An error occurred while connecting to the database: {0}")]
+    DbErr(#[from] DbErr),
+    #[error(transparent)]
+    DbUtilsError(#[from] DbUtilsError),
+    #[error("Invalid recurrence rule '{0}'. Expected e.g. 'FREQ=MONTHLY;INTERVAL=1'")]
+    InvalidRule(String),
+    #[error("Recurring transaction end date must be after its start date")]
+    InvalidEndDate,
+    #[error("Category with ID '{0}' not found in account")]
+    CategoryNotFound(Uuid),
+    #[error("No recurring transaction found with ID '{0}'")]
+    RecurringNotFound(Uuid),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> axum::response::Response {
+        return match self {
+            Error::DbErr(_) | Error::DbUtilsError(_) | Error::CurrencyConversionProviderError(_) => {
+                error!("{}", self);
+                internal_server_error_response()
+            },
+            Error::InvalidRule(_) | Error::InvalidEndDate |
+            Error::CategoryNotFound(_) | Error::RecurringNotFound(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+        };
+    }
+}
@@ -0,0 +1,360 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::error;
+use schmeconomics_entities::{categories, prelude::*, recurring_transactions, transactions};
+use sea_orm::{prelude::Uuid, ColumnTrait, DbConn, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, Set, TransactionTrait};
+use serde::Deserialize;
+use utils_rs::date_time_provider::DynDateTimeProvider;
+
+use crate::{currency_conv_provider::{DynCurrencyConversionProvider, USD_CURRENCY_TYPE}, db_utils::{adjust_category_balances, validate_user_account_role, LedgerContext, LedgerSource, Role}};
+
+use {error::*, models::*};
+
+pub mod error;
+pub mod models;
+pub mod routes;
+
+#[cfg(test)]
+mod test;
+
+pub type DynRecurringService = Arc<dyn RecurringService + Send + Sync>;
+
+///
+/// Maximum number of occurrences posted for one recurring transaction per run.
+/// Any further due occurrences are posted on the following runs.
+///
+const MAX_OCCURRENCES_PER_RUN: usize = 100;
+
+#[derive(Deserialize)]
+pub struct Config {
+    ///
+    /// How often, in seconds, the background job posts due recurring transactions
+    ///
+    pub run_interval_s: u64,
+}
+
+#[async_trait]
+pub trait RecurringService {
+    async fn get_recurring(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<RecurringTransactionModel>>;
+    async fn create_recurring(&self, user_id: Uuid, req: CreateRecurringTransactionModel) -> Result<RecurringTransactionModel>;
+    async fn update_recurring(&self, user_id: Uuid, req: UpdateRecurringTransactionModel) -> Result<RecurringTransactionModel>;
+    ///
+    /// Pauses or resumes a recurring transaction. Occurrences which fell
+    /// while it was paused are skipped, rather than posted on resume.
+    ///
+    async fn set_paused(&self, user_id: Uuid, account_id: Uuid, id: Uuid, paused: bool) -> Result<RecurringTransactionModel>;
+    ///
+    /// Skips the next pending occurrence, without posting a transaction for it
+    ///
+    async fn skip_next(&self, user_id: Uuid, account_id: Uuid, id: Uuid) -> Result<RecurringTransactionModel>;
+    async fn delete_recurring(&self, user_id: Uuid, account_id: Uuid, id: Uuid) -> Result<()>;
+    ///
+    /// Posts a transaction for every pending occurrence up to now, across all accounts,
+    /// up to `MAX_OCCURRENCES_PER_RUN` for each recurring transaction.
+    /// Returns the number of transactions posted.
+    ///
+    async fn post_due_transactions(&self) -> Result<u64>;
+}
+
+pub struct DbConnRecurringService {
+    db: DbConn,
+    dt_provider: DynDateTimeProvider,
+    cc_provider: DynCurrencyConversionProvider,
+}
+
+#[async_trait]
+impl RecurringService for DbConnRecurringService {
+    async fn get_recurring(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<RecurringTransactionModel>> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Read).await?;
+
+        Ok(
+            RecurringTransactions::find()
+                .filter(recurring_transactions::Column::AccountId.eq(account_id))
+                .order_by_asc(recurring_transactions::Column::StartUtc)
+                .all(&self.db).await?
+                .into_iter()
+                .map(|r| r.into())
+                .collect()
+        )
+    }
+
+    async fn create_recurring(&self, user_id: Uuid, req: CreateRecurringTransactionModel) -> Result<RecurringTransactionModel> {
+        validate_user_account_role(&self.db, user_id, req.account_id, Role::Write).await?;
+
+        let rule = req.rrule.parse::<RecurrenceRule>()?;
+        if req.end_utc.is_some_and(|end| end <= req.start_utc) {
+            return Err(Error::InvalidEndDate);
+        }
+        if let Some(cat_id) = req.category_id {
+            self.validate_category(req.account_id, cat_id).await?;
+        }
+
+        let recurring = recurring_transactions::ActiveModel {
+            id: Set(Uuid::now_v7()),
+            account_id: Set(req.account_id),
+            user_id: Set(Some(user_id)),
+            category_id: Set(req.category_id),
+            amount: Set(req.amount),
+            currency_type: Set(req.currency_type),
+            notes: Set(req.notes),
+            rrule: Set(rule.to_string()),
+            start_utc: Set(req.start_utc),
+            end_utc: Set(req.end_utc),
+            occurrence_idx: Set(0),
+            next_occurrence_utc: Set(next_occurrence(&rule, req.start_utc, 0, req.end_utc)),
+            paused: Set(false),
+        };
+
+        Ok(RecurringTransactions::insert(recurring).exec_with_returning(&self.db).await?.into())
+    }
+
+    async fn update_recurring(&self, user_id: Uuid, req: UpdateRecurringTransactionModel) -> Result<RecurringTransactionModel> {
+        validate_user_account_role(&self.db, user_id, req.account_id, Role::Write).await?;
+
+        let recurring = self.find_recurring(req.account_id, req.id).await?;
+        if let Some(cat_id) = req.new_category_id {
+            self.validate_category(req.account_id, cat_id).await?;
+        }
+
+        let mut rule = recurring.rrule.parse::<RecurrenceRule>()?;
+        let mut start_utc = recurring.start_utc;
+        let mut occurrence_idx = recurring.occurrence_idx;
+        let end_utc = match req.new_end_utc {
+            Some(new_end_utc) => new_end_utc,
+            None => recurring.end_utc,
+        };
+
+        // A new rule restarts the schedule from the next pending occurrence
+        if let Some(new_rrule) = req.new_rrule {
+            rule = new_rrule.parse::<RecurrenceRule>()?;
+            start_utc = recurring.next_occurrence_utc.unwrap_or(self.dt_provider.utc_now());
+            occurrence_idx = 0;
+        }
+        if end_utc.is_some_and(|end| end <= start_utc) {
+            return Err(Error::InvalidEndDate);
+        }
+
+        let mut active = recurring.into_active_model();
+        if let Some(amount) = req.new_amount { active.amount = Set(amount); }
+        if let Some(currency_type) = req.new_currency_type { active.currency_type = Set(currency_type); }
+        if let Some(cat_id) = req.new_category_id { active.category_id = Set(Some(cat_id)); }
+        if let Some(notes) = req.new_notes { active.notes = Set(Some(notes)); }
+        active.rrule = Set(rule.to_string());
+        active.start_utc = Set(start_utc);
+        active.end_utc = Set(end_utc);
+        active.occurrence_idx = Set(occurrence_idx);
+        active.next_occurrence_utc = Set(next_occurrence(&rule, start_utc, occurrence_idx as u32, end_utc));
+
+        Ok(RecurringTransactions::update(active).exec(&self.db).await?.into())
+    }
+
+    async fn set_paused(&self, user_id: Uuid, account_id: Uuid, id: Uuid, paused: bool) -> Result<RecurringTransactionModel> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Write).await?;
+
+        let recurring = self.find_recurring(account_id, id).await?;
+        let rule = recurring.rrule.parse::<RecurrenceRule>()?;
+
+        // Skip past every occurrence which is already due when resuming
+        let mut occurrence_idx = recurring.occurrence_idx as u32;
+        if recurring.paused && !paused {
+            let now = self.dt_provider.utc_now();
+            while next_occurrence(&rule, recurring.start_utc, occurrence_idx, recurring.end_utc)
+                .is_some_and(|at| at <= now)
+            {
+                occurrence_idx += 1;
+            }
+        }
+
+        let next_occurrence_utc = next_occurrence(&rule, recurring.start_utc, occurrence_idx, recurring.end_utc);
+        let mut active = recurring.into_active_model();
+        active.paused = Set(paused);
+        active.occurrence_idx = Set(occurrence_idx as i32);
+        active.next_occurrence_utc = Set(next_occurrence_utc);
+
+        Ok(RecurringTransactions::update(active).exec(&self.db).await?.into())
+    }
+
+    async fn skip_next(&self, user_id: Uuid, account_id: Uuid, id: Uuid) -> Result<RecurringTransactionModel> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Write).await?;
+
+        let recurring = self.find_recurring(account_id, id).await?;
+        if recurring.next_occurrence_utc.is_none() {
+            return Ok(recurring.into());
+        }
+        let rule = recurring.rrule.parse::<RecurrenceRule>()?;
+
+        let occurrence_idx = recurring.occurrence_idx + 1;
+        let next_occurrence_utc = next_occurrence(&rule, recurring.start_utc, occurrence_idx as u32, recurring.end_utc);
+        let mut active = recurring.into_active_model();
+        active.occurrence_idx = Set(occurrence_idx);
+        active.next_occurrence_utc = Set(next_occurrence_utc);
+
+        Ok(RecurringTransactions::update(active).exec(&self.db).await?.into())
+    }
+
+    async fn delete_recurring(&self, user_id: Uuid, account_id: Uuid, id: Uuid) -> Result<()> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Write).await?;
+
+        let res = RecurringTransactions::delete_many()
+            .filter(recurring_transactions::Column::Id.eq(id))
+            .filter(recurring_transactions::Column::AccountId.eq(account_id))
+            .exec(&self.db).await?;
+
+        return if res.rows_affected > 0 {
+            Ok(())
+        } else {
+            Err(Error::RecurringNotFound(id))
+        };
+    }
+
+    async fn post_due_transactions(&self) -> Result<u64> {
+        let now = self.dt_provider.utc_now();
+        let due = RecurringTransactions::find()
+            .filter(recurring_transactions::Column::Paused.eq(false))
+            .filter(recurring_transactions::Column::NextOccurrenceUtc.lte(now))
+            .all(&self.db).await?;
+
+        let mut posted = 0;
+        for recurring in due {
+            let id = recurring.id;
+            // One failing recurring transaction should not hold up the others,
+            // its occurrences are retried on the next run
+            match self.post_due(recurring, now).await {
+                Ok(count) => posted += count,
+                Err(e) => error!("Could not post recurring transaction {}: {}", id, e),
+            }
+        }
+        Ok(posted)
+    }
+}
+
+impl DbConnRecurringService {
+    pub fn new_dyn(
+        db: DbConn,
+        dt_provider: DynDateTimeProvider,
+        cc_provider: DynCurrencyConversionProvider
+    ) -> DynRecurringService {
+        Arc::new(Self { db, dt_provider, cc_provider })
+    }
+
+    ///
+    /// Posts a transaction for each occurrence of `recurring` up to `now`,
+    /// at most `MAX_OCCURRENCES_PER_RUN` at a time. Returns the number of transactions posted.
+    ///
+    async fn post_due(&self, recurring: recurring_transactions::Model, now: DateTime<Utc>) -> Result<u64> {
+        let rule = recurring.rrule.parse::<RecurrenceRule>()?;
+
+        let mut occurrence_idx = recurring.occurrence_idx as u32;
+        let mut occurrences = vec![];
+        while occurrences.len() < MAX_OCCURRENCES_PER_RUN {
+            match next_occurrence(&rule, recurring.start_utc, occurrence_idx, recurring.end_utc) {
+                Some(at) if at <= now => {
+                    occurrences.push(at);
+                    occurrence_idx += 1;
+                },
+                _ => break,
+            }
+        }
+        if occurrences.is_empty() {
+            return Ok(0);
+        }
+
+        let (amount, rate) = self.cc_provider.convert_with_rate(&recurring.currency_type, USD_CURRENCY_TYPE, recurring.amount).await?;
+
+        let tx = self.db.begin().await?;
+
+        // Claim the occurrences. If another run has already posted them
+        // the occurrence index has moved on, and no row is updated
+        let claimed = RecurringTransactions::update_many()
+            .filter(recurring_transactions::Column::Id.eq(recurring.id))
+            .filter(recurring_transactions::Column::OccurrenceIdx.eq(recurring.occurrence_idx))
+            .set(recurring_transactions::ActiveModel {
+                occurrence_idx: Set(occurrence_idx as i32),
+                next_occurrence_utc: Set(next_occurrence(&rule, recurring.start_utc, occurrence_idx, recurring.end_utc)),
+                ..Default::default()
+            })
+            .exec(&tx).await?;
+
+        if claimed.rows_affected == 0 {
+            return Ok(0);
+        }
+
+        let insertions = occurrences.iter()
+            .map(|at| transactions::ActiveModel {
+                account_id:     Set(recurring.account_id),
+                user_id:        Set(recurring.user_id),
+                category_id:    Set(recurring.category_id),
+                timestamp:      Set(*at),
+                amount:         Set(amount),
+                notes:          Set(recurring.notes.clone()),
+                is_refill:      Set(false),
+                orig_currency_type: Set(Some(recurring.currency_type.clone())),
+                orig_amount:    Set(Some(recurring.amount)),
+                exchange_rate:  Set(Some(rate)),
+
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        Transactions::insert_many(insertions).exec(&tx).await?;
+
+        adjust_category_balances(
+            &tx,
+            HashMap::from([(recurring.category_id, amount * occurrences.len() as i64)]),
+            &LedgerContext { source: LedgerSource::Recurring, user_id: recurring.user_id, timestamp: now }
+        ).await?;
+        tx.commit().await?;
+
+        Ok(occurrences.len() as u64)
+    }
+
+    async fn find_recurring(&self, account_id: Uuid, id: Uuid) -> Result<recurring_transactions::Model> {
+        RecurringTransactions::find_by_id(id)
+            .filter(recurring_transactions::Column::AccountId.eq(account_id))
+            .one(&self.db).await?
+            .ok_or(Error::RecurringNotFound(id))
+    }
+
+    async fn validate_category(&self, account_id: Uuid, cat_id: Uuid) -> Result<()> {
+        let cat = Categories::find_by_id(cat_id)
+            .filter(categories::Column::AccountId.eq(account_id))
+            .one(&self.db).await?;
+
+        return if cat.is_some() {
+            Ok(())
+        } else {
+            Err(Error::CategoryNotFound(cat_id))
+        };
+    }
+}
+
+///
+/// Returns the `idx`th occurrence of the rule, or `None` if it falls after `end`
+///
+fn next_occurrence(
+    rule: &RecurrenceRule,
+    start: DateTime<Utc>,
+    idx: u32,
+    end: Option<DateTime<Utc>>
+) -> Option<DateTime<Utc>> {
+    rule.nth(start, idx).filter(|at| end.is_none_or(|end| *at <= end))
+}
+
+impl From<recurring_transactions::Model> for RecurringTransactionModel {
+    fn from(value: recurring_transactions::Model) -> Self {
+        RecurringTransactionModel {
+            id: value.id,
+            account_id: value.account_id,
+            category_id: value.category_id,
+            amount: value.amount,
+            currency_type: value.currency_type,
+            notes: value.notes,
+            rrule: value.rrule,
+            start_utc: value.start_utc,
+            end_utc: value.end_utc,
+            next_occurrence_utc: value.next_occurrence_utc,
+            paused: value.paused,
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Days, Months, Utc};
+use sea_orm::prelude::{DateTimeUtc, Uuid};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use super::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Frequency { Daily, Weekly, Monthly, Yearly, }
+
+///
+/// A subset of an iCalendar RRULE, e.g. `FREQ=MONTHLY;INTERVAL=2`.
+/// Occurrences repeat every `interval` periods of `freq` from the start date.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RecurrenceRule {
+    pub freq: Frequency,
+    pub interval: u32,
+}
+
+impl FromStr for RecurrenceRule {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut freq = None;
+        let mut interval = 1;
+
+        for part in s.trim().trim_start_matches("RRULE:").split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part.split_once('=')
+                .ok_or(Error::InvalidRule(s.to_string()))?;
+            match key.trim().to_uppercase().as_str() {
+                "FREQ" => freq = Some(match value.trim().to_uppercase().as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return Err(Error::InvalidRule(s.to_string())),
+                }),
+                "INTERVAL" => interval = value.trim().parse::<u32>()
+                    .ok()
+                    .filter(|i| *i > 0)
+                    .ok_or(Error::InvalidRule(s.to_string()))?,
+                _ => return Err(Error::InvalidRule(s.to_string())),
+            }
+        }
+
+        Ok(Self { freq: freq.ok_or(Error::InvalidRule(s.to_string()))?, interval })
+    }
+}
+
+impl ToString for RecurrenceRule {
+    fn to_string(&self) -> String {
+        let freq = match self.freq {
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        format!("FREQ={};INTERVAL={}", freq, self.interval)
+    }
+}
+
+impl RecurrenceRule {
+    ///
+    /// Returns the `n`th (0-based) occurrence after `start`. Monthly and yearly
+    /// occurrences are always counted from `start`, so a rule starting on the 31st
+    /// falls on the last day of shorter months without drifting.
+    ///
+    pub fn nth(&self, start: DateTime<Utc>, n: u32) -> Option<DateTime<Utc>> {
+        let steps = n.checked_mul(self.interval)?;
+        match self.freq {
+            Frequency::Daily => start.checked_add_days(Days::new(steps as u64)),
+            Frequency::Weekly => start.checked_add_days(Days::new(steps as u64 * 7)),
+            Frequency::Monthly => start.checked_add_months(Months::new(steps)),
+            Frequency::Yearly => start.checked_add_months(Months::new(steps.checked_mul(12)?)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecurringTransactionModel {
+    pub id: Uuid,
+    pub account_id: Uuid,
+    pub category_id: Option<Uuid>,
+    pub amount: i64,
+    pub currency_type: String,
+    pub notes: Option<String>,
+    pub rrule: String,
+    pub start_utc: DateTimeUtc,
+    pub end_utc: Option<DateTimeUtc>,
+    ///
+    /// When the next transaction will be posted.
+    /// `None` once the last occurrence before `end_utc` has been posted.
+    ///
+    pub next_occurrence_utc: Option<DateTimeUtc>,
+    pub paused: bool,
+}
+
+#[derive(Deserialize)]
+pub struct CreateRecurringTransactionModel {
+    pub account_id: Uuid,
+    pub category_id: Option<Uuid>,
+    pub amount: i64,
+    pub currency_type: String,
+    pub notes: Option<String>,
+    pub rrule: String,
+    pub start_utc: DateTimeUtc,
+    pub end_utc: Option<DateTimeUtc>,
+}
+
+///
+/// Changes to a recurring transaction. Changing the `rrule` restarts
+/// the schedule from the next pending occurrence.
+///
+#[derive(Deserialize)]
+pub struct UpdateRecurringTransactionModel {
+    pub account_id: Uuid,
+    pub id: Uuid,
+    pub new_amount: Option<i64>,
+    pub new_currency_type: Option<String>,
+    pub new_category_id: Option<Uuid>,
+    pub new_notes: Option<String>,
+    pub new_rrule: Option<String>,
+    ///
+    /// `null` clears the end date, so the transaction recurs indefinitely
+    ///
+    #[serde(default, deserialize_with = "present_or_null")]
+    pub new_end_utc: Option<Option<DateTimeUtc>>,
+}
+
+///
+/// Deserializes a field which is present as `Some`, even when it is `null`,
+/// so a missing field can be told apart from one being cleared
+///
+fn present_or_null<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
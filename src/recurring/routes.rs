@@ -0,0 +1,75 @@
+use axum::{extract::{Path, State}, routing::{delete, get, post, put}, Json, Router};
+use uuid::Uuid;
+
+use crate::{auth::middleware::AuthUser, state::AppState};
+
+use super::{error::Result, models::{CreateRecurringTransactionModel, RecurringTransactionModel, UpdateRecurringTransactionModel}, DynRecurringService};
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route("/create", post(create_recurring))
+        .route("/update", put(update_recurring))
+        .route("/{account_id}", get(get_recurring))
+        .route("/{account_id}/{id}", delete(delete_recurring))
+        .route("/{account_id}/{id}/pause", post(pause_recurring))
+        .route("/{account_id}/{id}/resume", post(resume_recurring))
+        .route("/{account_id}/{id}/skip", post(skip_next))
+        .with_state(state)
+}
+
+pub async fn get_recurring(
+    State(recurring_svc): State<DynRecurringService>,
+    Path(account_id): Path<Uuid>,
+    user: AuthUser,
+) -> Result<Json<Vec<RecurringTransactionModel>>> {
+    Ok(Json(recurring_svc.get_recurring(user.id, account_id).await?))
+}
+
+pub async fn create_recurring(
+    State(recurring_svc): State<DynRecurringService>,
+    user: AuthUser,
+    Json(body): Json<CreateRecurringTransactionModel>,
+) -> Result<Json<RecurringTransactionModel>> {
+    Ok(Json(recurring_svc.create_recurring(user.id, body).await?))
+}
+
+pub async fn update_recurring(
+    State(recurring_svc): State<DynRecurringService>,
+    user: AuthUser,
+    Json(body): Json<UpdateRecurringTransactionModel>,
+) -> Result<Json<RecurringTransactionModel>> {
+    Ok(Json(recurring_svc.update_recurring(user.id, body).await?))
+}
+
+pub async fn pause_recurring(
+    State(recurring_svc): State<DynRecurringService>,
+    Path((account_id, id)): Path<(Uuid, Uuid)>,
+    user: AuthUser,
+) -> Result<Json<RecurringTransactionModel>> {
+    Ok(Json(recurring_svc.set_paused(user.id, account_id, id, true).await?))
+}
+
+pub async fn resume_recurring(
+    State(recurring_svc): State<DynRecurringService>,
+    Path((account_id, id)): Path<(Uuid, Uuid)>,
+    user: AuthUser,
+) -> Result<Json<RecurringTransactionModel>> {
+    Ok(Json(recurring_svc.set_paused(user.id, account_id, id, false).await?))
+}
+
+pub async fn skip_next(
+    State(recurring_svc): State<DynRecurringService>,
+    Path((account_id, id)): Path<(Uuid, Uuid)>,
+    user: AuthUser,
+) -> Result<Json<RecurringTransactionModel>> {
+    Ok(Json(recurring_svc.skip_next(user.id, account_id, id).await?))
+}
+
+pub async fn delete_recurring(
+    State(recurring_svc): State<DynRecurringService>,
+    Path((account_id, id)): Path<(Uuid, Uuid)>,
+    user: AuthUser,
+) -> Result<()> {
+    recurring_svc.delete_recurring(user.id, account_id, id).await?;
+    Ok(())
+}
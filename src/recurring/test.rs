@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Days, TimeZone, Utc};
+use lazy_static::lazy_static;
+use mockall::predicate::{always, eq};
+use sea_orm::{prelude::{Expr, Uuid}, sea_query::TableCreateStatement, ColumnTrait, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, QueryFilter, Schema, Set};
+
+use schmeconomics_entities::{account_users, accounts, categories, prelude::*, recurring_transactions, users};
+use utils_rs::date_time_provider::MockDateTimeProvider;
+
+use crate::{currency_conv_provider::{MockCurrencyConversionProvider, USD_CURRENCY_TYPE}, db_utils::Role, recurring::{models::{CreateRecurringTransactionModel, Frequency, RecurrenceRule, UpdateRecurringTransactionModel}, Error, RecurringService, MAX_OCCURRENCES_PER_RUN}};
+
+use super::DbConnRecurringService;
+
+lazy_static! {
+    static ref TEST_USER_1_ID: Uuid = Uuid::parse_str("be5ca263-2307-4e5a-acbd-3281fb81ea60").unwrap();
+    static ref TEST_ACCOUNT_1_ID: Uuid = Uuid::parse_str("f017369e-9dd1-4434-b197-40361cc0dbcd").unwrap();
+    static ref TEST_CAT_1_ID: Uuid = Uuid::parse_str("c8be0f8e-629e-46ce-9e76-e691caa0714b").unwrap();
+
+    static ref TEST_CAT_1_ORIG_BAL: i64 = 100000;
+
+    // 2024-11-10 12:03:34
+    static ref TEST_DT: DateTime<Utc> = DateTime::<Utc>::from_timestamp_millis(1731240214000).unwrap();
+}
+
+async fn create_test_db() -> anyhow::Result<DbConn> {
+    // In-memory Sqlite connection
+    let db = Database::connect("sqlite::memory:").await?;
+
+    // Schema and Tables SeaOrm statements
+    let schema = Schema::new(DbBackend::Sqlite);
+    let user_stmt: TableCreateStatement = schema.create_table_from_entity(Users);
+    let account_stmt: TableCreateStatement = schema.create_table_from_entity(Accounts);
+    let account_user_stmt: TableCreateStatement = schema.create_table_from_entity(AccountUsers);
+    let category_stmt: TableCreateStatement = schema.create_table_from_entity(Categories);
+    let tx_stmt: TableCreateStatement = schema.create_table_from_entity(Transactions);
+    let recurring_stmt: TableCreateStatement = schema.create_table_from_entity(RecurringTransactions);
+    let ledger_stmt: TableCreateStatement = schema.create_table_from_entity(CategoryLedger);
+
+    db.execute(db.get_database_backend().build(&user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&category_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_stmt)).await?;
+    db.execute(db.get_database_backend().build(&recurring_stmt)).await?;
+    db.execute(db.get_database_backend().build(&ledger_stmt)).await?;
+
+    // Insert test user
+    let new_user = users::ActiveModel {
+        id: Set(*TEST_USER_1_ID),
+        email: Set(String::from("user1@mail.com")),
+        email_verified: Set(true),
+        password_hash: Set(String::from("password")),
+        name: Set(String::from("tester 1")),
+        created_on_utc: Set(Utc::now()),
+        two_factor_enabled: Set(false),
+
+        ..Default::default()
+    };
+    Users::insert(new_user).exec(&db).await?;
+
+    // Create test account
+    let account = accounts::ActiveModel {
+        id: Set(*TEST_ACCOUNT_1_ID),
+        ..Default::default()
+    };
+    Accounts::insert(account).exec(&db).await?;
+
+    let account_user = account_users::ActiveModel {
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        user_id: Set(*TEST_USER_1_ID),
+        role: Set(Role::Admin.to_string()),
+        verified: Set(true),
+        created_on: Set(Utc::now()),
+    };
+    AccountUsers::insert(account_user).exec(&db).await?;
+
+    let cat1 = categories::ActiveModel {
+        id: Set(*TEST_CAT_1_ID),
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        name: Set(String::from("Rent")),
+        balance: Set(*TEST_CAT_1_ORIG_BAL),
+        refill_value: Set(0),
+        order: Set(1),
+    };
+    Categories::insert(cat1).exec(&db).await?;
+
+    Ok(db)
+}
+
+fn create_test_service(db: &DbConn, now: DateTime<Utc>) -> DbConnRecurringService {
+    let mut mock_dt_service = MockDateTimeProvider::new();
+    mock_dt_service.expect_utc_now().returning(move || now);
+
+    let mut mock_cc_provider = MockCurrencyConversionProvider::new();
+    mock_cc_provider.expect_convert_with_rate()
+        .with(eq(USD_CURRENCY_TYPE), eq(USD_CURRENCY_TYPE), always())
+        .returning(|_, _, am| Ok((am, 1.0)));
+
+    DbConnRecurringService {
+        db: db.clone(),
+        dt_provider: Arc::new(mock_dt_service),
+        cc_provider: Arc::new(mock_cc_provider),
+    }
+}
+
+async fn create_monthly_rent(svc: &DbConnRecurringService) -> anyhow::Result<Uuid> {
+    let recurring = svc.create_recurring(
+        *TEST_USER_1_ID,
+        CreateRecurringTransactionModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            category_id: Some(*TEST_CAT_1_ID),
+            amount: -150000,
+            currency_type: USD_CURRENCY_TYPE.to_string(),
+            notes: Some(String::from("Rent")),
+            rrule: String::from("FREQ=MONTHLY"),
+            start_utc: Utc.with_ymd_and_hms(2024, 9, 15, 0, 0, 0).unwrap(),
+            end_utc: None,
+        }
+    ).await?;
+
+    Ok(recurring.id)
+}
+
+#[tokio::test]
+async fn test_post_due_transactions() -> anyhow::Result<()> {
+    let db = create_test_db().await?;
+    let svc = create_test_service(&db, *TEST_DT);
+    create_monthly_rent(&svc).await?;
+
+    // Occurrences on Sep. 15th and Oct. 15th are due, and are only posted once
+    assert_eq!(2, svc.post_due_transactions().await?);
+    assert_eq!(0, svc.post_due_transactions().await?);
+
+    let txs = Transactions::find().all(&db).await?;
+    assert_eq!(2, txs.len());
+    assert_eq!(Utc.with_ymd_and_hms(2024, 9, 15, 0, 0, 0).unwrap(), txs[0].timestamp);
+    assert_eq!(Utc.with_ymd_and_hms(2024, 10, 15, 0, 0, 0).unwrap(), txs[1].timestamp);
+    assert_eq!(Some(*TEST_USER_1_ID), txs[0].user_id);
+
+    let cat = Categories::find_by_id(*TEST_CAT_1_ID).one(&db).await?.unwrap();
+    assert_eq!(*TEST_CAT_1_ORIG_BAL - 300000, cat.balance);
+
+    let recurring = svc.get_recurring(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID).await?;
+    assert_eq!(Some(Utc.with_ymd_and_hms(2024, 11, 15, 0, 0, 0).unwrap()), recurring[0].next_occurrence_utc);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_skip_and_pause() -> anyhow::Result<()> {
+    let db = create_test_db().await?;
+    let svc = create_test_service(&db, *TEST_DT);
+    let id = create_monthly_rent(&svc).await?;
+    svc.post_due_transactions().await?;
+
+    let recurring = svc.skip_next(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, id).await?;
+    assert_eq!(Some(Utc.with_ymd_and_hms(2024, 12, 15, 0, 0, 0).unwrap()), recurring.next_occurrence_utc);
+
+    // Nothing is posted while paused, and missed occurrences are skipped on resume
+    svc.set_paused(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, id, true).await?;
+    let later_svc = create_test_service(&db, *TEST_DT + Days::new(60));
+    assert_eq!(0, later_svc.post_due_transactions().await?);
+
+    let recurring = later_svc.set_paused(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, id, false).await?;
+    assert!(!recurring.paused);
+    assert_eq!(Some(Utc.with_ymd_and_hms(2025, 1, 15, 0, 0, 0).unwrap()), recurring.next_occurrence_utc);
+    assert_eq!(0, later_svc.post_due_transactions().await?);
+    assert_eq!(2, Transactions::find().all(&db).await?.len());
+
+    svc.delete_recurring(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, id).await?;
+    let res = svc.skip_next(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID, id).await;
+    assert!(matches!(res, Err(Error::RecurringNotFound(not_found)) if not_found == id));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_post_due_transactions_caps_backfill() -> anyhow::Result<()> {
+    let db = create_test_db().await?;
+    let svc = create_test_service(&db, *TEST_DT);
+
+    // Every day from Jan. 1st to Nov. 10th is due
+    svc.create_recurring(
+        *TEST_USER_1_ID,
+        CreateRecurringTransactionModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            category_id: Some(*TEST_CAT_1_ID),
+            amount: -500,
+            currency_type: USD_CURRENCY_TYPE.to_string(),
+            notes: Some(String::from("Coffee")),
+            rrule: String::from("FREQ=DAILY"),
+            start_utc: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            end_utc: None,
+        }
+    ).await?;
+
+    // A recurring transaction which fails to post does not hold up the others
+    let broken_id = create_monthly_rent(&svc).await?;
+    RecurringTransactions::update_many()
+        .col_expr(recurring_transactions::Column::Rrule, Expr::value("FREQ=HOURLY"))
+        .filter(recurring_transactions::Column::Id.eq(broken_id))
+        .exec(&db).await?;
+
+    let max = MAX_OCCURRENCES_PER_RUN as u64;
+    assert_eq!(max, svc.post_due_transactions().await?);
+    assert_eq!(max, svc.post_due_transactions().await?);
+    assert_eq!(max, svc.post_due_transactions().await?);
+    assert_eq!(315 - 3 * max, svc.post_due_transactions().await?);
+    assert_eq!(0, svc.post_due_transactions().await?);
+    assert_eq!(315, Transactions::find().all(&db).await?.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_recurring_end_date() -> anyhow::Result<()> {
+    let db = create_test_db().await?;
+    let svc = create_test_service(&db, *TEST_DT);
+    let id = create_monthly_rent(&svc).await?;
+    svc.post_due_transactions().await?;
+
+    let update = |new_end_utc| UpdateRecurringTransactionModel {
+        account_id: *TEST_ACCOUNT_1_ID,
+        id,
+        new_amount: None,
+        new_currency_type: None,
+        new_category_id: None,
+        new_notes: None,
+        new_rrule: None,
+        new_end_utc,
+    };
+
+    // Ending before the next occurrence leaves nothing to post
+    let end_utc = Utc.with_ymd_and_hms(2024, 11, 14, 0, 0, 0).unwrap();
+    let recurring = svc.update_recurring(*TEST_USER_1_ID, update(Some(Some(end_utc)))).await?;
+    assert_eq!(None, recurring.next_occurrence_utc);
+
+    // Leaving out the end date keeps it, and a null end date clears it
+    let recurring = svc.update_recurring(*TEST_USER_1_ID, update(None)).await?;
+    assert_eq!(Some(end_utc), recurring.end_utc);
+    let recurring = svc.update_recurring(*TEST_USER_1_ID, update(Some(None))).await?;
+    assert_eq!(None, recurring.end_utc);
+    assert_eq!(Some(Utc.with_ymd_and_hms(2024, 11, 15, 0, 0, 0).unwrap()), recurring.next_occurrence_utc);
+
+    let req = format!(r#"{{ "account_id": "{}", "id": "{}", "new_end_utc": null }}"#, *TEST_ACCOUNT_1_ID, id);
+    assert_eq!(Some(None), serde_json::from_str::<UpdateRecurringTransactionModel>(&req)?.new_end_utc);
+    let req = format!(r#"{{ "account_id": "{}", "id": "{}" }}"#, *TEST_ACCOUNT_1_ID, id);
+    assert_eq!(None, serde_json::from_str::<UpdateRecurringTransactionModel>(&req)?.new_end_utc);
+
+    Ok(())
+}
+
+#[test]
+fn test_recurrence_rule() {
+    let rule = "RRULE:FREQ=WEEKLY;INTERVAL=2".parse::<RecurrenceRule>().unwrap();
+    assert_eq!(RecurrenceRule { freq: Frequency::Weekly, interval: 2 }, rule);
+    assert_eq!("FREQ=WEEKLY;INTERVAL=2", rule.to_string());
+    assert_eq!(Some(*TEST_DT + Days::new(28)), rule.nth(*TEST_DT, 2));
+
+    // Monthly occurrences are clamped to the end of shorter months without drifting
+    let monthly = "FREQ=MONTHLY".parse::<RecurrenceRule>().unwrap();
+    let start = Utc.with_ymd_and_hms(2025, 1, 31, 0, 0, 0).unwrap();
+    assert_eq!(Some(Utc.with_ymd_and_hms(2025, 2, 28, 0, 0, 0).unwrap()), monthly.nth(start, 1));
+    assert_eq!(Some(Utc.with_ymd_and_hms(2025, 3, 31, 0, 0, 0).unwrap()), monthly.nth(start, 2));
+
+    assert!("FREQ=HOURLY".parse::<RecurrenceRule>().is_err());
+    assert!("INTERVAL=2".parse::<RecurrenceRule>().is_err());
+    assert!("FREQ=DAILY;INTERVAL=0".parse::<RecurrenceRule>().is_err());
+}
@@ -0,0 +1,37 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use log::error;
+use sea_orm::DbErr;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{db_utils::DbUtilsError, response::internal_server_error_response};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("An error occurred while connecting to the database: {0}")]
+    DbErr(#[from] DbErr),
+    #[error(transparent)]
+    DbUtilsError(#[from] DbUtilsError),
+    #[error("Could not parse RefillCadence from string {0}")]
+    CouldNotParseCadence(String),
+    #[error("Refill anchor {1} is out of range for cadence {0}")]
+    InvalidAnchor(String, i32),
+    #[error("No refill schedule found for account {0}")]
+    ScheduleNotFound(Uuid),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> axum::response::Response {
+        return match self {
+            Error::DbErr(_) | Error::DbUtilsError(_) | Error::CouldNotParseCadence(_) => {
+                error!("{}", self);
+                internal_server_error_response()
+            },
+            Error::InvalidAnchor(_, _) | Error::ScheduleNotFound(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+        };
+    }
+}
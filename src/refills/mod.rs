@@ -0,0 +1,227 @@
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use log::error;
+use schmeconomics_entities::{categories, prelude::*, refill_schedules, transactions};
+use sea_orm::{prelude::{Expr, Uuid}, ColumnTrait, Condition, DbConn, EntityTrait, IntoActiveModel, QueryFilter, Set, TransactionTrait};
+use serde::Deserialize;
+use utils_rs::date_time_provider::DynDateTimeProvider;
+
+use crate::db_utils::{adjust_category_balances, validate_user_account_role, LedgerContext, LedgerSource, Role};
+
+use {error::*, models::*};
+
+pub mod error;
+pub mod models;
+pub mod routes;
+
+#[cfg(test)]
+mod test;
+
+pub type DynRefillService = Arc<dyn RefillService + Send + Sync>;
+
+#[derive(Deserialize)]
+pub struct Config {
+    ///
+    /// How often, in seconds, the background job checks for accounts due for a refill
+    ///
+    pub run_interval_s: u64,
+}
+
+#[async_trait]
+pub trait RefillService {
+    async fn get_schedule(&self, user_id: Uuid, account_id: Uuid) -> Result<RefillScheduleModel>;
+    async fn set_schedule(&self, user_id: Uuid, req: SetRefillScheduleModel) -> Result<RefillScheduleModel>;
+    async fn delete_schedule(&self, user_id: Uuid, account_id: Uuid) -> Result<()>;
+    ///
+    /// Refills each category in the account by its `refill_value`, performed by user with the given `user_id`.
+    /// Does nothing if the account has already been refilled in the current period.
+    ///
+    async fn refill_account(&self, user_id: Uuid, account_id: Uuid) -> Result<RefillResultModel>;
+    ///
+    /// Refills every scheduled account which has not yet been refilled in its current period.
+    /// Returns the number of accounts refilled.
+    ///
+    async fn refill_due_accounts(&self) -> Result<u64>;
+}
+
+pub struct DbConnRefillService {
+    db: DbConn,
+    dt_provider: DynDateTimeProvider,
+}
+
+#[async_trait]
+impl RefillService for DbConnRefillService {
+    async fn get_schedule(&self, user_id: Uuid, account_id: Uuid) -> Result<RefillScheduleModel> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Read).await?;
+
+        return match RefillSchedules::find_by_id(account_id).one(&self.db).await? {
+            Some(schedule) => Ok(schedule.try_into()?),
+            None => Err(Error::ScheduleNotFound(account_id)),
+        };
+    }
+
+    async fn set_schedule(&self, user_id: Uuid, req: SetRefillScheduleModel) -> Result<RefillScheduleModel> {
+        validate_user_account_role(&self.db, user_id, req.account_id, Role::Admin).await?;
+
+        if !req.cadence.is_valid_anchor(req.anchor) {
+            return Err(Error::InvalidAnchor(req.cadence.to_string(), req.anchor));
+        }
+
+        let tx = self.db.begin().await?;
+        let schedule = match RefillSchedules::find_by_id(req.account_id).one(&tx).await? {
+            // Update the existing schedule, keeping the last refill date
+            // so the current period is not refilled twice
+            Some(schedule) => {
+                let mut schedule = schedule.into_active_model();
+                schedule.cadence = Set(req.cadence.to_string());
+                schedule.anchor = Set(req.anchor);
+                RefillSchedules::update(schedule).exec(&tx).await?
+            },
+            None => {
+                let schedule = refill_schedules::ActiveModel {
+                    account_id: Set(req.account_id),
+                    cadence: Set(req.cadence.to_string()),
+                    anchor: Set(req.anchor),
+                    last_refill_utc: Set(None),
+                };
+                RefillSchedules::insert(schedule).exec_with_returning(&tx).await?
+            }
+        };
+        tx.commit().await?;
+
+        Ok(schedule.try_into()?)
+    }
+
+    async fn delete_schedule(&self, user_id: Uuid, account_id: Uuid) -> Result<()> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Admin).await?;
+
+        let res = RefillSchedules::delete_by_id(account_id).exec(&self.db).await?;
+        return if res.rows_affected > 0 {
+            Ok(())
+        } else {
+            Err(Error::ScheduleNotFound(account_id))
+        };
+    }
+
+    async fn refill_account(&self, user_id: Uuid, account_id: Uuid) -> Result<RefillResultModel> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Write).await?;
+
+        return match RefillSchedules::find_by_id(account_id).one(&self.db).await? {
+            Some(schedule) => self.refill(schedule, Some(user_id)).await,
+            None => Err(Error::ScheduleNotFound(account_id)),
+        };
+    }
+
+    async fn refill_due_accounts(&self) -> Result<u64> {
+        let schedules = RefillSchedules::find().all(&self.db).await?;
+
+        let mut refilled = 0;
+        for schedule in schedules {
+            let account_id = schedule.account_id;
+            // One failing account should not hold up the others,
+            // it is retried on the next run
+            match self.refill(schedule, None).await {
+                Ok(res) if res.refilled => refilled += 1,
+                Ok(_) => {},
+                Err(e) => error!("Could not refill account {}: {}", account_id, e),
+            }
+        }
+        Ok(refilled)
+    }
+}
+
+impl DbConnRefillService {
+    pub fn new_dyn(db: DbConn, dt_provider: DynDateTimeProvider) -> DynRefillService {
+        Arc::new(Self { db, dt_provider })
+    }
+
+    async fn refill(
+        &self,
+        schedule: refill_schedules::Model,
+        user_id: Option<Uuid>
+    ) -> Result<RefillResultModel> {
+        let now = self.dt_provider.utc_now();
+        let cadence = schedule.cadence.parse::<RefillCadence>()?;
+        let period_start = cadence.period_start(schedule.anchor, now);
+
+        let tx = self.db.begin().await?;
+
+        // Claim the current period for the account. If the account has already
+        // been refilled in this period no row is updated, and nothing is refilled
+        let claimed = RefillSchedules::update_many()
+            .filter(refill_schedules::Column::AccountId.eq(schedule.account_id))
+            .filter(
+                Condition::any()
+                    .add(refill_schedules::Column::LastRefillUtc.is_null())
+                    .add(refill_schedules::Column::LastRefillUtc.lt(period_start))
+            )
+            .col_expr(refill_schedules::Column::LastRefillUtc, Expr::value(now))
+            .exec(&tx).await?;
+
+        if claimed.rows_affected == 0 {
+            return Ok(RefillResultModel {
+                account_id: schedule.account_id,
+                period_start_utc: period_start,
+                refilled: false,
+                cats: vec![]
+            });
+        }
+
+        let cats = Categories::find()
+            .filter(categories::Column::AccountId.eq(schedule.account_id))
+            .filter(categories::Column::RefillValue.ne(0))
+            .all(&tx).await?;
+
+        // Record each refill as a transaction, so it shows in the account's history
+        let mut totals = HashMap::new();
+        let mut insertions = vec![];
+        for cat in &cats {
+            totals.insert(Some(cat.id), cat.refill_value);
+            insertions.push(
+                transactions::ActiveModel {
+                    account_id:     Set(schedule.account_id),
+                    user_id:        Set(user_id),
+                    category_id:    Set(Some(cat.id)),
+                    timestamp:      Set(now),
+                    amount:         Set(cat.refill_value),
+                    notes:          Set(Some(String::from("Refill"))),
+                    is_refill:      Set(true),
+
+                    ..Default::default()
+                }
+            );
+        }
+
+        if !insertions.is_empty() {
+            Transactions::insert_many(insertions).exec(&tx).await?;
+        }
+        adjust_category_balances(
+            &tx,
+            totals,
+            &LedgerContext { source: LedgerSource::Refill, user_id, timestamp: now }
+        ).await?;
+        tx.commit().await?;
+
+        Ok(RefillResultModel {
+            account_id: schedule.account_id,
+            period_start_utc: period_start,
+            refilled: true,
+            cats: cats.into_iter()
+                .map(|cat| RefilledCategoryModel { cat_id: cat.id, am: cat.refill_value })
+                .collect()
+        })
+    }
+}
+
+impl TryFrom<refill_schedules::Model> for RefillScheduleModel {
+    type Error = Error;
+    fn try_from(value: refill_schedules::Model) -> Result<Self> {
+        Ok(RefillScheduleModel {
+            account_id: value.account_id,
+            cadence: value.cadence.parse()?,
+            anchor: value.anchor,
+            last_refill_utc: value.last_refill_utc,
+        })
+    }
+}
@@ -0,0 +1,107 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Days, Months, TimeZone, Utc};
+use sea_orm::prelude::{DateTimeUtc, Uuid};
+use serde::{Deserialize, Serialize};
+
+use super::error::Error;
+
+///
+/// How often an account's categories are refilled.
+/// The schedule's `anchor` is the weekday (0 = Monday) for `Weekly`,
+/// and the day of the month (1 - 28) for `Monthly`.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+pub enum RefillCadence { Weekly, Monthly, }
+
+impl FromStr for RefillCadence {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Weekly" => Ok(Self::Weekly),
+            "Monthly" => Ok(Self::Monthly),
+            _ => Err(Error::CouldNotParseCadence(s.to_string())),
+        }
+    }
+}
+
+impl ToString for RefillCadence {
+    fn to_string(&self) -> String {
+        match self {
+            Self::Weekly => String::from("Weekly"),
+            Self::Monthly => String::from("Monthly"),
+        }
+    }
+}
+
+impl RefillCadence {
+    pub fn is_valid_anchor(&self, anchor: i32) -> bool {
+        match self {
+            Self::Weekly => (0..=6).contains(&anchor),
+            Self::Monthly => (1..=28).contains(&anchor),
+        }
+    }
+
+    ///
+    /// Returns the start of the refill period containing `now`
+    ///
+    pub fn period_start(&self, anchor: i32, now: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Self::Weekly => {
+                let days_back = (now.weekday().num_days_from_monday() + 7 - anchor as u32) % 7;
+                (now.date_naive() - Days::new(days_back as u64))
+                    .and_hms_opt(0, 0, 0).unwrap().and_utc()
+            },
+            Self::Monthly => {
+                let this_month = Utc.with_ymd_and_hms(now.year(), now.month(), anchor as u32, 0, 0, 0).unwrap();
+                if this_month <= now { this_month } else { this_month - Months::new(1) }
+            }
+        }
+    }
+
+    ///
+    /// Returns the start of the refill period following the one starting at `period_start`
+    ///
+    pub fn next_period_start(&self, period_start: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Self::Weekly => period_start + Days::new(7),
+            Self::Monthly => period_start + Months::new(1),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefillScheduleModel {
+    pub account_id: Uuid,
+    pub cadence: RefillCadence,
+    pub anchor: i32,
+    ///
+    /// The last time the account's categories were refilled.
+    /// `None` if the account has never been refilled.
+    ///
+    pub last_refill_utc: Option<DateTimeUtc>,
+}
+
+#[derive(Deserialize)]
+pub struct SetRefillScheduleModel {
+    pub account_id: Uuid,
+    pub cadence: RefillCadence,
+    pub anchor: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefillResultModel {
+    pub account_id: Uuid,
+    pub period_start_utc: DateTimeUtc,
+    ///
+    /// `false` if the account was already refilled for the period
+    ///
+    pub refilled: bool,
+    pub cats: Vec<RefilledCategoryModel>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RefilledCategoryModel {
+    pub cat_id: Uuid,
+    pub am: i64,
+}
@@ -0,0 +1,48 @@
+use axum::{extract::{Path, State}, routing::{delete, get, post, put}, Json, Router};
+use uuid::Uuid;
+
+use crate::{auth::middleware::AuthUser, state::AppState};
+
+use super::{error::Result, models::{RefillResultModel, RefillScheduleModel, SetRefillScheduleModel}, DynRefillService};
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route("/", put(set_schedule))
+        .route("/{account_id}", get(get_schedule))
+        .route("/{account_id}", delete(delete_schedule))
+        .route("/{account_id}/run", post(refill_account))
+        .with_state(state)
+}
+
+pub async fn get_schedule(
+    State(refill_svc): State<DynRefillService>,
+    Path(account_id): Path<Uuid>,
+    user: AuthUser,
+) -> Result<Json<RefillScheduleModel>> {
+    Ok(Json(refill_svc.get_schedule(user.id, account_id).await?))
+}
+
+pub async fn set_schedule(
+    State(refill_svc): State<DynRefillService>,
+    user: AuthUser,
+    Json(body): Json<SetRefillScheduleModel>,
+) -> Result<Json<RefillScheduleModel>> {
+    Ok(Json(refill_svc.set_schedule(user.id, body).await?))
+}
+
+pub async fn delete_schedule(
+    State(refill_svc): State<DynRefillService>,
+    Path(account_id): Path<Uuid>,
+    user: AuthUser,
+) -> Result<()> {
+    refill_svc.delete_schedule(user.id, account_id).await?;
+    Ok(())
+}
+
+pub async fn refill_account(
+    State(refill_svc): State<DynRefillService>,
+    Path(account_id): Path<Uuid>,
+    user: AuthUser,
+) -> Result<Json<RefillResultModel>> {
+    Ok(Json(refill_svc.refill_account(user.id, account_id).await?))
+}
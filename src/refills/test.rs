@@ -0,0 +1,230 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Months, TimeZone, Utc};
+use lazy_static::lazy_static;
+use sea_orm::{prelude::Uuid, sea_query::TableCreateStatement, ColumnTrait, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, QueryFilter, Schema, Set};
+
+use schmeconomics_entities::{account_users, accounts, categories, prelude::*, refill_schedules, transactions, users};
+use utils_rs::date_time_provider::MockDateTimeProvider;
+
+use crate::{db_utils::Role, refills::{models::{RefillCadence, SetRefillScheduleModel}, Error, RefillService}};
+
+use super::DbConnRefillService;
+
+lazy_static! {
+    static ref TEST_USER_1_ID: Uuid = Uuid::parse_str("be5ca263-2307-4e5a-acbd-3281fb81ea60").unwrap();
+    static ref TEST_ACCOUNT_1_ID: Uuid = Uuid::parse_str("f017369e-9dd1-4434-b197-40361cc0dbcd").unwrap();
+    static ref TEST_CAT_1_ID: Uuid = Uuid::parse_str("c8be0f8e-629e-46ce-9e76-e691caa0714b").unwrap();
+    static ref TEST_CAT_2_ID: Uuid = Uuid::parse_str("0fd2a2ce-cce1-43c4-a69d-8b1b523f0127").unwrap();
+
+    static ref TEST_CAT_1_ORIG_BAL: i64 = 1000;
+    static ref TEST_CAT_1_REFILL: i64 = 25000;
+    static ref TEST_CAT_2_ORIG_BAL: i64 = 14000;
+
+    // 2024-11-10 12:03:34
+    static ref TEST_DT: DateTime<Utc> = DateTime::<Utc>::from_timestamp_millis(1731240214000).unwrap();
+}
+
+async fn create_test_db() -> anyhow::Result<DbConn> {
+    // In-memory Sqlite connection
+    let db = Database::connect("sqlite::memory:").await?;
+
+    // Schema and Tables SeaOrm statements
+    let schema = Schema::new(DbBackend::Sqlite);
+    let user_stmt: TableCreateStatement = schema.create_table_from_entity(Users);
+    let account_stmt: TableCreateStatement = schema.create_table_from_entity(Accounts);
+    let account_user_stmt: TableCreateStatement = schema.create_table_from_entity(AccountUsers);
+    let category_stmt: TableCreateStatement = schema.create_table_from_entity(Categories);
+    let tx_stmt: TableCreateStatement = schema.create_table_from_entity(Transactions);
+    let schedule_stmt: TableCreateStatement = schema.create_table_from_entity(RefillSchedules);
+    let ledger_stmt: TableCreateStatement = schema.create_table_from_entity(CategoryLedger);
+
+    db.execute(db.get_database_backend().build(&user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&category_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_stmt)).await?;
+    db.execute(db.get_database_backend().build(&schedule_stmt)).await?;
+    db.execute(db.get_database_backend().build(&ledger_stmt)).await?;
+
+    // Insert test user
+    let new_user = users::ActiveModel {
+        id: Set(*TEST_USER_1_ID),
+        email: Set(String::from("user1@mail.com")),
+        email_verified: Set(true),
+        password_hash: Set(String::from("password")),
+        name: Set(String::from("tester 1")),
+        created_on_utc: Set(Utc::now()),
+        two_factor_enabled: Set(false),
+
+        ..Default::default()
+    };
+    Users::insert(new_user).exec(&db).await?;
+
+    // Create test account
+    let account = accounts::ActiveModel {
+        id: Set(*TEST_ACCOUNT_1_ID),
+        ..Default::default()
+    };
+    Accounts::insert(account).exec(&db).await?;
+
+    let account_user = account_users::ActiveModel {
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        user_id: Set(*TEST_USER_1_ID),
+        role: Set(Role::Admin.to_string()),
+        verified: Set(true),
+        created_on: Set(Utc::now()),
+    };
+    AccountUsers::insert(account_user).exec(&db).await?;
+
+    // Insert test categories. Only the 1st category has a refill value
+    let cat1 = categories::ActiveModel {
+        id: Set(*TEST_CAT_1_ID),
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        name: Set(String::from("Cat1")),
+        balance: Set(*TEST_CAT_1_ORIG_BAL),
+        refill_value: Set(*TEST_CAT_1_REFILL),
+        order: Set(1),
+    };
+    let cat2 = categories::ActiveModel {
+        id: Set(*TEST_CAT_2_ID),
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        name: Set(String::from("Cat2")),
+        balance: Set(*TEST_CAT_2_ORIG_BAL),
+        refill_value: Set(0),
+        order: Set(2),
+    };
+    Categories::insert_many(vec![cat1, cat2]).exec(&db).await?;
+
+    Ok(db)
+}
+
+fn create_test_service(db: &DbConn, now: DateTime<Utc>) -> DbConnRefillService {
+    let mut mock_dt_service = MockDateTimeProvider::new();
+    mock_dt_service.expect_utc_now().returning(move || now);
+
+    DbConnRefillService {
+        db: db.clone(),
+        dt_provider: Arc::new(mock_dt_service),
+    }
+}
+
+async fn set_monthly_schedule(svc: &DbConnRefillService) -> anyhow::Result<()> {
+    svc.set_schedule(
+        *TEST_USER_1_ID,
+        SetRefillScheduleModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            cadence: RefillCadence::Monthly,
+            anchor: 1,
+        }
+    ).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_refill_is_idempotent_per_period() -> anyhow::Result<()> {
+    let db = create_test_db().await?;
+    let svc = create_test_service(&db, *TEST_DT);
+    set_monthly_schedule(&svc).await?;
+
+    assert_eq!(1, svc.refill_due_accounts().await?);
+    assert_eq!(0, svc.refill_due_accounts().await?);
+
+    let res = svc.refill_account(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID).await?;
+    assert!(!res.refilled);
+    assert_eq!(Utc.with_ymd_and_hms(2024, 11, 1, 0, 0, 0).unwrap(), res.period_start_utc);
+
+    let txs = Transactions::find().all(&db).await?;
+    assert_eq!(1, txs.len());
+    assert_eq!(Some(*TEST_CAT_1_ID), txs[0].category_id);
+    assert_eq!(*TEST_CAT_1_REFILL, txs[0].amount);
+    assert_eq!(None, txs[0].user_id);
+    assert_eq!(*TEST_DT, txs[0].timestamp);
+    assert!(txs[0].is_refill);
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL + *TEST_CAT_1_REFILL, cats[0].balance);
+    assert_eq!(*TEST_CAT_2_ORIG_BAL, cats[1].balance);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_refill_next_period() -> anyhow::Result<()> {
+    let db = create_test_db().await?;
+    set_monthly_schedule(&create_test_service(&db, *TEST_DT)).await?;
+
+    create_test_service(&db, *TEST_DT).refill_due_accounts().await?;
+    create_test_service(&db, *TEST_DT + Months::new(1)).refill_due_accounts().await?;
+
+    let refills = Transactions::find()
+        .filter(transactions::Column::IsRefill.eq(true))
+        .all(&db).await?;
+    assert_eq!(2, refills.len());
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL + *TEST_CAT_1_REFILL * 2, cats[0].balance);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_refill_due_accounts_skips_failing_account() -> anyhow::Result<()> {
+    let db = create_test_db().await?;
+    let svc = create_test_service(&db, *TEST_DT);
+    set_monthly_schedule(&svc).await?;
+
+    // A schedule whose cadence cannot be parsed fails to refill
+    let other_account_id = Uuid::now_v7();
+    Accounts::insert(accounts::ActiveModel { id: Set(other_account_id), ..Default::default() }).exec(&db).await?;
+    RefillSchedules::insert(refill_schedules::ActiveModel {
+        account_id: Set(other_account_id),
+        cadence: Set(String::from("Daily")),
+        anchor: Set(1),
+        last_refill_utc: Set(None),
+    }).exec(&db).await?;
+
+    assert_eq!(1, svc.refill_due_accounts().await?);
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL + *TEST_CAT_1_REFILL, cats[0].balance);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_set_schedule_invalid_anchor() -> anyhow::Result<()> {
+    let db = create_test_db().await?;
+    let svc = create_test_service(&db, *TEST_DT);
+
+    let res = svc.set_schedule(
+        *TEST_USER_1_ID,
+        SetRefillScheduleModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            cadence: RefillCadence::Monthly,
+            anchor: 31,
+        }
+    ).await;
+
+    assert!(matches!(res, Err(Error::InvalidAnchor(_, anchor)) if anchor == 31));
+
+    Ok(())
+}
+
+#[test]
+fn test_period_start() {
+    // 2024-11-10 is a Sunday
+    assert_eq!(
+        Utc.with_ymd_and_hms(2024, 11, 4, 0, 0, 0).unwrap(),
+        RefillCadence::Weekly.period_start(0, *TEST_DT)
+    );
+    assert_eq!(
+        Utc.with_ymd_and_hms(2024, 11, 10, 0, 0, 0).unwrap(),
+        RefillCadence::Weekly.period_start(6, *TEST_DT)
+    );
+    assert_eq!(
+        Utc.with_ymd_and_hms(2024, 10, 15, 0, 0, 0).unwrap(),
+        RefillCadence::Monthly.period_start(15, *TEST_DT)
+    );
+}
@@ -0,0 +1,36 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use log::error;
+use sea_orm::{prelude::DateTimeUtc, DbErr};
+use thiserror::Error;
+
+use crate::{db_utils::DbUtilsError, response::internal_server_error_response};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("An error occurred while connecting to the database: {0}")]
+    DbErr(#[from] DbErr),
+    #[error(transparent)]
+    DbUtilsError(#[from] DbUtilsError),
+    #[error(transparent)]
+    RefillError(#[from] crate::refills::error::Error),
+    #[error("Report start {0} must be before its end {1}")]
+    InvalidRange(DateTimeUtc, DateTimeUtc),
+    #[error("Report start and end must both be set, or both be left out")]
+    PartialRange,
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> axum::response::Response {
+        return match self {
+            Error::DbErr(_) | Error::DbUtilsError(_) | Error::RefillError(_) => { 
+                error!("{}", self);
+                internal_server_error_response()
+            },
+            Error::InvalidRange(_, _) | Error::PartialRange => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+        };
+    }
+}
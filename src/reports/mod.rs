@@ -0,0 +1,246 @@
+use std::{collections::{BTreeMap, HashMap}, sync::Arc};
+
+use async_trait::async_trait;
+use schmeconomics_entities::{categories, prelude::*, transaction_splits, transactions};
+use sea_orm::{prelude::{DateTimeUtc, Expr, Uuid}, sea_query::{Func, JoinType, SimpleExpr}, ColumnTrait, Condition, DbConn, EntityTrait, FromQueryResult, QueryFilter, QueryOrder, QuerySelect, QueryTrait};
+use utils_rs::date_time_provider::DynDateTimeProvider;
+
+use crate::{db_utils::{validate_user_account_role, Role}, refills::models::RefillCadence};
+
+use {error::*, models::*};
+
+pub mod error;
+pub mod models;
+pub mod routes;
+
+#[cfg(test)]
+mod test;
+
+pub type DynReportService = Arc<dyn ReportService + Send + Sync>;
+
+#[async_trait]
+pub trait ReportService {
+    ///
+    /// Totals the account's spending and refills per category, aggregated in the database
+    /// 
+    async fn get_spending_report(&self, user_id: Uuid, report_req: SpendingReportReqModel) -> Result<SpendingReportModel>;
+    ///
+    /// Compares each category's spending with its budget, where a category is budgeted
+    /// its refill value for every refill period the report covers. Periods the report only
+    /// partly covers budget the same share of the refill value.
+    /// 
+    async fn get_budget_report(&self, user_id: Uuid, report_req: BudgetReportReqModel) -> Result<BudgetReportModel>;
+}
+
+pub struct DbConnReportService {
+    db: DbConn,
+    dt_provider: DynDateTimeProvider,
+}
+
+#[derive(FromQueryResult)]
+struct SpendingQuery {
+    month: Option<String>,
+    category_id: Option<Uuid>,
+    spent: Option<i64>,
+    refilled: Option<i64>,
+    tx_count: i64,
+}
+
+#[async_trait]
+impl ReportService for DbConnReportService {
+    async fn get_spending_report(&self, user_id: Uuid, report_req: SpendingReportReqModel) -> Result<SpendingReportModel> {
+        validate_user_account_role(&self.db, user_id, report_req.account_id, Role::Read).await?;
+
+        if report_req.start_utc >= report_req.end_utc {
+            return Err(Error::InvalidRange(report_req.start_utc, report_req.end_utc));
+        }
+
+        let rows = self.category_totals(report_req.account_id, report_req.start_utc, report_req.end_utc, report_req.grouping).await?;
+
+        Ok(SpendingReportModel {
+            account_id: report_req.account_id,
+            start_utc: report_req.start_utc,
+            end_utc: report_req.end_utc,
+            grouping: report_req.grouping,
+            rows,
+        })
+    }
+
+    async fn get_budget_report(&self, user_id: Uuid, report_req: BudgetReportReqModel) -> Result<BudgetReportModel> {
+        validate_user_account_role(&self.db, user_id, report_req.account_id, Role::Read).await?;
+
+        // Accounts without a refill schedule are budgeted by calendar month
+        let (cadence, anchor) = match RefillSchedules::find_by_id(report_req.account_id).one(&self.db).await? {
+            Some(schedule) => (schedule.cadence.parse::<RefillCadence>()?, schedule.anchor),
+            None => (RefillCadence::Monthly, 1),
+        };
+
+        let (start_utc, end_utc) = match (report_req.start_utc, report_req.end_utc) {
+            (Some(start_utc), Some(end_utc)) => (start_utc, end_utc),
+            (None, None) => {
+                let start_utc = cadence.period_start(anchor, self.dt_provider.utc_now());
+                (start_utc, cadence.next_period_start(start_utc))
+            },
+            _ => return Err(Error::PartialRange),
+        };
+        if start_utc >= end_utc {
+            return Err(Error::InvalidRange(start_utc, end_utc));
+        }
+
+        let mut periods = 0.0;
+        let mut period_start = cadence.period_start(anchor, start_utc);
+        while period_start < end_utc {
+            let period_end = cadence.next_period_start(period_start);
+            let covered = period_end.min(end_utc) - period_start.max(start_utc);
+            periods += covered.num_seconds() as f64 / (period_end - period_start).num_seconds() as f64;
+            period_start = period_end;
+        }
+
+        let cats = Categories::find()
+            .filter(categories::Column::AccountId.eq(report_req.account_id))
+            .order_by_asc(categories::Column::Order)
+            .all(&self.db).await?;
+
+        // Spending is reported as a positive amount, net of any refunds
+        let mut spent = HashMap::new();
+        for row in self.category_totals(report_req.account_id, start_utc, end_utc, ReportGrouping::Range).await? {
+            spent.insert(row.cat_id, -row.spent);
+        }
+
+        let cats = cats.into_iter()
+            .map(|cat| CategoryBudgetModel {
+                cat_id: cat.id,
+                name: cat.name,
+                budget: BudgetLineModel::new((cat.refill_value as f64 * periods).round() as i64, spent.get(&Some(cat.id)).copied().unwrap_or(0)),
+            })
+            .collect::<Vec<_>>();
+        let totals = BudgetLineModel::new(
+            cats.iter().map(|cat| cat.budget.budgeted).sum(),
+            cats.iter().map(|cat| cat.budget.spent).sum(),
+        );
+
+        Ok(BudgetReportModel {
+            account_id: report_req.account_id,
+            start_utc,
+            end_utc,
+            periods,
+            cats,
+            uncategorized_spent: spent.get(&None).copied().unwrap_or(0),
+            totals,
+        })
+    }
+}
+
+impl DbConnReportService {
+    pub fn new_dyn(db: DbConn, dt_provider: DynDateTimeProvider) -> DynReportService {
+        Arc::new(DbConnReportService { db, dt_provider })
+    }
+
+    ///
+    /// Totals the spending and refills of each category from `start_utc` up to, but not including, `end_utc`
+    /// 
+    async fn category_totals(
+        &self,
+        account_id: Uuid,
+        start_utc: DateTimeUtc,
+        end_utc: DateTimeUtc,
+        grouping: ReportGrouping,
+    ) -> Result<Vec<CategorySpendingModel>> {
+        let month = month_expr(grouping);
+        let reported = Condition::all()
+            .add(transactions::Column::AccountId.eq(account_id))
+            .add(transactions::Column::DeleteOn.is_null())
+            .add(transactions::Column::TransferId.is_null())
+            .add(transactions::Column::Timestamp.gte(start_utc))
+            .add(transactions::Column::Timestamp.lt(end_utc));
+
+        // Split transactions are totalled from their splits instead
+        let mut tx_query = Transactions::find()
+            .filter(reported.clone())
+            .filter(
+                transactions::Column::Id.not_in_subquery(
+                    TransactionSplits::find()
+                        .select_only()
+                        .column(transaction_splits::Column::TransactionId)
+                        .into_query()
+                )
+            )
+            .select_only()
+            .column_as(month.clone(), "month")
+            .column(transactions::Column::CategoryId)
+            .column_as(refill_amount_expr(false).sum(), "spent")
+            .column_as(refill_amount_expr(true).sum(), "refilled")
+            .column_as(transactions::Column::Id.count(), "tx_count")
+            .group_by(transactions::Column::CategoryId);
+
+        let mut split_query = TransactionSplits::find()
+            .join(
+                JoinType::InnerJoin,
+                transaction_splits::Entity::belongs_to(transactions::Entity)
+                    .from(transaction_splits::Column::TransactionId)
+                    .to(transactions::Column::Id)
+                    .into()
+            )
+            .filter(reported)
+            .select_only()
+            .column_as(month.clone(), "month")
+            .column(transaction_splits::Column::CategoryId)
+            .column_as(transaction_splits::Column::Amount.sum(), "spent")
+            .column_as(Expr::value(0), "refilled")
+            // A transaction split more than once into the same category is still counted once
+            .column_as(
+                SimpleExpr::from(Func::count_distinct(Expr::col((TransactionSplits, transaction_splits::Column::TransactionId)))),
+                "tx_count"
+            )
+            .group_by(transaction_splits::Column::CategoryId);
+
+        if grouping == ReportGrouping::Month {
+            tx_query = tx_query.group_by(month.clone());
+            split_query = split_query.group_by(month);
+        }
+
+        let tx_totals = tx_query.into_model::<SpendingQuery>().all(&self.db).await?;
+        let split_totals = split_query.into_model::<SpendingQuery>().all(&self.db).await?;
+
+        // A category can have both whole and split transactions in the same period
+        let mut rows = BTreeMap::new();
+        for total in tx_totals.into_iter().chain(split_totals) {
+            let row = rows.entry((total.month.clone(), total.category_id))
+                .or_insert(CategorySpendingModel {
+                    month: total.month,
+                    cat_id: total.category_id,
+                    spent: 0,
+                    refilled: 0,
+                    tx_count: 0,
+                });
+            row.spent += total.spent.unwrap_or(0);
+            row.refilled += total.refilled.unwrap_or(0);
+            row.tx_count += total.tx_count;
+        }
+
+        Ok(rows.into_values().collect())
+    }
+}
+
+///
+/// The calendar month of the transaction's timestamp, or NULL when the report is not grouped by month
+/// 
+fn month_expr(grouping: ReportGrouping) -> SimpleExpr {
+    match grouping {
+        ReportGrouping::Month => Expr::cust_with_expr(
+            "strftime('%Y-%m', $1)",
+            Expr::col((Transactions, transactions::Column::Timestamp))
+        ),
+        ReportGrouping::Range => Expr::cust("NULL"),
+    }
+}
+
+///
+/// The transaction's amount when its `is_refill` matches, otherwise 0
+/// 
+fn refill_amount_expr(is_refill: bool) -> Expr {
+    Expr::expr(
+        Expr::case(transactions::Column::IsRefill.eq(is_refill), Expr::col((Transactions, transactions::Column::Amount)))
+            .finally(0)
+    )
+}
@@ -0,0 +1,122 @@
+use sea_orm::prelude::{DateTimeUtc, Uuid};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub enum ReportGrouping {
+    ///
+    /// One row per category per calendar month, in UTC
+    /// 
+    #[default]
+    Month,
+    ///
+    /// One row per category covering the whole range
+    /// 
+    Range,
+}
+
+///
+/// Aggregates the account's transactions dated from `start_utc` up to, but not including, `end_utc`
+/// 
+#[derive(Deserialize)]
+pub struct SpendingReportReqModel {
+    pub account_id: Uuid,
+    pub start_utc: DateTimeUtc,
+    pub end_utc: DateTimeUtc,
+    #[serde(default)]
+    pub grouping: ReportGrouping,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategorySpendingModel {
+    ///
+    /// The calendar month formatted as `YYYY-MM`, or `None` when grouped by range
+    /// 
+    pub month: Option<String>,
+    ///
+    /// `None` for uncategorized transactions
+    /// 
+    pub cat_id: Option<Uuid>,
+    ///
+    /// Total of the category's transactions other than refills, negative when money was spent
+    /// 
+    pub spent: i64,
+    pub refilled: i64,
+    pub tx_count: i64,
+}
+
+///
+/// Transfers only move money between categories, so they are left out of the report.
+/// Split transactions count towards each of their split categories.
+/// 
+#[derive(Debug, Serialize)]
+pub struct SpendingReportModel {
+    pub account_id: Uuid,
+    pub start_utc: DateTimeUtc,
+    pub end_utc: DateTimeUtc,
+    pub grouping: ReportGrouping,
+    pub rows: Vec<CategorySpendingModel>,
+}
+
+///
+/// Reports on the period from `start_utc` up to, but not including, `end_utc`.
+/// When both are left out the account's current refill period is reported.
+/// 
+#[derive(Deserialize)]
+pub struct BudgetReportReqModel {
+    pub account_id: Uuid,
+    pub start_utc: Option<DateTimeUtc>,
+    pub end_utc: Option<DateTimeUtc>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BudgetLineModel {
+    pub budgeted: i64,
+    ///
+    /// Spending net of refunds, positive when money was spent
+    /// 
+    pub spent: i64,
+    pub remaining: i64,
+    ///
+    /// `None` when nothing was budgeted
+    /// 
+    pub percent_used: Option<f64>,
+    pub over_budget: bool,
+}
+
+impl BudgetLineModel {
+    pub fn new(budgeted: i64, spent: i64) -> Self {
+        Self {
+            budgeted,
+            spent,
+            remaining: budgeted - spent,
+            percent_used: (budgeted != 0).then(|| spent as f64 / budgeted as f64 * 100.0),
+            over_budget: spent > budgeted,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategoryBudgetModel {
+    pub cat_id: Uuid,
+    pub name: String,
+    #[serde(flatten)]
+    pub budget: BudgetLineModel,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BudgetReportModel {
+    pub account_id: Uuid,
+    pub start_utc: DateTimeUtc,
+    pub end_utc: DateTimeUtc,
+    ///
+    /// The number of refill periods the report covers, each budgeting a category its refill value.
+    /// Fractional when the report starts or ends part way through a period.
+    /// 
+    pub periods: f64,
+    pub cats: Vec<CategoryBudgetModel>,
+    ///
+    /// Spending without a category, which no budget covers and is left out of the totals
+    /// 
+    pub uncategorized_spent: i64,
+    pub totals: BudgetLineModel,
+}
@@ -0,0 +1,28 @@
+use axum::{extract::State, routing::post, Json, Router};
+
+use crate::{auth::middleware::AuthUser, state::AppState};
+
+use super::{error::Result, models::{BudgetReportModel, BudgetReportReqModel, SpendingReportModel, SpendingReportReqModel}, DynReportService};
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route("/spending", post(get_spending_report))
+        .route("/budget", post(get_budget_report))
+        .with_state(state)
+}
+
+pub async fn get_spending_report(
+    State(report_svc): State<DynReportService>,
+    user: AuthUser,
+    Json(body): Json<SpendingReportReqModel>,
+) -> Result<Json<SpendingReportModel>> {
+    Ok(Json(report_svc.get_spending_report(user.id, body).await?))
+}
+
+pub async fn get_budget_report(
+    State(report_svc): State<DynReportService>,
+    user: AuthUser,
+    Json(body): Json<BudgetReportReqModel>,
+) -> Result<Json<BudgetReportModel>> {
+    Ok(Json(report_svc.get_budget_report(user.id, body).await?))
+}
@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use lazy_static::lazy_static;
+use sea_orm::{sea_query::TableCreateStatement, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, Schema, Set};
+use uuid::Uuid;
+
+use schmeconomics_entities::{account_users, accounts, categories, prelude::*, refill_schedules, transaction_splits, transactions, users};
+use utils_rs::date_time_provider::MockDateTimeProvider;
+
+use crate::{db_utils::Role, refills::models::RefillCadence, reports::{models::{BudgetReportReqModel, ReportGrouping, SpendingReportReqModel}, Error, ReportService}};
+
+use super::DbConnReportService;
+
+lazy_static! {
+    static ref TEST_USER_1_ID: Uuid = Uuid::parse_str("be5ca263-2307-4e5a-acbd-3281fb81ea60").unwrap();
+    static ref TEST_ACCOUNT_1_ID: Uuid = Uuid::parse_str("f017369e-9dd1-4434-b197-40361cc0dbcd").unwrap();
+    static ref TEST_CAT_1_ID: Uuid = Uuid::parse_str("0c7a5b1e-3f0e-4a52-9f4b-7b1f5a0e6d21").unwrap();
+    static ref TEST_CAT_2_ID: Uuid = Uuid::parse_str("6d2f3c4a-8b7e-4e1d-a5c9-2e8f1b3d4c56").unwrap();
+
+    // 2024-11-10 12:03:34
+    static ref TEST_DT: DateTime<Utc> = DateTime::<Utc>::from_timestamp_millis(1731240214000).unwrap();
+    // 2024-11-01 00:00:00
+    static ref TEST_NOV_START: DateTime<Utc> = DateTime::<Utc>::from_timestamp_millis(1730419200000).unwrap();
+}
+
+async fn create_test_db() -> anyhow::Result<DbConn> {
+    // In-memory Sqlite connection
+    let db = Database::connect("sqlite::memory:").await?;
+
+    // Schema and Tables SeaOrm statements
+    let schema = Schema::new(DbBackend::Sqlite);
+    let user_stmt: TableCreateStatement = schema.create_table_from_entity(Users);
+    let account_stmt: TableCreateStatement = schema.create_table_from_entity(Accounts);
+    let account_user_stmt: TableCreateStatement = schema.create_table_from_entity(AccountUsers);
+    let category_stmt: TableCreateStatement = schema.create_table_from_entity(Categories);
+    let tx_stmt: TableCreateStatement = schema.create_table_from_entity(Transactions);
+    let split_stmt: TableCreateStatement = schema.create_table_from_entity(TransactionSplits);
+    let schedule_stmt: TableCreateStatement = schema.create_table_from_entity(RefillSchedules);
+
+    db.execute(db.get_database_backend().build(&user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&category_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_stmt)).await?;
+    db.execute(db.get_database_backend().build(&split_stmt)).await?;
+    db.execute(db.get_database_backend().build(&schedule_stmt)).await?;
+
+    // Insert test user
+    let new_user = users::ActiveModel {
+        id: Set(*TEST_USER_1_ID),
+        email: Set(String::from("user1@mail.com")),
+        email_verified: Set(true),
+        password_hash: Set(String::from("password")),
+        name: Set(String::from("tester 1")),
+        created_on_utc: Set(Utc::now()),
+        two_factor_enabled: Set(false),
+
+        ..Default::default()
+    };
+    Users::insert(new_user).exec(&db).await?;
+
+    // Create test account
+    let account = accounts::ActiveModel {
+        id: Set(*TEST_ACCOUNT_1_ID),
+        ..Default::default()
+    };
+    Accounts::insert(account).exec(&db).await?;
+
+    let account_user = account_users::ActiveModel {
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        user_id: Set(*TEST_USER_1_ID),
+        role: Set(Role::Read.to_string()),
+        verified: Set(true),
+        created_on: Set(Utc::now()),
+    };
+    AccountUsers::insert(account_user).exec(&db).await?;
+
+    let cats = [(*TEST_CAT_1_ID, "Groceries", 3000, 1), (*TEST_CAT_2_ID, "Dining", 0, 2)].map(|(id, name, refill_value, order)| categories::ActiveModel {
+        id: Set(id),
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        name: Set(String::from(name)),
+        balance: Set(0),
+        refill_value: Set(refill_value),
+        order: Set(order),
+    });
+    Categories::insert_many(cats).exec(&db).await?;
+
+    Ok(db)
+}
+
+async fn create_test_service() -> anyhow::Result<(DbConnReportService, DbConn)> {
+    let db = create_test_db().await?;
+
+    let mut mock_dt_service = MockDateTimeProvider::new();
+    mock_dt_service.expect_utc_now().returning(|| *TEST_DT);
+
+    Ok((DbConnReportService { db: db.clone(), dt_provider: Arc::new(mock_dt_service) }, db))
+}
+
+async fn insert_tx(db: &DbConn, cat_id: Option<Uuid>, timestamp: DateTime<Utc>, amount: i64, model_fn: impl FnOnce(&mut transactions::ActiveModel)) -> anyhow::Result<i32> {
+    let mut tx = transactions::ActiveModel {
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        category_id: Set(cat_id),
+        timestamp: Set(timestamp),
+        amount: Set(amount),
+        is_refill: Set(false),
+
+        ..Default::default()
+    };
+    model_fn(&mut tx);
+    Ok(Transactions::insert(tx).exec(db).await?.last_insert_id)
+}
+
+#[tokio::test]
+async fn test_spending_report() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+    let oct = *TEST_NOV_START - Duration::days(5);
+
+    insert_tx(&db, Some(*TEST_CAT_1_ID), oct, -1000, |_| {}).await?;
+    insert_tx(&db, Some(*TEST_CAT_1_ID), *TEST_DT, -2500, |_| {}).await?;
+    insert_tx(&db, Some(*TEST_CAT_1_ID), *TEST_NOV_START, 5000, |tx| tx.is_refill = Set(true)).await?;
+    insert_tx(&db, None, *TEST_DT, -300, |_| {}).await?;
+
+    // Split transactions count once towards each split category, even when split into it more than once
+    let split_tx_id = insert_tx(&db, None, *TEST_DT, -900, |_| {}).await?;
+    TransactionSplits::insert_many([(*TEST_CAT_1_ID, -150), (*TEST_CAT_1_ID, -250), (*TEST_CAT_2_ID, -500)].map(|(cat_id, am)| transaction_splits::ActiveModel {
+        transaction_id: Set(split_tx_id),
+        category_id: Set(cat_id),
+        amount: Set(am),
+
+        ..Default::default()
+    })).exec(&db).await?;
+
+    // Transfers and deleted transactions are left out
+    insert_tx(&db, Some(*TEST_CAT_2_ID), *TEST_DT, -700, |tx| tx.transfer_id = Set(Some(Uuid::now_v7()))).await?;
+    insert_tx(&db, Some(*TEST_CAT_2_ID), *TEST_DT, -800, |tx| tx.delete_on = Set(Some(*TEST_DT))).await?;
+
+    let report_req = |grouping| SpendingReportReqModel {
+        account_id: *TEST_ACCOUNT_1_ID,
+        start_utc: oct - Duration::days(1),
+        end_utc: *TEST_DT + Duration::days(1),
+        grouping,
+    };
+
+    let report = svc.get_spending_report(*TEST_USER_1_ID, report_req(ReportGrouping::Month)).await?;
+    let mut rows = report.rows.iter()
+        .map(|row| (row.month.as_deref(), row.cat_id, row.spent, row.refilled, row.tx_count))
+        .collect::<Vec<_>>();
+    rows.sort();
+    let mut expected = vec![
+        (Some("2024-10"), Some(*TEST_CAT_1_ID), -1000, 0, 1),
+        (Some("2024-11"), None, -300, 0, 1),
+        (Some("2024-11"), Some(*TEST_CAT_1_ID), -2900, 5000, 3),
+        (Some("2024-11"), Some(*TEST_CAT_2_ID), -500, 0, 1),
+    ];
+    expected.sort();
+    assert_eq!(expected, rows);
+
+    let report = svc.get_spending_report(*TEST_USER_1_ID, report_req(ReportGrouping::Range)).await?;
+    let cat1 = report.rows.iter().find(|row| row.cat_id == Some(*TEST_CAT_1_ID)).unwrap();
+    assert_eq!((None, -3900, 5000, 4), (cat1.month.as_deref(), cat1.spent, cat1.refilled, cat1.tx_count));
+    assert_eq!(3, report.rows.len());
+
+    // The end of the range is excluded
+    let report = svc.get_spending_report(
+        *TEST_USER_1_ID,
+        SpendingReportReqModel { end_utc: *TEST_NOV_START, ..report_req(ReportGrouping::Month) }
+    ).await?;
+    assert_eq!(1, report.rows.len());
+
+    let res = svc.get_spending_report(
+        *TEST_USER_1_ID,
+        SpendingReportReqModel { end_utc: oct - Duration::days(1), ..report_req(ReportGrouping::Month) }
+    ).await;
+    assert!(matches!(res, Err(Error::InvalidRange(_, _))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_budget_report() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    insert_tx(&db, Some(*TEST_CAT_1_ID), *TEST_DT, -2500, |_| {}).await?;
+    insert_tx(&db, Some(*TEST_CAT_1_ID), *TEST_DT, -400, |_| {}).await?;
+    insert_tx(&db, Some(*TEST_CAT_1_ID), *TEST_NOV_START, 3000, |tx| tx.is_refill = Set(true)).await?;
+    insert_tx(&db, Some(*TEST_CAT_2_ID), *TEST_DT, -500, |_| {}).await?;
+    insert_tx(&db, None, *TEST_DT, -300, |_| {}).await?;
+    // Before the current period
+    insert_tx(&db, Some(*TEST_CAT_1_ID), *TEST_NOV_START - Duration::days(1), -9000, |_| {}).await?;
+
+    // Without a refill schedule the current calendar month is reported
+    let report = svc.get_budget_report(
+        *TEST_USER_1_ID,
+        BudgetReportReqModel { account_id: *TEST_ACCOUNT_1_ID, start_utc: None, end_utc: None }
+    ).await?;
+    assert_eq!((*TEST_NOV_START, 1.0), (report.start_utc, report.periods));
+
+    let cat1 = &report.cats[0].budget;
+    assert_eq!((3000, 2900, 100, false), (cat1.budgeted, cat1.spent, cat1.remaining, cat1.over_budget));
+    assert!((cat1.percent_used.unwrap() - 96.67).abs() < 0.01);
+
+    // Nothing is budgeted for the category, so any spending is over budget
+    let cat2 = &report.cats[1].budget;
+    assert_eq!((0, 500, -500, None, true), (cat2.budgeted, cat2.spent, cat2.remaining, cat2.percent_used, cat2.over_budget));
+
+    assert_eq!(300, report.uncategorized_spent);
+    assert_eq!((3000, 3400, -400, true), (report.totals.budgeted, report.totals.spent, report.totals.remaining, report.totals.over_budget));
+
+    // A weekly schedule budgets each week the range covers, and a share of the weeks it partly covers
+    RefillSchedules::insert(refill_schedules::ActiveModel {
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        cadence: Set(RefillCadence::Weekly.to_string()),
+        anchor: Set(0),
+        last_refill_utc: Set(None),
+    }).exec(&db).await?;
+
+    let report = svc.get_budget_report(
+        *TEST_USER_1_ID,
+        BudgetReportReqModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            start_utc: Some(*TEST_NOV_START),
+            end_utc: Some(*TEST_NOV_START + Duration::days(30)),
+        }
+    ).await?;
+    // Three days of the first week, three full weeks, then six days of the last
+    assert!((report.periods - 30.0 / 7.0).abs() < 1e-9);
+    assert_eq!((12857, 2900), (report.cats[0].budget.budgeted, report.cats[0].budget.spent));
+
+    let res = svc.get_budget_report(
+        *TEST_USER_1_ID,
+        BudgetReportReqModel { account_id: *TEST_ACCOUNT_1_ID, start_utc: Some(*TEST_NOV_START), end_utc: None }
+    ).await;
+    assert!(matches!(res, Err(Error::PartialRange)));
+
+    Ok(())
+}
@@ -0,0 +1,168 @@
+use std::collections::HashSet;
+
+use log::warn;
+use regex::{Regex, RegexBuilder};
+use sea_orm::{prelude::Uuid, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, QueryFilter, QueryOrder};
+
+use schmeconomics_entities::{categories, categorization_rules, prelude::*, tags};
+
+///
+/// Largest compiled size of a rule's regex, so a single rule cannot slow down every insert
+/// 
+const REGEX_SIZE_LIMIT: usize = 1 << 16;
+
+///
+/// Compiles a rule pattern. Patterns are case-insensitive, and match anywhere in the text
+/// 
+pub fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+///
+/// The parts of a transaction rules are matched against
+/// 
+pub struct RuleInput<'a> {
+    pub notes: Option<&'a str>,
+    pub payee_name: Option<&'a str>,
+    ///
+    /// Amount in `currency_type`, before conversion to USD
+    /// 
+    pub amount: i64,
+    pub currency_type: &'a str,
+}
+
+///
+/// Changes the matching rules make to a transaction
+/// 
+#[derive(Debug, Default, PartialEq)]
+pub struct RuleOutcome {
+    pub rule_ids: Vec<Uuid>,
+    pub category_id: Option<Uuid>,
+    pub tag_ids: Vec<Uuid>,
+    pub notes: Option<String>,
+}
+
+struct CompiledRule {
+    id: Uuid,
+    notes_pattern: Option<Regex>,
+    payee_pattern: Option<Regex>,
+    min_amount: Option<i64>,
+    max_amount: Option<i64>,
+    currency_type: Option<String>,
+    set_category_id: Option<Uuid>,
+    add_tag_ids: Vec<Uuid>,
+    set_notes: Option<String>,
+}
+
+impl CompiledRule {
+    fn matches(&self, input: &RuleInput) -> bool {
+        let text_matches = |pattern: &Option<Regex>, text: Option<&str>| match pattern {
+            Some(pattern) => text.is_some_and(|text| pattern.is_match(text)),
+            None => true,
+        };
+
+        text_matches(&self.notes_pattern, input.notes)
+            && text_matches(&self.payee_pattern, input.payee_name)
+            && self.min_amount.is_none_or(|min| input.amount >= min)
+            && self.max_amount.is_none_or(|max| input.amount <= max)
+            && self.currency_type.as_ref().is_none_or(|currency_type| currency_type.eq_ignore_ascii_case(input.currency_type))
+    }
+}
+
+///
+/// An account's rules, in priority order
+/// 
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleSet {
+    ///
+    /// Loads and compiles the account's rules. Categories and tags deleted since
+    /// a rule was saved are dropped from its actions.
+    /// 
+    pub async fn load(db: &impl ConnectionTrait, account_id: Uuid) -> Result<RuleSet, DbErr> {
+        let models = CategorizationRules::find()
+            .filter(categorization_rules::Column::AccountId.eq(account_id))
+            .order_by_asc(categorization_rules::Column::Priority)
+            .order_by_asc(categorization_rules::Column::Id)
+            .all(db).await?;
+        if models.is_empty() {
+            return Ok(RuleSet { rules: vec![] });
+        }
+
+        let cat_ids = Categories::find()
+            .filter(categories::Column::AccountId.eq(account_id))
+            .all(db).await?
+            .into_iter()
+            .map(|cat| cat.id)
+            .collect::<HashSet<Uuid>>();
+        let tag_ids = Tags::find()
+            .filter(tags::Column::AccountId.eq(account_id))
+            .all(db).await?
+            .into_iter()
+            .map(|tag| tag.id)
+            .collect::<HashSet<Uuid>>();
+
+        let mut rules = vec![];
+        for model in models {
+            // Patterns are validated when saved, so this only fails if the limits change
+            let compile = |pattern: &Option<String>| pattern.as_deref().map(compile_pattern).transpose();
+            let (notes_pattern, payee_pattern) = match (compile(&model.notes_pattern), compile(&model.payee_pattern)) {
+                (Ok(notes_pattern), Ok(payee_pattern)) => (notes_pattern, payee_pattern),
+                (Err(e), _) | (_, Err(e)) => {
+                    warn!("Skipping categorization rule {}: {}", model.id, e);
+                    continue;
+                },
+            };
+
+            rules.push(CompiledRule {
+                id: model.id,
+                notes_pattern,
+                payee_pattern,
+                min_amount: model.min_amount,
+                max_amount: model.max_amount,
+                currency_type: model.currency_type,
+                set_category_id: model.set_category_id.filter(|cat_id| cat_ids.contains(cat_id)),
+                add_tag_ids: serde_json::from_str::<Vec<Uuid>>(&model.add_tag_ids)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|tag_id| tag_ids.contains(tag_id))
+                    .collect(),
+                set_notes: model.set_notes,
+            });
+        }
+
+        Ok(RuleSet { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    ///
+    /// Evaluates every rule in priority order. The first matching rule to set the
+    /// category or notes wins, while the tags of every matching rule are added.
+    /// 
+    pub fn evaluate(&self, input: &RuleInput) -> RuleOutcome {
+        let mut outcome = RuleOutcome::default();
+        for rule in self.rules.iter().filter(|rule| rule.matches(input)) {
+            outcome.rule_ids.push(rule.id);
+            if outcome.category_id.is_none() {
+                outcome.category_id = rule.set_category_id;
+            }
+            if outcome.notes.is_none() {
+                outcome.notes = rule.set_notes.clone();
+            }
+            for tag_id in &rule.add_tag_ids {
+                if !outcome.tag_ids.contains(tag_id) {
+                    outcome.tag_ids.push(*tag_id);
+                }
+            }
+        }
+        outcome
+    }
+}
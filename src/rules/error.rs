@@ -0,0 +1,42 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use log::error;
+use sea_orm::DbErr;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{db_utils::DbUtilsError, response::internal_server_error_response};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("An error occurred while connecting to the database: {0}")]
+    DbErr(#[from] DbErr),
+    #[error(transparent)]
+    DbUtilsError(#[from] DbUtilsError),
+    #[error("Could not (de)serialize JSON: {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error("Invalid rule: {0}")]
+    InvalidRule(String),
+    #[error("Rule with ID '{0}' not found")]
+    RuleNotFound(Uuid),
+    #[error("Category with ID '{0}' not found in account")]
+    CategoryNotFound(Uuid),
+    #[error("Tag with ID '{0}' not found in account")]
+    TagNotFound(Uuid),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> axum::response::Response {
+        return match self {
+            Error::DbErr(_) | Error::DbUtilsError(_) | Error::SerdeJsonError(_) => { 
+                error!("{}", self);
+                internal_server_error_response()
+            },
+            Error::InvalidRule(_) | Error::RuleNotFound(_) |
+            Error::CategoryNotFound(_) | Error::TagNotFound(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+        };
+    }
+}
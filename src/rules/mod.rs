@@ -0,0 +1,328 @@
+use std::{collections::{HashMap, HashSet}, sync::Arc};
+
+use async_trait::async_trait;
+use schmeconomics_entities::{categories, categorization_rules, payees, prelude::*, tags, transaction_splits, transaction_tags, transactions};
+use sea_orm::{prelude::Uuid, ColumnTrait, Condition, DbConn, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, Set, TransactionTrait};
+use utils_rs::date_time_provider::DynDateTimeProvider;
+
+use crate::{currency_conv_provider::USD_CURRENCY_TYPE, db_utils::{adjust_category_balances, validate_user_account_role, LedgerContext, LedgerSource, Role}, transactions::models::ToCondition};
+
+use {engine::*, error::*, models::*};
+
+pub mod engine;
+pub mod error;
+pub mod models;
+pub mod routes;
+
+#[cfg(test)]
+mod test;
+
+pub type DynRuleService = Arc<dyn RuleService + Send + Sync>;
+
+///
+/// Most transaction IDs bound in a single query, keeping well under SQLite's bound variable limit
+/// 
+const ID_CHUNK_SIZE: usize = 500;
+
+///
+/// Per-account rules which categorize, tag and rename transactions as they are created or imported
+/// 
+#[async_trait]
+pub trait RuleService {
+    async fn get_rules(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<RuleModel>>;
+    async fn create_rule(&self, user_id: Uuid, rule: CreateRuleModel) -> Result<RuleModel>;
+    async fn update_rule(&self, user_id: Uuid, rule: UpdateRuleModel) -> Result<RuleModel>;
+    async fn delete_rule(&self, user_id: Uuid, rule: DeleteRuleModel) -> Result<()>;
+    ///
+    /// Evaluates the rules over existing transactions, returning the changes they make.
+    /// When `dry_run` is set nothing is changed, so the changes can be reviewed first.
+    /// 
+    async fn run_rules(&self, user_id: Uuid, run_req: RunRulesModel, dry_run: bool) -> Result<Vec<RuleChangeModel>>;
+}
+
+pub struct DbConnRuleService {
+    db: DbConn,
+    dt_provider: DynDateTimeProvider,
+}
+
+#[async_trait]
+impl RuleService for DbConnRuleService {
+    async fn get_rules(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<RuleModel>> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Read).await?;
+
+        CategorizationRules::find()
+            .filter(categorization_rules::Column::AccountId.eq(account_id))
+            .order_by_asc(categorization_rules::Column::Priority)
+            .order_by_asc(categorization_rules::Column::Id)
+            .all(&self.db).await?
+            .into_iter()
+            .map(RuleModel::try_from)
+            .collect()
+    }
+
+    async fn create_rule(&self, user_id: Uuid, create_rule: CreateRuleModel) -> Result<RuleModel> {
+        validate_user_account_role(&self.db, user_id, create_rule.account_id, Role::Write).await?;
+
+        let def = self.validate_rule(create_rule.account_id, create_rule.def).await?;
+        let new_rule = categorization_rules::ActiveModel {
+            id:         Set(Uuid::now_v7()),
+            account_id: Set(create_rule.account_id),
+            ..to_active_model(&def)?
+        };
+        let rule = CategorizationRules::insert(new_rule).exec_with_returning(&self.db).await?;
+
+        RuleModel::try_from(rule)
+    }
+
+    async fn update_rule(&self, user_id: Uuid, update_rule: UpdateRuleModel) -> Result<RuleModel> {
+        validate_user_account_role(&self.db, user_id, update_rule.account_id, Role::Write).await?;
+
+        let ex_rule = CategorizationRules::find_by_id(update_rule.id)
+            .filter(categorization_rules::Column::AccountId.eq(update_rule.account_id))
+            .one(&self.db).await?
+            .ok_or(Error::RuleNotFound(update_rule.id))?;
+        let def = self.validate_rule(update_rule.account_id, update_rule.def).await?;
+
+        let updated = CategorizationRules::update(
+            categorization_rules::ActiveModel {
+                id:         Set(ex_rule.id),
+                account_id: Set(ex_rule.account_id),
+                ..to_active_model(&def)?
+            }
+        ).exec(&self.db).await?;
+
+        RuleModel::try_from(updated)
+    }
+
+    async fn delete_rule(&self, user_id: Uuid, delete_rule: DeleteRuleModel) -> Result<()> {
+        validate_user_account_role(&self.db, user_id, delete_rule.account_id, Role::Write).await?;
+
+        let rule = CategorizationRules::find_by_id(delete_rule.rule_id)
+            .filter(categorization_rules::Column::AccountId.eq(delete_rule.account_id))
+            .one(&self.db).await?
+            .ok_or(Error::RuleNotFound(delete_rule.rule_id))?;
+        CategorizationRules::delete(rule.into_active_model()).exec(&self.db).await?;
+
+        Ok(())
+    }
+
+    async fn run_rules(&self, user_id: Uuid, run_req: RunRulesModel, dry_run: bool) -> Result<Vec<RuleChangeModel>> {
+        let role = if dry_run { Role::Read } else { Role::Write };
+        validate_user_account_role(&self.db, user_id, run_req.account_id, role).await?;
+
+        // The transactions are loaded and filtered in the same DB transaction as they are changed,
+        // so a transaction recategorized or reconciled meanwhile is not rewritten from a stale read.
+        // A dry run changes nothing, so its DB transaction is simply rolled back.
+        let db_tx = self.db.begin().await?;
+
+        let rule_set = RuleSet::load(&db_tx, run_req.account_id).await?;
+        if rule_set.is_empty() {
+            return Ok(vec![]);
+        }
+
+        // Split, transfer and refill transactions keep their categories balanced elsewhere,
+        // and reconciled transactions are locked
+        let cond = run_req.filters.unwrap_or(vec![]).into_iter()
+            .fold(
+                Condition::all()
+                    .add(transactions::Column::AccountId.eq(run_req.account_id))
+                    .add(transactions::Column::DeleteOn.is_null())
+                    .add(transactions::Column::ReconciliationId.is_null())
+                    .add(transactions::Column::TransferId.is_null())
+                    .add(transactions::Column::IsRefill.eq(false)),
+                |cond, filter| cond.add(filter.into_condition())
+            );
+        let mut txs = Transactions::find().filter(cond)
+            .order_by_asc(transactions::Column::Id)
+            .all(&db_tx).await?;
+        let mut split_tx_ids = HashSet::new();
+        for chunk in txs.chunks(ID_CHUNK_SIZE) {
+            split_tx_ids.extend(
+                TransactionSplits::find()
+                    .filter(transaction_splits::Column::TransactionId.is_in(chunk.iter().map(|tx| tx.id)))
+                    .all(&db_tx).await?
+                    .into_iter()
+                    .map(|split| split.transaction_id)
+            );
+        }
+        txs.retain(|tx| !split_tx_ids.contains(&tx.id));
+
+        let payee_names = Payees::find()
+            .filter(payees::Column::AccountId.eq(run_req.account_id))
+            .all(&db_tx).await?
+            .into_iter()
+            .map(|payee| (payee.id, payee.name))
+            .collect::<HashMap<Uuid, String>>();
+        let mut ex_tag_ids = HashMap::<i32, HashSet<Uuid>>::new();
+        for chunk in txs.chunks(ID_CHUNK_SIZE) {
+            for tx_tag in TransactionTags::find()
+                .filter(transaction_tags::Column::TransactionId.is_in(chunk.iter().map(|tx| tx.id)))
+                .all(&db_tx).await?
+            {
+                ex_tag_ids.entry(tx_tag.transaction_id).or_default().insert(tx_tag.tag_id);
+            }
+        }
+
+        let mut changes = vec![];
+        let mut totals = HashMap::new();
+        for tx in &txs {
+            let outcome = rule_set.evaluate(&RuleInput {
+                notes: tx.notes.as_deref(),
+                payee_name: tx.payee_id.and_then(|payee_id| payee_names.get(&payee_id)).map(|name| name.as_str()),
+                amount: tx.orig_amount.unwrap_or(tx.amount),
+                currency_type: tx.orig_currency_type.as_deref().unwrap_or(USD_CURRENCY_TYPE),
+            });
+
+            let new_cat_id = outcome.category_id.or(tx.category_id);
+            let new_notes = outcome.notes.or(tx.notes.clone());
+            let added_tag_ids = outcome.tag_ids.into_iter()
+                .filter(|tag_id| !ex_tag_ids.get(&tx.id).is_some_and(|tag_ids| tag_ids.contains(tag_id)))
+                .collect::<Vec<Uuid>>();
+            if new_cat_id == tx.category_id && new_notes == tx.notes && added_tag_ids.is_empty() {
+                continue;
+            }
+
+            if new_cat_id != tx.category_id {
+                *totals.entry(tx.category_id).or_insert(0i64) -= tx.amount;
+                *totals.entry(new_cat_id).or_insert(0i64) += tx.amount;
+            }
+            changes.push(RuleChangeModel {
+                tx_id: tx.id,
+                rule_ids: outcome.rule_ids,
+                old_cat_id: tx.category_id,
+                new_cat_id,
+                added_tag_ids,
+                old_notes: tx.notes.clone(),
+                new_notes,
+            });
+        }
+        if dry_run || changes.is_empty() {
+            return Ok(changes);
+        }
+
+        let changes_by_tx = changes.iter().map(|change| (change.tx_id, change)).collect::<HashMap<i32, &RuleChangeModel>>();
+        for (tx, change) in txs.into_iter().filter_map(|tx| changes_by_tx.get(&tx.id).map(|change| (tx, *change))) {
+            let mut tx = tx.into_active_model();
+            tx.category_id = Set(change.new_cat_id);
+            tx.notes = Set(change.new_notes.clone());
+            Transactions::update(tx).exec(&db_tx).await?;
+
+            if !change.added_tag_ids.is_empty() {
+                TransactionTags::insert_many(
+                    change.added_tag_ids.iter().map(|tag_id| transaction_tags::ActiveModel {
+                        transaction_id: Set(change.tx_id),
+                        tag_id:         Set(*tag_id),
+                    })
+                ).exec(&db_tx).await?;
+            }
+        }
+        adjust_category_balances(
+            &db_tx,
+            totals,
+            &LedgerContext { source: LedgerSource::Rule, user_id: Some(user_id), timestamp: self.dt_provider.utc_now() }
+        ).await?;
+        db_tx.commit().await?;
+
+        Ok(changes)
+    }
+}
+
+impl DbConnRuleService {
+    pub fn new_dyn(db: DbConn, dt_provider: DynDateTimeProvider) -> DynRuleService {
+        Arc::new(DbConnRuleService { db, dt_provider })
+    }
+
+    ///
+    /// Validates the rule's conditions and actions, returning the definition with its name and currency normalized
+    /// 
+    async fn validate_rule(&self, account_id: Uuid, mut def: RuleDefinitionModel) -> Result<RuleDefinitionModel> {
+        def.name = def.name.trim().to_string();
+        if def.name.is_empty() {
+            return Err(Error::InvalidRule(String::from("name cannot be empty")));
+        }
+        def.currency_type = def.currency_type.map(|currency_type| currency_type.trim().to_uppercase());
+
+        if def.notes_pattern.is_none() && def.payee_pattern.is_none() && def.min_am.is_none()
+            && def.max_am.is_none() && def.currency_type.is_none()
+        {
+            return Err(Error::InvalidRule(String::from("at least one condition is required")));
+        }
+        if def.set_cat_id.is_none() && def.add_tag_ids.is_empty() && def.set_notes.is_none() {
+            return Err(Error::InvalidRule(String::from("at least one action is required")));
+        }
+        if let (Some(min), Some(max)) = (def.min_am, def.max_am) {
+            if min > max {
+                return Err(Error::InvalidRule(format!("min_am {} is greater than max_am {}", min, max)));
+            }
+        }
+        for (field, pattern) in [("notes_pattern", &def.notes_pattern), ("payee_pattern", &def.payee_pattern)] {
+            if let Some(pattern) = pattern {
+                compile_pattern(pattern).map_err(|e| Error::InvalidRule(format!("{}: {}", field, e)))?;
+            }
+        }
+
+        if let Some(cat_id) = def.set_cat_id {
+            Categories::find_by_id(cat_id)
+                .filter(categories::Column::AccountId.eq(account_id))
+                .one(&self.db).await?
+                .ok_or(Error::CategoryNotFound(cat_id))?;
+        }
+        def.add_tag_ids = def.add_tag_ids.into_iter().collect::<HashSet<Uuid>>().into_iter().collect();
+        def.add_tag_ids.sort();
+        let found = Tags::find()
+            .filter(tags::Column::AccountId.eq(account_id))
+            .filter(tags::Column::Id.is_in(def.add_tag_ids.clone()))
+            .all(&self.db).await?
+            .into_iter()
+            .map(|tag| tag.id)
+            .collect::<HashSet<Uuid>>();
+        if let Some(missing) = def.add_tag_ids.iter().find(|id| !found.contains(id)) {
+            return Err(Error::TagNotFound(*missing));
+        }
+
+        Ok(def)
+    }
+}
+
+fn to_active_model(def: &RuleDefinitionModel) -> Result<categorization_rules::ActiveModel> {
+    Ok(
+        categorization_rules::ActiveModel {
+            name:            Set(def.name.clone()),
+            priority:        Set(def.priority),
+            notes_pattern:   Set(def.notes_pattern.clone()),
+            payee_pattern:   Set(def.payee_pattern.clone()),
+            min_amount:      Set(def.min_am),
+            max_amount:      Set(def.max_am),
+            currency_type:   Set(def.currency_type.clone()),
+            set_category_id: Set(def.set_cat_id),
+            add_tag_ids:     Set(serde_json::to_string(&def.add_tag_ids)?),
+            set_notes:       Set(def.set_notes.clone()),
+
+            ..Default::default()
+        }
+    )
+}
+
+impl TryFrom<categorization_rules::Model> for RuleModel {
+    type Error = Error;
+
+    fn try_from(value: categorization_rules::Model) -> Result<Self> {
+        Ok(
+            RuleModel {
+                id: value.id,
+                def: RuleDefinitionModel {
+                    name: value.name,
+                    priority: value.priority,
+                    notes_pattern: value.notes_pattern,
+                    payee_pattern: value.payee_pattern,
+                    min_am: value.min_amount,
+                    max_am: value.max_amount,
+                    currency_type: value.currency_type,
+                    set_cat_id: value.set_category_id,
+                    add_tag_ids: serde_json::from_str(&value.add_tag_ids)?,
+                    set_notes: value.set_notes,
+                },
+            }
+        )
+    }
+}
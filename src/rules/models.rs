@@ -0,0 +1,96 @@
+use sea_orm::prelude::Uuid;
+use serde::{Deserialize, Serialize};
+
+use crate::transactions::models::TransactionFilter;
+
+///
+/// Conditions a transaction must meet for a rule to match, and the changes the rule makes.
+/// Every condition which is set must match, and at least one condition and one action are required.
+/// 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RuleDefinitionModel {
+    pub name: String,
+    ///
+    /// Rules are evaluated in ascending priority
+    /// 
+    pub priority: i32,
+    ///
+    /// Case-insensitive regex matched against the transaction notes
+    /// 
+    pub notes_pattern: Option<String>,
+    ///
+    /// Case-insensitive regex matched against the name of the transaction's payee
+    /// 
+    pub payee_pattern: Option<String>,
+    ///
+    /// Inclusive bounds on the amount in the transaction's original currency
+    /// 
+    pub min_am: Option<i64>,
+    pub max_am: Option<i64>,
+    pub currency_type: Option<String>,
+    pub set_cat_id: Option<Uuid>,
+    #[serde(default)]
+    pub add_tag_ids: Vec<Uuid>,
+    ///
+    /// Replaces the transaction notes
+    /// 
+    pub set_notes: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RuleModel {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub def: RuleDefinitionModel,
+}
+
+#[derive(Deserialize)]
+pub struct CreateRuleModel {
+    pub account_id: Uuid,
+    #[serde(flatten)]
+    pub def: RuleDefinitionModel,
+}
+
+///
+/// Replaces the definition of an existing rule
+/// 
+#[derive(Deserialize)]
+pub struct UpdateRuleModel {
+    pub account_id: Uuid,
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub def: RuleDefinitionModel,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteRuleModel {
+    pub account_id: Uuid,
+    pub rule_id: Uuid,
+}
+
+///
+/// Runs the account's rules over its existing transactions matching the filters.
+/// Split, transfer, refill and reconciled transactions are never changed.
+/// 
+#[derive(Deserialize)]
+pub struct RunRulesModel {
+    pub account_id: Uuid,
+    pub filters: Option<Vec<TransactionFilter>>,
+}
+
+///
+/// A change the rules make, or would make, to an existing transaction
+/// 
+#[derive(Debug, Serialize)]
+pub struct RuleChangeModel {
+    pub tx_id: i32,
+    ///
+    /// Every rule which matched the transaction, in priority order
+    /// 
+    pub rule_ids: Vec<Uuid>,
+    pub old_cat_id: Option<Uuid>,
+    pub new_cat_id: Option<Uuid>,
+    pub added_tag_ids: Vec<Uuid>,
+    pub old_notes: Option<String>,
+    pub new_notes: Option<String>,
+}
@@ -0,0 +1,66 @@
+use axum::{extract::{Path, State}, routing::{delete, get, post, put}, Json, Router};
+use uuid::Uuid;
+
+use crate::{auth::middleware::AuthUser, state::AppState};
+
+use super::{error::Result, models::{CreateRuleModel, DeleteRuleModel, RuleChangeModel, RuleModel, RunRulesModel, UpdateRuleModel}, DynRuleService};
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route("/{account_id}", get(get_rules))
+        .route("/", post(post_rule))
+        .route("/", put(update_rule))
+        .route("/", delete(delete_rule))
+        .route("/dry-run", post(dry_run))
+        .route("/apply", post(apply))
+        .with_state(state)
+}
+
+pub async fn get_rules(
+    State(rule_svc): State<DynRuleService>,
+    Path(account_id): Path<Uuid>,
+    user: AuthUser,
+) -> Result<Json<Vec<RuleModel>>> {
+    Ok(Json(rule_svc.get_rules(user.id, account_id).await?))
+}
+
+pub async fn post_rule(
+    State(rule_svc): State<DynRuleService>,
+    user: AuthUser,
+    Json(body): Json<CreateRuleModel>,
+) -> Result<Json<RuleModel>> {
+    Ok(Json(rule_svc.create_rule(user.id, body).await?))
+}
+
+pub async fn update_rule(
+    State(rule_svc): State<DynRuleService>,
+    user: AuthUser,
+    Json(body): Json<UpdateRuleModel>,
+) -> Result<Json<RuleModel>> {
+    Ok(Json(rule_svc.update_rule(user.id, body).await?))
+}
+
+pub async fn delete_rule(
+    State(rule_svc): State<DynRuleService>,
+    user: AuthUser,
+    Json(body): Json<DeleteRuleModel>,
+) -> Result<()> {
+    rule_svc.delete_rule(user.id, body).await?;
+    Ok(())
+}
+
+pub async fn dry_run(
+    State(rule_svc): State<DynRuleService>,
+    user: AuthUser,
+    Json(body): Json<RunRulesModel>,
+) -> Result<Json<Vec<RuleChangeModel>>> {
+    Ok(Json(rule_svc.run_rules(user.id, body, true).await?))
+}
+
+pub async fn apply(
+    State(rule_svc): State<DynRuleService>,
+    user: AuthUser,
+    Json(body): Json<RunRulesModel>,
+) -> Result<Json<Vec<RuleChangeModel>>> {
+    Ok(Json(rule_svc.run_rules(user.id, body, false).await?))
+}
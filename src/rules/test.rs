@@ -0,0 +1,254 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use lazy_static::lazy_static;
+use sea_orm::{sea_query::TableCreateStatement, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, Schema, Set};
+use utils_rs::date_time_provider::MockDateTimeProvider;
+use uuid::Uuid;
+
+use schmeconomics_entities::{account_users, accounts, categories, prelude::*, tags, transactions, users};
+
+use crate::{db_utils::Role, rules::{models::{CreateRuleModel, DeleteRuleModel, RuleDefinitionModel, RunRulesModel}, Error, RuleService}};
+
+use super::DbConnRuleService;
+
+lazy_static! {
+    static ref TEST_USER_1_ID: Uuid = Uuid::parse_str("be5ca263-2307-4e5a-acbd-3281fb81ea60").unwrap();
+    static ref TEST_ACCOUNT_1_ID: Uuid = Uuid::parse_str("f017369e-9dd1-4434-b197-40361cc0dbcd").unwrap();
+    static ref TEST_CAT_1_ID: Uuid = Uuid::parse_str("0c7a5b1e-3f0e-4a52-9f4b-7b1f5a0e6d21").unwrap();
+    static ref TEST_CAT_2_ID: Uuid = Uuid::parse_str("6d2f3c4a-8b7e-4e1d-a5c9-2e8f1b3d4c56").unwrap();
+    static ref TEST_TAG_1_ID: Uuid = Uuid::parse_str("9a4e2b7c-1d3f-4c8a-b6e5-3f7d2a1c9b84").unwrap();
+}
+
+async fn create_test_db() -> anyhow::Result<DbConn> {
+    // In-memory Sqlite connection
+    let db = Database::connect("sqlite::memory:").await?;
+
+    // Schema and Tables SeaOrm statements
+    let schema = Schema::new(DbBackend::Sqlite);
+    let user_stmt: TableCreateStatement = schema.create_table_from_entity(Users);
+    let account_stmt: TableCreateStatement = schema.create_table_from_entity(Accounts);
+    let account_user_stmt: TableCreateStatement = schema.create_table_from_entity(AccountUsers);
+    let category_stmt: TableCreateStatement = schema.create_table_from_entity(Categories);
+    let tx_stmt: TableCreateStatement = schema.create_table_from_entity(Transactions);
+    let split_stmt: TableCreateStatement = schema.create_table_from_entity(TransactionSplits);
+    let tag_stmt: TableCreateStatement = schema.create_table_from_entity(Tags);
+    let tx_tag_stmt: TableCreateStatement = schema.create_table_from_entity(TransactionTags);
+    let payee_stmt: TableCreateStatement = schema.create_table_from_entity(Payees);
+    let rule_stmt: TableCreateStatement = schema.create_table_from_entity(CategorizationRules);
+    let ledger_stmt: TableCreateStatement = schema.create_table_from_entity(CategoryLedger);
+
+    db.execute(db.get_database_backend().build(&user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&category_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_stmt)).await?;
+    db.execute(db.get_database_backend().build(&split_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tag_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_tag_stmt)).await?;
+    db.execute(db.get_database_backend().build(&payee_stmt)).await?;
+    db.execute(db.get_database_backend().build(&rule_stmt)).await?;
+    db.execute(db.get_database_backend().build(&ledger_stmt)).await?;
+
+    // Insert test user
+    let new_user = users::ActiveModel {
+        id: Set(*TEST_USER_1_ID),
+        email: Set(String::from("user1@mail.com")),
+        email_verified: Set(true),
+        password_hash: Set(String::from("password")),
+        name: Set(String::from("tester 1")),
+        created_on_utc: Set(Utc::now()),
+        two_factor_enabled: Set(false),
+
+        ..Default::default()
+    };
+    Users::insert(new_user).exec(&db).await?;
+
+    // Create test account
+    let account = accounts::ActiveModel {
+        id: Set(*TEST_ACCOUNT_1_ID),
+        ..Default::default()
+    };
+    Accounts::insert(account).exec(&db).await?;
+
+    let account_user = account_users::ActiveModel {
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        user_id: Set(*TEST_USER_1_ID),
+        role: Set(Role::Admin.to_string()),
+        verified: Set(true),
+        created_on: Set(Utc::now()),
+    };
+    AccountUsers::insert(account_user).exec(&db).await?;
+
+    let cats = [(*TEST_CAT_1_ID, "Shopping", 1), (*TEST_CAT_2_ID, "Coffee", 2)].map(|(id, name, order)| categories::ActiveModel {
+        id: Set(id),
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        name: Set(String::from(name)),
+        balance: Set(0),
+        refill_value: Set(0),
+        order: Set(order),
+    });
+    Categories::insert_many(cats).exec(&db).await?;
+
+    Tags::insert(
+        tags::ActiveModel { id: Set(*TEST_TAG_1_ID), account_id: Set(*TEST_ACCOUNT_1_ID), name: Set(String::from("online")) }
+    ).exec(&db).await?;
+
+    Ok(db)
+}
+
+async fn create_test_service() -> anyhow::Result<(DbConnRuleService, DbConn)> {
+    let db = create_test_db().await?;
+
+    let mut mock_dt_service = MockDateTimeProvider::new();
+    mock_dt_service.expect_utc_now().returning(Utc::now);
+
+    Ok((DbConnRuleService { db: db.clone(), dt_provider: Arc::new(mock_dt_service) }, db))
+}
+
+fn rule_def(name: &str, priority: i32) -> RuleDefinitionModel {
+    RuleDefinitionModel {
+        name: String::from(name),
+        priority,
+        notes_pattern: None,
+        payee_pattern: None,
+        min_am: None,
+        max_am: None,
+        currency_type: None,
+        set_cat_id: None,
+        add_tag_ids: vec![],
+        set_notes: None,
+    }
+}
+
+fn create_rule(def: RuleDefinitionModel) -> CreateRuleModel {
+    CreateRuleModel { account_id: *TEST_ACCOUNT_1_ID, def }
+}
+
+#[tokio::test]
+async fn test_create_rule_validation() -> anyhow::Result<()> {
+    let (svc, _db) = create_test_service().await?;
+
+    let res = svc.create_rule(*TEST_USER_1_ID, create_rule(RuleDefinitionModel { set_cat_id: Some(*TEST_CAT_1_ID), ..rule_def("no conditions", 0) })).await;
+    assert!(matches!(res, Err(Error::InvalidRule(_))));
+
+    let res = svc.create_rule(*TEST_USER_1_ID, create_rule(RuleDefinitionModel { min_am: Some(0), ..rule_def("no actions", 0) })).await;
+    assert!(matches!(res, Err(Error::InvalidRule(_))));
+
+    let res = svc.create_rule(
+        *TEST_USER_1_ID,
+        create_rule(RuleDefinitionModel { min_am: Some(10), max_am: Some(5), set_cat_id: Some(*TEST_CAT_1_ID), ..rule_def("bad range", 0) })
+    ).await;
+    assert!(matches!(res, Err(Error::InvalidRule(_))));
+
+    let res = svc.create_rule(
+        *TEST_USER_1_ID,
+        create_rule(RuleDefinitionModel { notes_pattern: Some(String::from("amzn(")), set_cat_id: Some(*TEST_CAT_1_ID), ..rule_def("bad regex", 0) })
+    ).await;
+    assert!(matches!(res, Err(Error::InvalidRule(msg)) if msg.starts_with("notes_pattern")));
+
+    let test_id = Uuid::now_v7();
+    let res = svc.create_rule(
+        *TEST_USER_1_ID,
+        create_rule(RuleDefinitionModel { notes_pattern: Some(String::from("amzn")), set_cat_id: Some(test_id), ..rule_def("bad category", 0) })
+    ).await;
+    assert!(matches!(res, Err(Error::CategoryNotFound(id)) if id == test_id));
+
+    let res = svc.create_rule(
+        *TEST_USER_1_ID,
+        create_rule(RuleDefinitionModel { notes_pattern: Some(String::from("amzn")), add_tag_ids: vec![test_id], ..rule_def("bad tag", 0) })
+    ).await;
+    assert!(matches!(res, Err(Error::TagNotFound(id)) if id == test_id));
+
+    // Rules are listed in priority order
+    let low = svc.create_rule(
+        *TEST_USER_1_ID,
+        create_rule(RuleDefinitionModel { currency_type: Some(String::from(" cad ")), set_notes: Some(String::from("CAD")), ..rule_def(" Canadian ", 5) })
+    ).await?;
+    assert_eq!("Canadian", low.def.name);
+    assert_eq!(Some(String::from("CAD")), low.def.currency_type);
+    let high = svc.create_rule(
+        *TEST_USER_1_ID,
+        create_rule(RuleDefinitionModel { notes_pattern: Some(String::from("amzn")), add_tag_ids: vec![*TEST_TAG_1_ID, *TEST_TAG_1_ID], ..rule_def("Amazon", 1) })
+    ).await?;
+    assert_eq!(vec![*TEST_TAG_1_ID], high.def.add_tag_ids);
+
+    let rules = svc.get_rules(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID).await?;
+    assert_eq!(vec![high.id, low.id], rules.iter().map(|rule| rule.id).collect::<Vec<Uuid>>());
+
+    svc.delete_rule(*TEST_USER_1_ID, DeleteRuleModel { account_id: *TEST_ACCOUNT_1_ID, rule_id: low.id }).await?;
+    let res = svc.delete_rule(*TEST_USER_1_ID, DeleteRuleModel { account_id: *TEST_ACCOUNT_1_ID, rule_id: low.id }).await;
+    assert!(matches!(res, Err(Error::RuleNotFound(id)) if id == low.id));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dry_run_and_apply() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    for (notes, amount, category_id) in [("AMZN Mktp US", -2500, None), ("Starbucks", -500, Some(*TEST_CAT_1_ID)), ("Paycheque", 10000, None)] {
+        Transactions::insert(
+            transactions::ActiveModel {
+                account_id: Set(*TEST_ACCOUNT_1_ID),
+                category_id: Set(category_id),
+                timestamp: Set(Utc::now()),
+                amount: Set(amount),
+                notes: Set(Some(String::from(notes))),
+                is_refill: Set(false),
+
+                ..Default::default()
+            }
+        ).exec(&db).await?;
+    }
+
+    let amazon = svc.create_rule(
+        *TEST_USER_1_ID,
+        create_rule(RuleDefinitionModel {
+            notes_pattern: Some(String::from("^amzn")),
+            max_am: Some(0),
+            set_cat_id: Some(*TEST_CAT_1_ID),
+            add_tag_ids: vec![*TEST_TAG_1_ID],
+            set_notes: Some(String::from("Amazon")),
+            ..rule_def("Amazon", 0)
+        })
+    ).await?;
+    let coffee = svc.create_rule(
+        *TEST_USER_1_ID,
+        create_rule(RuleDefinitionModel { notes_pattern: Some(String::from("starbucks")), set_cat_id: Some(*TEST_CAT_2_ID), ..rule_def("Coffee", 1) })
+    ).await?;
+
+    // A dry run reports the changes without making them
+    let changes = svc.run_rules(*TEST_USER_1_ID, RunRulesModel { account_id: *TEST_ACCOUNT_1_ID, filters: None }, true).await?;
+    assert_eq!(2, changes.len());
+    assert_eq!(1, changes[0].tx_id);
+    assert_eq!(vec![amazon.id], changes[0].rule_ids);
+    assert_eq!(None, changes[0].old_cat_id);
+    assert_eq!(Some(*TEST_CAT_1_ID), changes[0].new_cat_id);
+    assert_eq!(vec![*TEST_TAG_1_ID], changes[0].added_tag_ids);
+    assert_eq!(Some(String::from("Amazon")), changes[0].new_notes);
+    assert_eq!(2, changes[1].tx_id);
+    assert_eq!(vec![coffee.id], changes[1].rule_ids);
+    assert_eq!(Some(*TEST_CAT_2_ID), changes[1].new_cat_id);
+    assert!(TransactionTags::find().all(&db).await?.is_empty());
+    assert_eq!(None, Transactions::find_by_id(1).one(&db).await?.unwrap().category_id);
+
+    let applied = svc.run_rules(*TEST_USER_1_ID, RunRulesModel { account_id: *TEST_ACCOUNT_1_ID, filters: None }, false).await?;
+    assert_eq!(2, applied.len());
+
+    let tx = Transactions::find_by_id(1).one(&db).await?.unwrap();
+    assert_eq!(Some(*TEST_CAT_1_ID), tx.category_id);
+    assert_eq!(Some(String::from("Amazon")), tx.notes);
+    assert_eq!(1, TransactionTags::find().all(&db).await?.len());
+
+    // The Amazon transaction moves into Shopping, while the Starbucks one moves out to Coffee
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(-2500 + 500, cats.iter().find(|cat| cat.id == *TEST_CAT_1_ID).unwrap().balance);
+    assert_eq!(-500, cats.iter().find(|cat| cat.id == *TEST_CAT_2_ID).unwrap().balance);
+
+    // Applying again changes nothing
+    let changes = svc.run_rules(*TEST_USER_1_ID, RunRulesModel { account_id: *TEST_ACCOUNT_1_ID, filters: None }, true).await?;
+    assert!(changes.is_empty());
+
+    Ok(())
+}
@@ -2,7 +2,7 @@ use axum_macros::FromRef;
 use schmeconomics_auth::auth_service::DynAuthService;
 use tokens_rs::token_service::DynTokenService;
 
-use crate::{accounts::DynAccountService, categories::DynCategoryService, transactions::DynTransactionService, users::DynUserService};
+use crate::{accounts::DynAccountService, attachments::DynAttachmentService, categories::DynCategoryService, payees::DynPayeeService, reconciliations::DynReconciliationService, recurring::DynRecurringService, refills::DynRefillService, reports::DynReportService, rules::DynRuleService, tags::DynTagService, transactions::DynTransactionService, users::DynUserService};
 
 #[derive(Clone, FromRef)]
 pub struct AppState {
@@ -12,4 +12,12 @@ pub struct AppState {
     pub tx_svc: DynTransactionService,
     pub account_svc: DynAccountService,
     pub user_svc: DynUserService,
+    pub refill_svc: DynRefillService,
+    pub recurring_svc: DynRecurringService,
+    pub tag_svc: DynTagService,
+    pub attachment_svc: DynAttachmentService,
+    pub payee_svc: DynPayeeService,
+    pub rule_svc: DynRuleService,
+    pub reconciliation_svc: DynReconciliationService,
+    pub report_svc: DynReportService,
 }
\ No newline at end of file
@@ -0,0 +1,37 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use log::error;
+use sea_orm::DbErr;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::{db_utils::DbUtilsError, response::internal_server_error_response};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("An error occurred while connecting to the database: {0}")]
+    DbErr(#[from] DbErr),
+    #[error(transparent)]
+    DbUtilsError(#[from] DbUtilsError),
+    #[error("Tag name '{0}' already taken in account")]
+    NameReuse(String),
+    #[error("Tag name cannot be empty")]
+    EmptyName,
+    #[error("Tag with ID '{0}' not found")]
+    TagNotFound(Uuid),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> axum::response::Response {
+        return match self {
+            Error::DbErr(_) | Error::DbUtilsError(_) => { 
+                error!("{}", self);
+                internal_server_error_response()
+            },
+            Error::NameReuse(_) | Error::EmptyName | Error::TagNotFound(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            }
+        };
+    }
+}
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use schmeconomics_entities::{prelude::*, tags, transaction_tags};
+use sea_orm::{prelude::{Expr, Uuid}, sea_query::{ExprTrait, Func}, ColumnTrait, Condition, ConnectionTrait, DbConn, EntityTrait, IntoActiveModel, QueryFilter, QueryOrder, Set, TransactionTrait};
+
+use crate::db_utils::{validate_user_account_role, Role};
+
+use {error::*, models::*};
+
+pub mod error;
+pub mod models;
+pub mod routes;
+
+#[cfg(test)]
+mod test;
+
+pub type DynTagService = Arc<dyn TagService + Send + Sync>;
+
+///
+/// Free-form labels which can be attached to any number of an account's transactions,
+/// independently of their category
+/// 
+#[async_trait]
+pub trait TagService {
+    async fn get_tags(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<TagModel>>;
+    async fn create_tag(&self, user_id: Uuid, tag: CreateTagModel) -> Result<TagModel>;
+    async fn rename_tag(&self, user_id: Uuid, tag: RenameTagModel) -> Result<TagModel>;
+    ///
+    /// Deletes the tag, removing it from every transaction it is attached to
+    /// 
+    async fn delete_tag(&self, user_id: Uuid, tag: DeleteTagModel) -> Result<()>;
+}
+
+pub struct DbConnTagService {
+    db: DbConn,
+}
+
+#[async_trait]
+impl TagService for DbConnTagService {
+    async fn get_tags(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<TagModel>> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Read).await?;
+
+        Ok(
+            Tags::find().filter(tags::Column::AccountId.eq(account_id))
+                .order_by_asc(tags::Column::Name)
+                .all(&self.db).await?
+                .into_iter()
+                .map(TagModel::from)
+                .collect()
+        )
+    }
+
+    async fn create_tag(&self, user_id: Uuid, create_tag: CreateTagModel) -> Result<TagModel> {
+        validate_user_account_role(&self.db, user_id, create_tag.account_id, Role::Write).await?;
+
+        let tx = self.db.begin().await?;
+        let fmt_name = self.validate_tag_name(create_tag.account_id, &create_tag.name, None, &tx).await?;
+
+        let new_tag = tags::ActiveModel {
+            id: Set(Uuid::now_v7()),
+            account_id: Set(create_tag.account_id),
+            name: Set(fmt_name),
+        };
+        let tag = Tags::insert(new_tag).exec_with_returning(&tx).await?;
+        tx.commit().await?;
+
+        Ok(tag.into())
+    }
+
+    async fn rename_tag(&self, user_id: Uuid, rename_tag: RenameTagModel) -> Result<TagModel> {
+        validate_user_account_role(&self.db, user_id, rename_tag.account_id, Role::Write).await?;
+
+        let tx = self.db.begin().await?;
+        let ex_tag = Tags::find_by_id(rename_tag.id)
+            .filter(tags::Column::AccountId.eq(rename_tag.account_id))
+            .one(&tx).await?
+            .ok_or(Error::TagNotFound(rename_tag.id))?;
+        let fmt_name = self.validate_tag_name(rename_tag.account_id, &rename_tag.new_name, Some(ex_tag.id), &tx).await?;
+
+        let mut ex_tag = ex_tag.into_active_model();
+        ex_tag.name = Set(fmt_name);
+        let updated = Tags::update(ex_tag).exec(&tx).await?;
+        tx.commit().await?;
+
+        Ok(updated.into())
+    }
+
+    async fn delete_tag(&self, user_id: Uuid, delete_tag: DeleteTagModel) -> Result<()> {
+        validate_user_account_role(&self.db, user_id, delete_tag.account_id, Role::Write).await?;
+
+        let tx = self.db.begin().await?;
+        let tag = Tags::find_by_id(delete_tag.tag_id)
+            .filter(tags::Column::AccountId.eq(delete_tag.account_id))
+            .one(&tx).await?
+            .ok_or(Error::TagNotFound(delete_tag.tag_id))?;
+
+        TransactionTags::delete_many()
+            .filter(transaction_tags::Column::TagId.eq(tag.id))
+            .exec(&tx).await?;
+        Tags::delete(tag.into_active_model()).exec(&tx).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+impl DbConnTagService {
+    pub fn new_dyn(db: DbConn) -> DynTagService {
+        Arc::new(DbConnTagService { db })
+    }
+
+    ///
+    /// Trims the tag name, ensuring it is not empty or already used in the account
+    /// 
+    async fn validate_tag_name(
+        &self,
+        account_id: Uuid,
+        tag_name: &str,
+        tag_id: Option<Uuid>,
+        tx: &impl ConnectionTrait,
+    ) -> Result<String> {
+        let fmt_name = tag_name.trim().to_string();
+        if fmt_name.is_empty() {
+            return Err(Error::EmptyName);
+        }
+
+        let existing_tag = Tags::find().filter(
+            Condition::all()
+                .add(tags::Column::AccountId.eq(account_id))
+                .add(Func::lower(Expr::col(tags::Column::Name)).eq(fmt_name.to_lowercase()))
+            ).one(tx).await?;
+        if existing_tag.is_some_and(|tag| Some(tag.id) != tag_id) {
+            return Err(Error::NameReuse(fmt_name));
+        }
+
+        Ok(fmt_name)
+    }
+}
@@ -0,0 +1,35 @@
+use sea_orm::prelude::Uuid;
+use serde::{Deserialize, Serialize};
+
+use schmeconomics_entities::tags;
+
+#[derive(Debug, Serialize)]
+pub struct TagModel {
+    pub id: Uuid,
+    pub name: String,
+}
+
+impl From<tags::Model> for TagModel {
+    fn from(value: tags::Model) -> Self {
+        TagModel { id: value.id, name: value.name }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateTagModel {
+    pub account_id: Uuid,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+pub struct RenameTagModel {
+    pub account_id: Uuid,
+    pub id: Uuid,
+    pub new_name: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteTagModel {
+    pub account_id: Uuid,
+    pub tag_id: Uuid,
+}
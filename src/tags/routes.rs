@@ -0,0 +1,48 @@
+use axum::{extract::{Path, State}, routing::{delete, get, post, put}, Json, Router};
+use uuid::Uuid;
+
+use crate::{auth::middleware::AuthUser, state::AppState};
+
+use super::{error::Result, models::{CreateTagModel, DeleteTagModel, RenameTagModel, TagModel}, DynTagService};
+
+pub fn routes(state: AppState) -> Router {
+    Router::new()
+        .route("/{account_id}", get(get_tags))
+        .route("/", post(post_tag))
+        .route("/", put(rename_tag))
+        .route("/", delete(delete_tag))
+        .with_state(state)
+}
+
+pub async fn get_tags(
+    State(tag_svc): State<DynTagService>,
+    Path(account_id): Path<Uuid>,
+    user: AuthUser,
+) -> Result<Json<Vec<TagModel>>> {
+    Ok(Json(tag_svc.get_tags(user.id, account_id).await?))
+}
+
+pub async fn post_tag(
+    State(tag_svc): State<DynTagService>,
+    user: AuthUser,
+    Json(body): Json<CreateTagModel>,
+) -> Result<Json<TagModel>> {
+    Ok(Json(tag_svc.create_tag(user.id, body).await?))
+}
+
+pub async fn rename_tag(
+    State(tag_svc): State<DynTagService>,
+    user: AuthUser,
+    Json(body): Json<RenameTagModel>,
+) -> Result<Json<TagModel>> {
+    Ok(Json(tag_svc.rename_tag(user.id, body).await?))
+}
+
+pub async fn delete_tag(
+    State(tag_svc): State<DynTagService>,
+    user: AuthUser,
+    Json(body): Json<DeleteTagModel>,
+) -> Result<()> {
+    tag_svc.delete_tag(user.id, body).await?;
+    Ok(())
+}
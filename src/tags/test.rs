@@ -0,0 +1,151 @@
+use chrono::Utc;
+use lazy_static::lazy_static;
+use sea_orm::{sea_query::TableCreateStatement, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, Schema, Set};
+use uuid::Uuid;
+
+use schmeconomics_entities::{account_users, accounts, prelude::*, transaction_tags, transactions, users};
+
+use crate::{db_utils::Role, tags::{models::{CreateTagModel, DeleteTagModel, RenameTagModel}, Error, TagService}};
+
+use super::DbConnTagService;
+
+lazy_static! {
+    static ref TEST_USER_1_ID: Uuid = Uuid::parse_str("be5ca263-2307-4e5a-acbd-3281fb81ea60").unwrap();
+    static ref TEST_ACCOUNT_1_ID: Uuid = Uuid::parse_str("f017369e-9dd1-4434-b197-40361cc0dbcd").unwrap();
+}
+
+async fn create_test_db() -> anyhow::Result<DbConn> {
+    // In-memory Sqlite connection
+    let db = Database::connect("sqlite::memory:").await?;
+
+    // Schema and Tables SeaOrm statements
+    let schema = Schema::new(DbBackend::Sqlite);
+    let user_stmt: TableCreateStatement = schema.create_table_from_entity(Users);
+    let account_stmt: TableCreateStatement = schema.create_table_from_entity(Accounts);
+    let account_user_stmt: TableCreateStatement = schema.create_table_from_entity(AccountUsers);
+    let tx_stmt: TableCreateStatement = schema.create_table_from_entity(Transactions);
+    let tag_stmt: TableCreateStatement = schema.create_table_from_entity(Tags);
+    let tx_tag_stmt: TableCreateStatement = schema.create_table_from_entity(TransactionTags);
+
+    db.execute(db.get_database_backend().build(&user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_stmt)).await?;
+    db.execute(db.get_database_backend().build(&account_user_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tag_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_tag_stmt)).await?;
+
+    // Insert test user
+    let new_user = users::ActiveModel {
+        id: Set(*TEST_USER_1_ID),
+        email: Set(String::from("user1@mail.com")),
+        email_verified: Set(true),
+        password_hash: Set(String::from("password")),
+        name: Set(String::from("tester 1")),
+        created_on_utc: Set(Utc::now()),
+        two_factor_enabled: Set(false),
+
+        ..Default::default()
+    };
+    Users::insert(new_user).exec(&db).await?;
+
+    // Create test account
+    let account = accounts::ActiveModel {
+        id: Set(*TEST_ACCOUNT_1_ID),
+        ..Default::default()
+    };
+    Accounts::insert(account).exec(&db).await?;
+
+    let account_user = account_users::ActiveModel {
+        account_id: Set(*TEST_ACCOUNT_1_ID),
+        user_id: Set(*TEST_USER_1_ID),
+        role: Set(Role::Admin.to_string()),
+        verified: Set(true),
+        created_on: Set(Utc::now()),
+    };
+    AccountUsers::insert(account_user).exec(&db).await?;
+
+    Ok(db)
+}
+
+async fn create_test_service() -> anyhow::Result<(DbConnTagService, DbConn)> {
+    let db = create_test_db().await?;
+    Ok((DbConnTagService { db: db.clone() }, db))
+}
+
+#[tokio::test]
+async fn test_create_and_rename_tag() -> anyhow::Result<()> {
+    let (svc, _db) = create_test_service().await?;
+
+    let tag = svc.create_tag(
+        *TEST_USER_1_ID,
+        CreateTagModel { account_id: *TEST_ACCOUNT_1_ID, name: String::from("  vacation-2026 ") }
+    ).await?;
+    assert_eq!("vacation-2026", tag.name);
+
+    // Names are unique per account, ignoring case
+    let res = svc.create_tag(
+        *TEST_USER_1_ID,
+        CreateTagModel { account_id: *TEST_ACCOUNT_1_ID, name: String::from("Vacation-2026") }
+    ).await;
+    assert!(matches!(res, Err(Error::NameReuse(name)) if name == "Vacation-2026"));
+
+    let res = svc.create_tag(
+        *TEST_USER_1_ID,
+        CreateTagModel { account_id: *TEST_ACCOUNT_1_ID, name: String::from("   ") }
+    ).await;
+    assert!(matches!(res, Err(Error::EmptyName)));
+
+    let renamed = svc.rename_tag(
+        *TEST_USER_1_ID,
+        RenameTagModel { account_id: *TEST_ACCOUNT_1_ID, id: tag.id, new_name: String::from("reimbursable") }
+    ).await?;
+    assert_eq!(tag.id, renamed.id);
+    assert_eq!("reimbursable", renamed.name);
+
+    // A tag can change the case of its own name
+    let renamed = svc.rename_tag(
+        *TEST_USER_1_ID,
+        RenameTagModel { account_id: *TEST_ACCOUNT_1_ID, id: tag.id, new_name: String::from("Reimbursable") }
+    ).await?;
+    assert_eq!("Reimbursable", renamed.name);
+
+    let tags = svc.get_tags(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID).await?;
+    assert_eq!(1, tags.len());
+    assert_eq!("Reimbursable", tags[0].name);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_tag_removes_from_transactions() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let tag = svc.create_tag(
+        *TEST_USER_1_ID,
+        CreateTagModel { account_id: *TEST_ACCOUNT_1_ID, name: String::from("tax-deductible") }
+    ).await?;
+
+    let tx = Transactions::insert(
+        transactions::ActiveModel {
+            account_id: Set(*TEST_ACCOUNT_1_ID),
+            timestamp: Set(Utc::now()),
+            amount: Set(-1000),
+            is_refill: Set(false),
+
+            ..Default::default()
+        }
+    ).exec_with_returning(&db).await?;
+    TransactionTags::insert(
+        transaction_tags::ActiveModel { transaction_id: Set(tx.id), tag_id: Set(tag.id) }
+    ).exec(&db).await?;
+
+    svc.delete_tag(*TEST_USER_1_ID, DeleteTagModel { account_id: *TEST_ACCOUNT_1_ID, tag_id: tag.id }).await?;
+    assert!(Tags::find().all(&db).await?.is_empty());
+    assert!(TransactionTags::find().all(&db).await?.is_empty());
+    assert_eq!(1, Transactions::find().all(&db).await?.len());
+
+    let res = svc.delete_tag(*TEST_USER_1_ID, DeleteTagModel { account_id: *TEST_ACCOUNT_1_ID, tag_id: tag.id }).await;
+    assert!(matches!(res, Err(Error::TagNotFound(id)) if id == tag.id));
+
+    Ok(())
+}
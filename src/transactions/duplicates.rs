@@ -0,0 +1,61 @@
+use std::collections::HashSet;
+
+use sea_orm::prelude::DateTimeUtc;
+
+use schmeconomics_entities::transactions;
+
+use super::Config;
+
+///
+/// Fields compared when deciding whether two transactions are probably the same purchase
+/// 
+pub struct DuplicateKey<'a> {
+    ///
+    /// Amount in USD
+    /// 
+    pub amount: i64,
+    pub timestamp: DateTimeUtc,
+    pub notes: Option<&'a str>,
+}
+
+impl<'a> From<&'a transactions::Model> for DuplicateKey<'a> {
+    fn from(value: &'a transactions::Model) -> Self {
+        DuplicateKey { amount: value.amount, timestamp: value.timestamp, notes: value.notes.as_deref() }
+    }
+}
+
+///
+/// Whether the transactions are within the configured window of each other,
+/// with amounts within the configured tolerance and similar notes
+/// 
+pub fn is_probable_duplicate(config: &Config, a: &DuplicateKey, b: &DuplicateKey) -> bool {
+    (a.timestamp - b.timestamp).num_seconds().abs() <= config.duplicate_window_s
+        && amounts_similar(a.amount, b.amount, config.duplicate_am_tolerance_pct)
+        && notes_similar(a.notes, b.notes)
+}
+
+fn amounts_similar(a: i64, b: i64, tolerance_pct: f64) -> bool {
+    a.signum() == b.signum()
+        && (a - b).abs() as f64 <= a.abs().max(b.abs()) as f64 * tolerance_pct / 100.0
+}
+
+///
+/// Notes are similar when at least half of their combined words are shared,
+/// ignoring case and punctuation, or when both are empty
+/// 
+fn notes_similar(a: Option<&str>, b: Option<&str>) -> bool {
+    let (a, b) = (note_words(a), note_words(b));
+    if a.is_empty() || b.is_empty() {
+        return a.is_empty() && b.is_empty();
+    }
+
+    a.intersection(&b).count() * 2 >= a.union(&b).count()
+}
+
+fn note_words(notes: Option<&str>) -> HashSet<String> {
+    notes.unwrap_or_default()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
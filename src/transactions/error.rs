@@ -1,12 +1,14 @@
 use std::backtrace;
 
-use axum::response::IntoResponse;
+use axum::{http::StatusCode, response::IntoResponse, Json};
 use log::error;
 use sea_orm::{prelude::Uuid, DbErr};
 use thiserror::Error;
 
 use crate::{currency_conv_provider, db_utils::DbUtilsError, response::internal_server_error_response};
 
+use super::models::DuplicateWarningModel;
+
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug, Error)]
@@ -21,17 +23,57 @@ pub enum Error {
     RowNotFound(String),
     #[error("Account {0} does not own transaction {1}")]
     AccountDoesNotOwnTransaction(Uuid, i32),
+    #[error("Category with ID '{0}' not found in account")]
+    CategoryNotFound(Uuid),
+    #[error("Invalid transaction cursor: {0}")]
+    InvalidCursor(String),
+    #[error("Could not read multipart form: {0}")]
+    MultipartError(#[from] axum::extract::multipart::MultipartError),
+    #[error("Invalid form field '{0}': {1}")]
+    InvalidField(&'static str, String),
+    #[error("Could not import statement: {0}")]
+    InvalidMapping(String),
+    #[error("Could not serialize exported transactions: {0}")]
+    ExportError(String),
+    #[error("Invalid transaction splits: {0}")]
+    InvalidSplits(String),
+    #[error("Transaction {0} is part of a transfer. Its amount and category cannot be changed")]
+    TransferTransaction(i32),
+    #[error("Idempotency key '{0}' was already used for another account")]
+    IdempotencyKeyReused(String),
+    #[error("Transaction {0} has no original amount to apply an exchange rate to")]
+    NoOriginalAmount(i32),
+    #[error("Tag with ID '{0}' not found in account")]
+    TagNotFound(Uuid),
+    #[error("Payee with ID '{0}' not found in account")]
+    PayeeNotFound(Uuid),
+    #[error("Could not (de)serialize JSON: {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error("Transaction {0} is reconciled. Its amount, timestamp and categories cannot be changed, and it cannot be deleted")]
+    ReconciledTransaction(i32),
+    #[error("Transaction {0} is not in the trash")]
+    TransactionNotInTrash(i32),
+    #[error("{} transaction(s) are probable duplicates", .0.len())]
+    ProbableDuplicates(Vec<DuplicateWarningModel>),
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
         return match self {
             Error::DbErr(_) | Error::DbUtilsError(_) |
-            Error::RowNotFound(_) | Error::CurrencyConversionProviderError(_) |
+            Error::RowNotFound(_) | Error::CurrencyConversionProviderError(_) | Error::ExportError(_) | Error::SerdeJsonError(_) |
             Error::AccountDoesNotOwnTransaction(_, _) => {
                 error!("{}\n{}", self, backtrace::Backtrace::capture());
                 internal_server_error_response()
             },
+            Error::CategoryNotFound(_) | Error::InvalidCursor(_) | Error::MultipartError(_) |
+            Error::InvalidField(_, _) | Error::InvalidMapping(_) | Error::InvalidSplits(_) |
+            Error::TransferTransaction(_) | Error::IdempotencyKeyReused(_) |
+            Error::NoOriginalAmount(_) | Error::TagNotFound(_) | Error::PayeeNotFound(_) |
+            Error::TransactionNotInTrash(_) | Error::ReconciledTransaction(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string()).into_response()
+            },
+            Error::ProbableDuplicates(duplicates) => (StatusCode::CONFLICT, Json(duplicates)).into_response(),
         }
     }
 }
\ No newline at end of file
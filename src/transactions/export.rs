@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+
+use futures::stream::{self, BoxStream};
+use sea_orm::{prelude::Uuid, ColumnTrait, Condition, DbConn, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+
+use schmeconomics_entities::{prelude::*, transaction_splits, transactions};
+
+use super::{error::{Error, Result}, models::{ExportFormat, ExportedTransactionModel}};
+
+///
+/// Number of transactions fetched from the database per chunk of the export
+///
+const EXPORT_CHUNK_SIZE: u64 = 500;
+
+const CSV_HEADERS: [&str; 7] = ["id", "timestamp_utc", "am", "category", "user_id", "notes", "is_refill"];
+
+pub type TransactionExportStream = BoxStream<'static, Result<Vec<u8>>>;
+
+struct ExportState {
+    db: DbConn,
+    cond: Condition,
+    format: ExportFormat,
+    cat_names: HashMap<Uuid, String>,
+    last_id: Option<i32>,
+    headers_written: bool,
+    done: bool,
+}
+
+///
+/// Streams every transaction matching `cond` in ascending id order. Transactions are
+/// fetched in chunks by keyset, so the export is never held in memory at once.
+///
+pub fn export_stream(
+    db: DbConn,
+    cond: Condition,
+    format: ExportFormat,
+    cat_names: HashMap<Uuid, String>,
+) -> TransactionExportStream {
+    let state = ExportState { db, cond, format, cat_names, last_id: None, headers_written: false, done: false };
+
+    Box::pin(stream::try_unfold(state, |mut state| async move {
+        if state.done {
+            return Ok(None);
+        }
+
+        let mut query = Transactions::find().filter(state.cond.clone());
+        if let Some(last_id) = state.last_id {
+            query = query.filter(transactions::Column::Id.gt(last_id));
+        }
+        let txs = query
+            .order_by_asc(transactions::Column::Id)
+            .limit(EXPORT_CHUNK_SIZE)
+            .all(&state.db).await?;
+
+        state.done = (txs.len() as u64) < EXPORT_CHUNK_SIZE;
+        state.last_id = txs.last().map(|tx| tx.id).or(state.last_id);
+
+        // Split transactions list the names of each of their split categories
+        let mut split_cat_ids = HashMap::<i32, Vec<Uuid>>::new();
+        if !txs.is_empty() {
+            for split in TransactionSplits::find()
+                .filter(transaction_splits::Column::TransactionId.is_in(txs.iter().map(|tx| tx.id)))
+                .order_by_asc(transaction_splits::Column::Id)
+                .all(&state.db).await?
+            {
+                split_cat_ids.entry(split.transaction_id).or_default().push(split.category_id);
+            }
+        }
+
+        let txs = txs.into_iter()
+            .map(|tx| ExportedTransactionModel {
+                id: tx.id,
+                timestamp_utc: tx.timestamp,
+                am: tx.amount,
+                category: match split_cat_ids.get(&tx.id) {
+                    Some(cat_ids) => Some(
+                        cat_ids.iter()
+                            .filter_map(|cat_id| state.cat_names.get(cat_id).map(|name| name.as_str()))
+                            .collect::<Vec<&str>>()
+                            .join("; ")
+                    ),
+                    None => tx.category_id.and_then(|cat_id| state.cat_names.get(&cat_id).cloned()),
+                },
+                user_id: tx.user_id,
+                notes: tx.notes,
+                is_refill: tx.is_refill,
+            })
+            .collect::<Vec<_>>();
+
+        let chunk = match state.format {
+            ExportFormat::Csv => {
+                let chunk = write_csv(&txs, !state.headers_written)?;
+                state.headers_written = true;
+                chunk
+            },
+            ExportFormat::Ndjson => write_ndjson(&txs)?,
+        };
+
+        Ok(Some((chunk, state)))
+    }))
+}
+
+fn write_csv(txs: &[ExportedTransactionModel], with_headers: bool) -> Result<Vec<u8>> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(vec![]);
+
+    if with_headers {
+        writer.write_record(CSV_HEADERS).map_err(|e| Error::ExportError(e.to_string()))?;
+    }
+    for tx in txs {
+        writer.serialize(tx).map_err(|e| Error::ExportError(e.to_string()))?;
+    }
+
+    writer.into_inner().map_err(|e| Error::ExportError(e.to_string()))
+}
+
+fn write_ndjson(txs: &[ExportedTransactionModel]) -> Result<Vec<u8>> {
+    let mut chunk = vec![];
+    for tx in txs {
+        serde_json::to_writer(&mut chunk, tx).map_err(|e| Error::ExportError(e.to_string()))?;
+        chunk.push(b'\n');
+    }
+    Ok(chunk)
+}
@@ -0,0 +1,148 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use csv::{ReaderBuilder, StringRecord};
+use sea_orm::prelude::Uuid;
+use serde::Deserialize;
+
+use crate::currency_conv_provider::USD_CURRENCY_TYPE;
+
+use super::{parse_amount, ParsedTransactionModel};
+
+///
+/// A column in the statement, either by 0-based index or by header name
+///
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+pub enum CsvColumn {
+    Index(usize),
+    Header(String),
+}
+
+///
+/// How amounts in the statement are signed. Transactions store
+/// spending as negative amounts, so statements which list spending
+/// as positive amounts are `Inverted`.
+///
+#[derive(Clone, Copy, Deserialize)]
+pub enum SignConvention { AsIs, Inverted, }
+
+///
+/// Describes how the columns of a bank's CSV statement map onto transactions
+///
+#[derive(Clone, Deserialize)]
+pub struct CsvMappingModel {
+    pub has_headers: bool,
+    ///
+    /// Field delimiter, which must be an ASCII character. Defaults to `,`
+    ///
+    pub delimiter: Option<char>,
+    pub date_col: CsvColumn,
+    ///
+    /// `chrono` format of the date column, e.g. `%m/%d/%Y`.
+    /// Dates without a time are imported at midnight UTC.
+    ///
+    pub date_format: String,
+    pub amount_col: CsvColumn,
+    pub description_col: Option<CsvColumn>,
+    pub currency_col: Option<CsvColumn>,
+    ///
+    /// Currency of rows without a currency column. Defaults to USD
+    ///
+    pub default_currency_type: Option<String>,
+    pub sign: SignConvention,
+    ///
+    /// Category assigned to every imported transaction
+    ///
+    pub category_id: Option<Uuid>,
+}
+
+///
+/// Parses every row of a CSV statement. Rows which cannot be parsed
+/// are returned as errors describing why, so they can be reported individually.
+/// Returns `Err` if the statement itself does not match the mapping.
+///
+pub fn parse_csv(
+    contents: &[u8],
+    mapping: &CsvMappingModel
+) -> Result<Vec<Result<ParsedTransactionModel, String>>, String> {
+    let delimiter = mapping.delimiter.unwrap_or(',');
+    if !delimiter.is_ascii() {
+        return Err(format!("Delimiter '{}' is not an ASCII character", delimiter));
+    }
+
+    let mut reader = ReaderBuilder::new()
+        .has_headers(mapping.has_headers)
+        .delimiter(delimiter as u8)
+        .flexible(true)
+        .from_reader(contents);
+
+    let headers = if mapping.has_headers {
+        Some(reader.headers().map_err(|e| e.to_string())?.clone())
+    } else {
+        None
+    };
+
+    // Resolve every mapped column to its index before reading any rows
+    let date_idx = column_idx(&mapping.date_col, headers.as_ref())?;
+    let amount_idx = column_idx(&mapping.amount_col, headers.as_ref())?;
+    let description_idx = match &mapping.description_col {
+        Some(col) => Some(column_idx(col, headers.as_ref())?),
+        None => None,
+    };
+    let currency_idx = match &mapping.currency_col {
+        Some(col) => Some(column_idx(col, headers.as_ref())?),
+        None => None,
+    };
+    let default_currency_type = mapping.default_currency_type.clone()
+        .unwrap_or(USD_CURRENCY_TYPE.to_string());
+
+    Ok(
+        reader.records().map(|record| {
+            let record = record.map_err(|e| e.to_string())?;
+
+            let date = field(&record, date_idx)?;
+            let timestamp_utc = NaiveDateTime::parse_from_str(date, &mapping.date_format)
+                .or_else(|_| NaiveDate::parse_from_str(date, &mapping.date_format)
+                    .map(|d| d.and_hms_opt(0, 0, 0).unwrap()))
+                .map_err(|_| format!("Could not parse date '{}' with format '{}'", date, mapping.date_format))?
+                .and_utc();
+
+            let amount = field(&record, amount_idx)?;
+            let am = parse_amount(amount)
+                .ok_or(format!("Could not parse amount '{}'", amount))?;
+            let am = match mapping.sign {
+                SignConvention::AsIs => am,
+                SignConvention::Inverted => -am,
+            };
+
+            let currency_type = match currency_idx {
+                Some(idx) => field(&record, idx)?.to_uppercase(),
+                None => default_currency_type.clone(),
+            };
+            let notes = match description_idx {
+                Some(idx) => Some(field(&record, idx)?.to_string()).filter(|n| !n.is_empty()),
+                None => None,
+            };
+
+            Ok(ParsedTransactionModel { timestamp_utc, currency_type, am, notes, external_id: None })
+        })
+            .collect()
+    )
+}
+
+fn column_idx(col: &CsvColumn, headers: Option<&StringRecord>) -> Result<usize, String> {
+    match (col, headers) {
+        (CsvColumn::Index(idx), _) => Ok(*idx),
+        (CsvColumn::Header(name), Some(headers)) => headers.iter()
+            .position(|h| h.trim().eq_ignore_ascii_case(name.trim()))
+            .ok_or(format!("Column '{}' not found in statement headers", name)),
+        (CsvColumn::Header(name), None) => {
+            Err(format!("Column '{}' referenced by name, but the statement has no headers", name))
+        }
+    }
+}
+
+fn field(record: &StringRecord, idx: usize) -> Result<&str, String> {
+    record.get(idx)
+        .map(|f| f.trim())
+        .ok_or(format!("Row has no column {}", idx))
+}
@@ -0,0 +1,97 @@
+use sea_orm::prelude::{DateTimeUtc, Uuid};
+use serde::Serialize;
+
+pub mod csv;
+pub mod ofx;
+
+///
+/// A single transaction parsed from an imported statement,
+/// in the statement's currency
+///
+#[derive(Clone, Debug, Serialize)]
+pub struct ParsedTransactionModel {
+    pub timestamp_utc: DateTimeUtc,
+    pub currency_type: String,
+    pub am: i64,
+    pub notes: Option<String>,
+    ///
+    /// ID assigned to the transaction by the bank, such as an OFX FITID.
+    /// Used to skip transactions which have already been imported.
+    ///
+    pub external_id: Option<String>,
+}
+
+///
+/// Outcome of importing a single statement row.
+/// Exactly one of `tx` and `error` is set, unless the row parsed
+/// but could not be converted to USD.
+/// Rows which were imported previously are `skipped`.
+///
+#[derive(Debug, Serialize)]
+pub struct ImportRowModel {
+    ///
+    /// 1-based index of the row in the statement
+    ///
+    pub row: usize,
+    pub tx: Option<ParsedTransactionModel>,
+    ///
+    /// The row's amount, converted to USD
+    ///
+    pub usd_am: Option<i64>,
+    pub error: Option<String>,
+    pub skipped: bool,
+    ///
+    /// IDs of existing transactions the row probably duplicates. The row is still imported,
+    /// so preview the import to review them first
+    ///
+    pub duplicate_of: Vec<i32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReportModel {
+    pub account_id: Uuid,
+    ///
+    /// `false` if the import was a preview, and nothing was written
+    ///
+    pub committed: bool,
+    pub imported_count: usize,
+    pub rows: Vec<ImportRowModel>,
+}
+
+///
+/// Parses a decimal amount, such as `-1,234.5`, `$12.00` or `(12.34)`,
+/// into minor units. Returns `None` if the amount has more than two decimal places.
+///
+pub fn parse_amount(value: &str) -> Option<i64> {
+    let mut value = value.trim();
+    let mut negative = false;
+
+    // Accounting notation wraps negative amounts in parentheses
+    if let Some(inner) = value.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+        negative = true;
+        value = inner.trim();
+    }
+
+    // Drop currency symbols on either side of the sign, and thousands separators
+    let is_symbol = |c: char| !c.is_ascii_digit() && !"+-.".contains(c);
+    value = value.trim_start_matches(is_symbol);
+    if let Some(inner) = value.strip_prefix('-').or(value.strip_suffix('-')) {
+        negative = !negative;
+        value = inner.trim();
+    } else if let Some(inner) = value.strip_prefix('+') {
+        value = inner.trim();
+    }
+    let value = value.trim_start_matches(is_symbol).replace(',', "");
+
+    let (whole, frac) = value.split_once('.').unwrap_or((&value, ""));
+    if whole.is_empty() && frac.is_empty() { return None; }
+    if frac.len() > 2 || !whole.chars().chain(frac.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let whole = if whole.is_empty() { 0 } else { whole.parse::<i64>().ok()? };
+    let frac = format!("{:0<2}", frac).parse::<i64>().ok()?;
+    let am = whole.checked_mul(100)?.checked_add(frac)?;
+
+    Some(if negative { -am } else { am })
+}
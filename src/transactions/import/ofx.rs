@@ -0,0 +1,124 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, TimeZone, Utc};
+
+use crate::currency_conv_provider::USD_CURRENCY_TYPE;
+
+use super::{parse_amount, ParsedTransactionModel};
+
+///
+/// Parses the transactions of an OFX or QFX statement. Both the SGML (OFX 1.x)
+/// and XML (OFX 2.x) forms are supported, since only the tags are inspected.
+/// Transactions which cannot be parsed are returned as errors describing why.
+/// Returns `Err` if the file is not an OFX statement.
+///
+pub fn parse_ofx(contents: &str) -> Result<Vec<Result<ParsedTransactionModel, String>>, String> {
+    if !contents.contains("<OFX>") && !contents.contains("<ofx>") {
+        return Err(String::from("File is not an OFX statement"));
+    }
+
+    let mut currency_type = String::from(USD_CURRENCY_TYPE);
+    let mut txs = vec![];
+    let mut current: Option<Vec<(String, String)>> = None;
+
+    for (tag, value) in tags(contents) {
+        match tag.as_str() {
+            "CURDEF" => currency_type = value.to_uppercase(),
+            "STMTTRN" => current = Some(vec![]),
+            "/STMTTRN" => {
+                if let Some(fields) = current.take() {
+                    txs.push(fields);
+                }
+            },
+            _ => {
+                if let Some(fields) = current.as_mut() {
+                    fields.push((tag, value));
+                }
+            }
+        }
+    }
+
+    // The statement currency is only known once the whole file is read,
+    // as CURDEF may come after the transaction list
+    Ok(txs.into_iter().map(|fields| parse_stmttrn(&fields, &currency_type)).collect())
+}
+
+fn parse_stmttrn(fields: &[(String, String)], currency_type: &str) -> Result<ParsedTransactionModel, String> {
+    let get = |name: &str| fields.iter()
+        .find(|(tag, value)| tag == name && !value.is_empty())
+        .map(|(_, value)| value.as_str());
+
+    let fit_id = get("FITID").ok_or(String::from("Transaction has no FITID"))?;
+    let date = get("DTPOSTED").ok_or(format!("Transaction {} has no DTPOSTED", fit_id))?;
+    let amount = get("TRNAMT").ok_or(format!("Transaction {} has no TRNAMT", fit_id))?;
+
+    let timestamp_utc = parse_ofx_date(date)
+        .ok_or(format!("Could not parse date '{}' of transaction {}", date, fit_id))?;
+    let am = parse_amount(amount)
+        .ok_or(format!("Could not parse amount '{}' of transaction {}", amount, fit_id))?;
+
+    // Transactions may override the statement currency
+    let currency_type = get("CURSYM")
+        .map(|c| c.to_uppercase())
+        .unwrap_or(currency_type.to_string());
+
+    let notes = match (get("NAME"), get("MEMO")) {
+        (Some(name), Some(memo)) if name != memo => Some(format!("{} - {}", name, memo)),
+        (Some(name), _) => Some(name.to_string()),
+        (None, memo) => memo.map(|m| m.to_string()),
+    };
+
+    Ok(
+        ParsedTransactionModel {
+            timestamp_utc,
+            currency_type,
+            am,
+            notes,
+            external_id: Some(fit_id.to_string()),
+        }
+    )
+}
+
+///
+/// Splits the file into `(TAG, value)` pairs. SGML elements are often left
+/// unclosed, so each value is the text between a tag and the next tag.
+///
+fn tags(contents: &str) -> impl Iterator<Item = (String, String)> + '_ {
+    contents.split('<').skip(1).filter_map(|part| {
+        let (tag, value) = part.split_once('>')?;
+        Some((tag.trim().to_uppercase(), decode_entities(value.trim())))
+    })
+}
+
+fn decode_entities(value: &str) -> String {
+    value.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+///
+/// Parses an OFX date, `YYYYMMDD[HHMMSS[.XXX]][[gmt offset[:tz name]]]`.
+/// Dates without an offset are taken as UTC.
+///
+fn parse_ofx_date(value: &str) -> Option<DateTime<Utc>> {
+    let (date, tz) = match value.split_once('[') {
+        Some((date, tz)) => (date, Some(tz.trim_end_matches(']'))),
+        None => (value, None),
+    };
+    let digits = date.split('.').next()?;
+
+    let naive = match digits.len() {
+        8 => NaiveDate::parse_from_str(digits, "%Y%m%d").ok()?.and_hms_opt(0, 0, 0)?,
+        12 => NaiveDateTime::parse_from_str(&format!("{}00", digits), "%Y%m%d%H%M%S").ok()?,
+        14 => NaiveDateTime::parse_from_str(digits, "%Y%m%d%H%M%S").ok()?,
+        _ => return None,
+    };
+
+    let offset_hours = match tz {
+        Some(tz) => tz.split(':').next()?.parse::<f64>().ok()?,
+        None => 0.0,
+    };
+    let offset = FixedOffset::east_opt((offset_hours * 3600.0) as i32)?;
+
+    Some(offset.from_local_datetime(&naive).single()?.with_timezone(&Utc))
+}
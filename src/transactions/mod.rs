@@ -1,21 +1,75 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::{HashMap, HashSet}, sync::Arc};
+
+use chrono::{Duration, NaiveDate};
 
 use async_trait::async_trait;
-use sea_orm::{prelude::{Expr, Uuid}, ColumnTrait, DbConn, EntityTrait, PaginatorTrait, QueryFilter, Set, TransactionTrait};
+use log::warn;
+use sea_orm::{prelude::{DateTimeUtc, Expr, Uuid}, ActiveValue::NotSet, ColumnTrait, Condition, ConnectionTrait, DbConn, DbErr, EntityTrait, FromQueryResult, IntoActiveModel, ItemsAndPagesNumber, PaginatorTrait, QueryFilter, QueryOrder, QuerySelect, Set, SqlErr, TransactionTrait};
+use serde::Deserialize;
 
-use schmeconomics_entities::{categories, prelude::*, transactions};
+use schmeconomics_entities::{categories, idempotency_keys, payees, prelude::*, tags, transaction_splits, transaction_tags, transactions};
 use utils_rs::date_time_provider::DynDateTimeProvider;
 
-use crate::{currency_conv_provider::{DynCurrencyConversionProvider, USD_CURRENCY_TYPE}, db_utils::{validate_user_account_role, Role}};
+use crate::{attachments::{delete_files, delete_tx_attachments, storage::DynAttachmentStorage}, currency_conv_provider::{apply_rate, DynCurrencyConversionProvider, USD_CURRENCY_TYPE}, db_utils::{adjust_category_balances, validate_user_account_role, LedgerContext, LedgerSource, Role}, rules::engine::{RuleInput, RuleSet}};
 
-use {error::*, models::*};
+use {duplicates::*, error::*, export::*, import::*, models::*};
 
+pub mod duplicates;
 pub mod error;
+pub mod export;
+pub mod import;
 pub mod models;
 pub mod routes;
 
 pub type DynTransactionService = Arc<dyn TransactionService + Send + Sync>;
 
+///
+/// Maximum length of an idempotency key
+/// 
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+///
+/// Larger requested page sizes are clamped to this
+/// 
+const MAX_PAGE_SIZE: u64 = 100;
+
+///
+/// Maximum number of transactions returned by one scroll
+/// 
+const MAX_SCROLL_LIMIT: u64 = 100;
+
+#[derive(Deserialize)]
+pub struct Config {
+    ///
+    /// How long, in seconds, an idempotency key replays its original result
+    /// 
+    pub idempotency_key_lt_s: i64,
+    ///
+    /// How often, in seconds, the background job purges expired idempotency keys
+    /// 
+    pub idempotency_purge_interval_s: u64,
+    ///
+    /// How far, in seconds, a client-supplied timestamp may be in the future
+    /// 
+    pub max_future_timestamp_s: i64,
+    ///
+    /// How far apart, in seconds, transactions may be and still be flagged as duplicates
+    /// 
+    pub duplicate_window_s: i64,
+    ///
+    /// How much, as a percentage of the larger amount, the amounts of duplicates may differ
+    /// 
+    pub duplicate_am_tolerance_pct: f64,
+    ///
+    /// How long, in seconds, deleted transactions stay in the trash before being purged
+    /// 
+    pub trash_retention_s: i64,
+    ///
+    /// How often, in seconds, the background job purges expired transactions from the trash
+    /// 
+    pub trash_purge_interval_s: u64,
+}
+
 #[cfg(test)]
 mod test;
 
@@ -25,37 +79,598 @@ pub trait TransactionService {
         &self, 
         user_id: Uuid, 
         get_req: GetTransactionReqModel,
-    ) -> Result<Vec<TransactionModel>>;
+    ) -> Result<TransactionPageModel>;
+
+    ///
+    /// Fetches transactions before or after a cursor, newest-first.
+    /// Unlike `get_transactions`, pages are stable while transactions are being added.
+    /// 
+    async fn scroll_transactions(
+        &self,
+        user_id: Uuid,
+        scroll_req: ScrollTransactionsReqModel,
+    ) -> Result<TransactionScrollModel>;
 
+    ///
+    /// Creates the transactions. When an idempotency key is given, retries
+    /// with the same key within the configured window replay the original result.
+    /// 
     async fn create_transactions(
         &self, 
         user_id: Uuid, 
         txs: CreateTransactionsModel
-    ) -> Result<()>;
+    ) -> Result<CreateTransactionsResultModel>;
+
+    ///
+    /// Updates a single transaction, moving its amount from the old category
+    /// balance to the new one.
+    /// 
+    async fn update_transaction(
+        &self,
+        user_id: Uuid,
+        update_req: UpdateTransactionModel,
+    ) -> Result<TransactionModel>;
 
+    ///
+    /// Applies the same category, tag and notes changes to every selected transaction
+    /// in a single DB transaction, moving their amounts between category balances.
+    /// 
+    async fn bulk_update_transactions(
+        &self,
+        user_id: Uuid,
+        update_req: BulkUpdateTransactionsModel,
+    ) -> Result<BulkUpdateResultModel>;
+
+    ///
+    /// Moves the transactions to the trash, reversing them from their category balances.
+    /// Returns when they will be purged for good.
+    /// 
     async fn delete_transactions(
         &self,
         user_id: Uuid, 
         delete_req: DeleteTransactionsModel,
-    ) -> Result<()>;
+    ) -> Result<DateTimeUtc>;
+
+    ///
+    /// Lists the account's deleted transactions which have not been purged yet
+    /// 
+    async fn get_trash(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<TrashedTransactionModel>>;
+
+    ///
+    /// Moves transactions out of the trash, applying them to their category balances again
+    /// 
+    async fn restore_transactions(
+        &self,
+        user_id: Uuid,
+        restore_req: RestoreTransactionsModel,
+    ) -> Result<Vec<TransactionModel>>;
+
+    ///
+    /// Permanently deletes every transaction whose retention in the trash has expired,
+    /// along with its splits, tags and attachments. Returns the number of transactions deleted.
+    /// 
+    async fn purge_trash(&self) -> Result<u64>;
+
+    ///
+    /// Imports the rows of a CSV bank statement into the account.
+    /// Rows which cannot be imported are reported individually, and do not fail the import.
+    /// 
+    async fn import_csv(
+        &self,
+        user_id: Uuid,
+        import_req: ImportCsvModel,
+    ) -> Result<ImportReportModel>;
+
+    ///
+    /// Imports the transactions of an OFX or QFX statement into the account.
+    /// Transactions whose FITID was already imported into the account are skipped,
+    /// so overlapping statements can be imported safely.
+    /// 
+    async fn import_ofx(
+        &self,
+        user_id: Uuid,
+        import_req: ImportOfxModel,
+    ) -> Result<ImportReportModel>;
+
+    ///
+    /// Streams every transaction matching the filters as CSV or NDJSON,
+    /// with category names in place of category IDs
+    /// 
+    async fn export_transactions(
+        &self,
+        user_id: Uuid,
+        export_req: ExportTransactionsReqModel,
+    ) -> Result<TransactionExportStream>;
+
+    ///
+    /// Lists transactions which are probably duplicates of earlier ones in the account:
+    /// within the configured window, with similar amounts and notes
+    /// 
+    async fn find_duplicates(
+        &self,
+        user_id: Uuid,
+        find_req: FindDuplicatesReqModel,
+    ) -> Result<Vec<SuspectedDuplicateModel>>;
+
+    ///
+    /// Deletes every idempotency key older than the configured window.
+    /// Returns the number of keys deleted.
+    /// 
+    async fn purge_idempotency_keys(&self) -> Result<u64>;
 }
 
 pub struct DbConnTransactionService {
     db: DbConn,
     dt_provider: DynDateTimeProvider,
     cc_provider: DynCurrencyConversionProvider,
+    storage: DynAttachmentStorage,
+    config: Config,
+}
+
+///
+/// A transaction ready to be inserted, with its amount already converted to USD
+/// 
+struct NewTransaction {
+    category_id: Option<Uuid>,
+    timestamp: DateTimeUtc,
+    amount: i64,
+    notes: Option<String>,
+    external_id: Option<String>,
+    orig_currency_type: String,
+    orig_amount: i64,
+    exchange_rate: f64,
+    ///
+    /// Category splits, in USD. When set, `category_id` is `None`
+    /// 
+    splits: Vec<SplitModel>,
+    tag_ids: Vec<Uuid>,
+    payee_id: Option<Uuid>,
 }
 
 impl DbConnTransactionService {
     pub fn new_dyn(
         db: DbConn, 
         dt_provider: DynDateTimeProvider, 
-        cc_provider: DynCurrencyConversionProvider
+        cc_provider: DynCurrencyConversionProvider,
+        storage: DynAttachmentStorage,
+        config: Config,
     ) -> DynTransactionService {
         Arc::new(Self {
-            db, dt_provider, cc_provider
+            db, dt_provider, cc_provider, storage, config
         })
     }
+
+    fn ledger_ctx(&self, source: LedgerSource, user_id: Uuid) -> LedgerContext {
+        LedgerContext { source, user_id: Some(user_id), timestamp: self.dt_provider.utc_now() }
+    }
+
+    ///
+    /// Inserts the transactions into the account, and adds
+    /// each amount to its category's balance
+    /// 
+    async fn insert_transactions(
+        &self,
+        db_tx: &impl ConnectionTrait,
+        account_id: Uuid,
+        user_id: Uuid,
+        txs: Vec<NewTransaction>,
+    ) -> Result<Vec<TransactionModel>> {
+        // Mapping of category total balance changes
+        let mut totals = HashMap::new();
+        // All transaction insertions, with their splits and tags
+        let mut insertions = vec![];
+
+        for tx in txs {
+            // Add a new category total, or add to the one already existing
+            *totals.entry(tx.category_id).or_insert(0i64) += tx.amount;
+            for split in &tx.splits {
+                *totals.entry(Some(split.cat_id)).or_insert(0i64) += split.am;
+            }
+
+            // Create a new transaction to add to the database
+            insertions.push((
+                transactions::ActiveModel { 
+                    account_id:     Set(account_id), 
+                    user_id:        Set(Some(user_id)), 
+                    category_id:    Set(tx.category_id), 
+                    timestamp:      Set(tx.timestamp),
+                    amount:         Set(tx.amount), 
+                    notes:          Set(tx.notes), 
+                    is_refill:      Set(false), 
+                    external_id:    Set(tx.external_id),
+                    payee_id:       Set(tx.payee_id),
+                    orig_currency_type: Set(Some(tx.orig_currency_type)),
+                    orig_amount:    Set(Some(tx.orig_amount)),
+                    exchange_rate:  Set(Some(tx.exchange_rate)),
+
+                    ..Default::default()
+                },
+                tx.splits,
+                tx.tag_ids,
+            ));
+        }
+
+        let mut inserted = vec![];
+        if insertions.iter().all(|(_, splits, tag_ids)| splits.is_empty() && tag_ids.is_empty()) {
+            if !insertions.is_empty() {
+                inserted = Transactions::insert_many(insertions.into_iter().map(|(tx, _, _)| tx))
+                    .exec_with_returning_many(db_tx).await?
+                    .into_iter()
+                    .map(|tx| tx.into())
+                    .collect();
+            }
+        } else {
+            // Splits and tags reference their transaction, so each transaction is inserted individually for its ID
+            for (tx, splits, tag_ids) in insertions {
+                let tx = Transactions::insert(tx).exec_with_returning(db_tx).await?;
+                self.insert_splits(db_tx, tx.id, splits.clone()).await?;
+                self.insert_tags(db_tx, tx.id, tag_ids.clone()).await?;
+                inserted.push(TransactionModel { splits, tag_ids, ..tx.into() });
+            }
+        }
+        adjust_category_balances(db_tx, totals, &self.ledger_ctx(LedgerSource::Transaction, user_id)).await?;
+
+        Ok(inserted)
+    }
+
+    ///
+    /// Converts each parsed statement row to USD and, unless `preview` is set,
+    /// inserts every row which converted successfully
+    /// 
+    async fn import_rows(
+        &self,
+        user_id: Uuid,
+        account_id: Uuid,
+        category_id: Option<Uuid>,
+        rows: Vec<std::result::Result<ParsedTransactionModel, String>>,
+        preview: bool,
+    ) -> Result<ImportReportModel> {
+        if let Some(cat_id) = category_id {
+            self.validate_category(&self.db, account_id, cat_id).await?;
+        }
+
+        // Find the external IDs which have already been imported into the account
+        let external_ids = rows.iter()
+            .filter_map(|row| row.as_ref().ok().and_then(|tx| tx.external_id.clone()))
+            .collect::<Vec<String>>();
+        let mut seen_ids = self.imported_external_ids(&self.db, account_id, external_ids).await?;
+
+        let rule_set = RuleSet::load(&self.db, account_id).await?;
+        // Rates are looked up once per currency and date, rather than once per row
+        let mut rates = HashMap::new();
+        let mut report_rows = vec![];
+        let mut new_txs = vec![];
+        // Index of the report row for each new transaction
+        let mut new_tx_rows = vec![];
+
+        for (idx, row) in rows.into_iter().enumerate() {
+            let mut report_row = ImportRowModel { row: idx + 1, tx: None, usd_am: None, error: None, skipped: false, duplicate_of: vec![] };
+            match row {
+                // Skip transactions already in the account, or repeated within the statement
+                Ok(parsed) if parsed.external_id.as_ref().is_some_and(|id| !seen_ids.insert(id.clone())) => {
+                    report_row.skipped = true;
+                    report_row.tx = Some(parsed);
+                },
+                Ok(parsed) => {
+                    match self.convert_on_cached(&mut rates, &parsed.currency_type, parsed.am, parsed.timestamp_utc).await {
+                        Ok((am, rate)) => {
+                            let mut new_tx = NewTransaction { 
+                                category_id, 
+                                timestamp: parsed.timestamp_utc, 
+                                amount: am, 
+                                notes: parsed.notes.clone(), 
+                                external_id: parsed.external_id.clone(),
+                                orig_currency_type: parsed.currency_type.clone(),
+                                orig_amount: parsed.am,
+                                exchange_rate: rate,
+                                splits: vec![],
+                                tag_ids: vec![],
+                                payee_id: None,
+                            };
+                            apply_rules(&rule_set, None, &mut new_tx);
+                            new_txs.push(new_tx);
+                            new_tx_rows.push(idx);
+                            report_row.usd_am = Some(am);
+                        },
+                        Err(e) => report_row.error = Some(e.to_string()),
+                    }
+                    report_row.tx = Some(parsed);
+                },
+                Err(e) => report_row.error = Some(e),
+            }
+            report_rows.push(report_row);
+        }
+
+        let keys = new_txs.iter()
+            .map(|tx| DuplicateKey { amount: tx.amount, timestamp: tx.timestamp, notes: tx.notes.as_deref() })
+            .collect::<Vec<DuplicateKey>>();
+        for (row_idx, matches) in new_tx_rows.iter().zip(self.match_duplicates(account_id, &keys).await?) {
+            report_rows[*row_idx].duplicate_of = matches.into_iter().map(|tx| tx.id).collect();
+        }
+
+        let mut imported_count = new_txs.len();
+        if !preview {
+            let db_tx = self.db.begin().await?;
+
+            // An overlapping import of the same statement may have committed since the
+            // external IDs were checked, so they are checked again before inserting
+            let external_ids = new_txs.iter().filter_map(|tx| tx.external_id.clone()).collect();
+            let committed_ids = self.imported_external_ids(&db_tx, account_id, external_ids).await?;
+            if !committed_ids.is_empty() {
+                let mut kept = vec![];
+                for (tx, row_idx) in new_txs.into_iter().zip(new_tx_rows) {
+                    if tx.external_id.as_ref().is_some_and(|id| committed_ids.contains(id)) {
+                        report_rows[row_idx].skipped = true;
+                        report_rows[row_idx].usd_am = None;
+                    } else {
+                        kept.push(tx);
+                    }
+                }
+                new_txs = kept;
+                imported_count = new_txs.len();
+            }
+
+            self.insert_transactions(&db_tx, account_id, user_id, new_txs).await?;
+            db_tx.commit().await?;
+        }
+
+        Ok(
+            ImportReportModel { 
+                account_id, 
+                committed: !preview, 
+                imported_count, 
+                rows: report_rows, 
+            }
+        )
+    }
+
+    ///
+    /// Returns which of `external_ids` have already been imported into the account
+    /// 
+    async fn imported_external_ids(
+        &self,
+        db: &impl ConnectionTrait,
+        account_id: Uuid,
+        external_ids: Vec<String>,
+    ) -> Result<HashSet<String>> {
+        if external_ids.is_empty() {
+            return Ok(HashSet::new());
+        }
+
+        Ok(
+            Transactions::find()
+                .filter(transactions::Column::AccountId.eq(account_id))
+                .filter(transactions::Column::ExternalId.is_in(external_ids))
+                .all(db).await?
+                .into_iter()
+                .filter_map(|tx| tx.external_id)
+                .collect()
+        )
+    }
+
+    async fn insert_splits(&self, db_tx: &impl ConnectionTrait, tx_id: i32, splits: Vec<SplitModel>) -> Result<()> {
+        if splits.is_empty() {
+            return Ok(());
+        }
+
+        TransactionSplits::insert_many(
+            splits.into_iter().map(|split| transaction_splits::ActiveModel {
+                transaction_id: Set(tx_id),
+                category_id:    Set(split.cat_id),
+                amount:         Set(split.am),
+
+                ..Default::default()
+            })
+        ).exec(db_tx).await?;
+
+        Ok(())
+    }
+
+    async fn insert_tags(&self, db_tx: &impl ConnectionTrait, tx_id: i32, tag_ids: Vec<Uuid>) -> Result<()> {
+        if tag_ids.is_empty() {
+            return Ok(());
+        }
+
+        TransactionTags::insert_many(
+            tag_ids.into_iter().map(|tag_id| transaction_tags::ActiveModel {
+                transaction_id: Set(tx_id),
+                tag_id:         Set(tag_id),
+            })
+        ).exec(db_tx).await?;
+
+        Ok(())
+    }
+
+    ///
+    /// Ensures every tag belongs to the account, returning the tag IDs without duplicates
+    /// 
+    async fn validate_tags(&self, db: &impl ConnectionTrait, account_id: Uuid, tag_ids: Vec<Uuid>) -> Result<Vec<Uuid>> {
+        let tag_ids = tag_ids.into_iter().collect::<HashSet<Uuid>>();
+        if tag_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let found = Tags::find()
+            .filter(tags::Column::AccountId.eq(account_id))
+            .filter(tags::Column::Id.is_in(tag_ids.iter().copied()))
+            .all(db).await?
+            .into_iter()
+            .map(|tag| tag.id)
+            .collect::<HashSet<Uuid>>();
+        if let Some(missing) = tag_ids.iter().find(|id| !found.contains(id)) {
+            return Err(Error::TagNotFound(*missing));
+        }
+
+        let mut tag_ids = tag_ids.into_iter().collect::<Vec<Uuid>>();
+        tag_ids.sort();
+        Ok(tag_ids)
+    }
+
+    ///
+    /// Validates that the splits sum to `amount` and belong to the account, then converts
+    /// each to USD at `rate`. Rounding differences are added to the last split, so the
+    /// converted splits always sum to `usd_amount`.
+    /// 
+    async fn convert_splits(
+        &self,
+        db: &impl ConnectionTrait,
+        account_id: Uuid,
+        amount: i64,
+        usd_amount: i64,
+        rate: f64,
+        splits: Vec<CreateSplitModel>,
+    ) -> Result<Vec<SplitModel>> {
+        if splits.is_empty() {
+            return Err(Error::InvalidSplits(String::from("at least one split is required")));
+        }
+        let split_total = splits.iter().map(|split| split.amount).sum::<i64>();
+        if split_total != amount {
+            return Err(Error::InvalidSplits(format!("splits sum to {}, but the transaction amount is {}", split_total, amount)));
+        }
+
+        let mut converted = vec![];
+        for split in splits {
+            self.validate_category(db, account_id, split.category_id).await?;
+            converted.push(
+                SplitModel { 
+                    cat_id: split.category_id, 
+                    am: apply_rate(split.amount, rate),
+                }
+            );
+        }
+
+        let diff = usd_amount - converted.iter().map(|split| split.am).sum::<i64>();
+        if let Some(last) = converted.last_mut() {
+            last.am += diff;
+        }
+
+        Ok(converted)
+    }
+
+    ///
+    /// Finds the existing transactions each of the new transactions probably duplicates.
+    /// Transfers and refills are never considered duplicates.
+    /// 
+    async fn match_duplicates(&self, account_id: Uuid, keys: &[DuplicateKey<'_>]) -> Result<Vec<Vec<transactions::Model>>> {
+        let (Some(min_ts), Some(max_ts)) = (keys.iter().map(|key| key.timestamp).min(), keys.iter().map(|key| key.timestamp).max()) else {
+            return Ok(vec![]);
+        };
+        let window = Duration::seconds(self.config.duplicate_window_s);
+        let candidates = Transactions::find()
+            .filter(transactions::Column::AccountId.eq(account_id))
+            .filter(transactions::Column::DeleteOn.is_null())
+            .filter(transactions::Column::TransferId.is_null())
+            .filter(transactions::Column::IsRefill.eq(false))
+            .filter(transactions::Column::Timestamp.gte(min_ts - window))
+            .filter(transactions::Column::Timestamp.lte(max_ts + window))
+            .order_by_asc(transactions::Column::Id)
+            .all(&self.db).await?;
+
+        Ok(
+            keys.iter()
+                .map(|key| {
+                    candidates.iter()
+                        .filter(|tx| is_probable_duplicate(&self.config, key, &DuplicateKey::from(*tx)))
+                        .cloned()
+                        .collect()
+                })
+                .collect()
+        )
+    }
+
+    ///
+    /// Returns the result stored for the idempotency key, or `None` if the key is unused.
+    /// Expired keys are deleted, so the key can be used again.
+    /// 
+    async fn find_idempotent_result(
+        &self,
+        user_id: Uuid,
+        account_id: Uuid,
+        key: &str,
+    ) -> Result<Option<CreateTransactionsResultModel>> {
+        let Some(ex_key) = IdempotencyKeys::find_by_id((user_id, key.to_string())).one(&self.db).await? else {
+            return Ok(None);
+        };
+
+        if ex_key.created_on_utc + Duration::seconds(self.config.idempotency_key_lt_s) < self.dt_provider.utc_now() {
+            IdempotencyKeys::delete_by_id((user_id, key.to_string())).exec(&self.db).await?;
+            return Ok(None);
+        }
+        if ex_key.account_id != account_id {
+            return Err(Error::IdempotencyKeyReused(key.to_string()));
+        }
+
+        Ok(Some(
+            CreateTransactionsResultModel {
+                txs: serde_json::from_str(&ex_key.response)?,
+                replayed: true,
+                duplicates: vec![],
+            }
+        ))
+    }
+
+    async fn find_payee(&self, db: &impl ConnectionTrait, account_id: Uuid, payee_id: Uuid) -> Result<payees::Model> {
+        Payees::find_by_id(payee_id)
+            .filter(payees::Column::AccountId.eq(account_id))
+            .one(db).await?
+            .ok_or(Error::PayeeNotFound(payee_id))
+    }
+
+    async fn validate_category(&self, db: &impl ConnectionTrait, account_id: Uuid, cat_id: Uuid) -> Result<()> {
+        let cat = Categories::find_by_id(cat_id)
+            .filter(categories::Column::AccountId.eq(account_id))
+            .one(db).await?;
+
+        return if cat.is_some() {
+            Ok(())
+        } else {
+            Err(Error::CategoryNotFound(cat_id))
+        };
+    }
+
+    ///
+    /// Rejects timestamps further in the future than the configured allowance
+    /// 
+    fn validate_timestamp(&self, ts: DateTimeUtc) -> Result<DateTimeUtc> {
+        if ts > self.dt_provider.utc_now() + Duration::seconds(self.config.max_future_timestamp_s) {
+            return Err(Error::InvalidField("timestamp_utc", String::from("cannot be in the future")));
+        }
+        Ok(ts)
+    }
+
+    ///
+    /// Converts the amount to USD, at the rate on the transaction's date when it was backdated
+    /// and the provider has a rate for that date. Otherwise the latest rate is used.
+    /// 
+    async fn convert_on(&self, currency_type: &str, am: i64, timestamp: Option<DateTimeUtc>) -> Result<(i64, f64)> {
+        if let Some(ts) = timestamp.filter(|_| currency_type != USD_CURRENCY_TYPE) {
+            if let Some(rate) = self.cc_provider.get_historical_conversion(currency_type, USD_CURRENCY_TYPE, ts.date_naive()).await? {
+                return Ok((apply_rate(am, rate), rate));
+            }
+            warn!("No {} rate for {}, converting at the latest rate", currency_type, ts.date_naive());
+        }
+        Ok(self.cc_provider.convert_with_rate(currency_type, USD_CURRENCY_TYPE, am).await?)
+    }
+
+    ///
+    /// Same as `convert_on`, but reuses the rate already found for the currency on the same date
+    /// 
+    async fn convert_on_cached(
+        &self,
+        rates: &mut HashMap<(String, NaiveDate), f64>,
+        currency_type: &str,
+        am: i64,
+        timestamp: DateTimeUtc,
+    ) -> Result<(i64, f64)> {
+        let key = (currency_type.to_string(), timestamp.date_naive());
+        if let Some(rate) = rates.get(&key) {
+            return Ok((apply_rate(am, *rate), *rate));
+        }
+
+        let (usd_am, rate) = self.convert_on(currency_type, am, Some(timestamp)).await?;
+        rates.insert(key, rate);
+        Ok((usd_am, rate))
+    }
 }
 
 #[async_trait]
@@ -64,88 +679,511 @@ impl TransactionService for DbConnTransactionService {
         &self, 
         user_id: Uuid, 
         get_req: GetTransactionReqModel,
-    ) -> Result<Vec<TransactionModel>> {
+    ) -> Result<TransactionPageModel> {
         validate_user_account_role(&self.db, user_id, get_req.account_id, Role::Read).await?;
         let filters = get_req.filters.unwrap_or(vec![]);
-        let page_size = get_req.page_size.unwrap_or(15);
+        let page_size = get_req.page_size.unwrap_or(15).min(MAX_PAGE_SIZE);
+        if page_size == 0 {
+            return Err(Error::InvalidField("page_size", String::from("must be at least 1")));
+        }
         let page_idx = get_req.page_idx.unwrap_or(0);
+        let sort = get_req.sort.unwrap_or_default();
 
         // Create the query for the particular account id, and apply each filter
         let mut query = Transactions::find()
-            .filter(transactions::Column::AccountId.eq(get_req.account_id));
+            .filter(transactions::Column::AccountId.eq(get_req.account_id))
+            .filter(transactions::Column::DeleteOn.is_null());
 
         for filter in filters {
             query = filter.into_select_query(query);
         }
 
-        // Paginate the results, and fetch the current page
-        let pagination = query.paginate(&self.db, page_size);
+        // Sum the amounts of every transaction matching the filters
+        #[derive(FromQueryResult)]
+        struct SumQuery { total: Option<i64> }
+        let total_am = query.clone().select_only()
+            .column_as(transactions::Column::Amount.sum(), "total")
+            .into_model::<SumQuery>().one(&self.db)
+            .await?.and_then(|sum| sum.total).unwrap_or(0);
+
+        // Paginate the sorted results, and fetch the current page
+        let pagination = sort.apply(query).paginate(&self.db, page_size);
+        let ItemsAndPagesNumber { number_of_items, number_of_pages } = pagination.num_items_and_pages().await?;
         let page = pagination.fetch_page(page_idx).await?;
 
         // Return the transactions in that collection
-        Ok(page.into_iter().map(|tx| tx.into()).collect())
+        Ok(
+            TransactionPageModel {
+                items: with_details(&self.db, page).await?,
+                total_count: number_of_items,
+                page_count: number_of_pages,
+                total_am,
+            }
+        )
+    }
+
+    async fn scroll_transactions(
+        &self,
+        user_id: Uuid,
+        scroll_req: ScrollTransactionsReqModel,
+    ) -> Result<TransactionScrollModel> {
+        validate_user_account_role(&self.db, user_id, scroll_req.account_id, Role::Read).await?;
+        let filters = scroll_req.filters.unwrap_or(vec![]);
+        let limit = scroll_req.limit.unwrap_or(15);
+        if !(1..=MAX_SCROLL_LIMIT).contains(&limit) {
+            return Err(Error::InvalidField("limit", format!("must be 1 - {}", MAX_SCROLL_LIMIT)));
+        }
+        let dir = scroll_req.dir.unwrap_or(ScrollDir::Older);
+        let cursor = match scroll_req.cursor {
+            Some(cursor) => Some(TransactionCursor::decode(&cursor).ok_or(Error::InvalidCursor(cursor))?),
+            None => None,
+        };
+
+        // Create the query for the particular account id, and apply each filter
+        let mut query = Transactions::find()
+            .filter(transactions::Column::AccountId.eq(scroll_req.account_id))
+            .filter(transactions::Column::DeleteOn.is_null());
+
+        for filter in filters {
+            query = filter.into_select_query(query);
+        }
+
+        // Fetch one more transaction than the limit, to check if there are more to scroll
+        let mut txs = match dir {
+            ScrollDir::Older => {
+                if let Some(cursor) = cursor {
+                    query = query.filter(transactions::Column::Id.lt(cursor.id));
+                }
+                query.order_by_desc(transactions::Column::Id)
+            },
+            ScrollDir::Newer => {
+                if let Some(cursor) = cursor {
+                    query = query.filter(transactions::Column::Id.gt(cursor.id));
+                }
+                query.order_by_asc(transactions::Column::Id)
+            },
+        }
+            .limit(limit + 1)
+            .all(&self.db).await?;
+
+        let has_more = txs.len() as u64 > limit;
+        txs.truncate(limit as usize);
+        if let ScrollDir::Newer = dir {
+            txs.reverse();
+        }
+
+        let older_cursor = match dir {
+            ScrollDir::Older if !has_more => None,
+            _ => txs.last().map(|tx| TransactionCursor { id: tx.id }.encode()),
+        };
+        // Keep the provided cursor when there is nothing newer, so clients can poll with it
+        let newer_cursor = txs.first().map(|tx| TransactionCursor { id: tx.id })
+            .or(cursor)
+            .map(|cursor| cursor.encode());
+
+        Ok(
+            TransactionScrollModel { 
+                items: with_details(&self.db, txs).await?, 
+                older_cursor, 
+                newer_cursor, 
+            }
+        )
     }
 
     async fn create_transactions(
         &self, 
         user_id: Uuid, 
         create_req: CreateTransactionsModel,
-    ) -> Result<()> {
+    ) -> Result<CreateTransactionsResultModel> {
         validate_user_account_role(&self.db, user_id, create_req.account_id, Role::Write).await?;
 
-        // Mapping of category total balance changes
-        let mut totals = HashMap::new();
-        // All transaction insertions
-        let mut insertions = vec![];
+        // Replay the original result if the request has already been made
+        if let Some(key) = &create_req.idempotency_key {
+            if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+                return Err(Error::InvalidField("idempotency_key", format!("must be 1 - {} characters", MAX_IDEMPOTENCY_KEY_LEN)));
+            }
+            if let Some(res) = self.find_idempotent_result(user_id, create_req.account_id, key).await? {
+                return Ok(res);
+            }
+        }
 
+        let now = self.dt_provider.utc_now();
+        let rule_set = RuleSet::load(&self.db, create_req.account_id).await?;
+        let mut new_txs = vec![];
         for tx in create_req.txs {
-            // Add a new category total, or add to the one already existing
-            let am = self.cc_provider.convert(&tx.currency_type, USD_CURRENCY_TYPE, tx.amount).await?;
-            *totals.entry(tx.category_id).or_insert(0i64) += am;
+            let timestamp = match tx.timestamp_utc {
+                Some(ts) => self.validate_timestamp(ts)?,
+                None => now,
+            };
+            let (am, rate) = self.convert_on(&tx.currency_type, tx.amount, tx.timestamp_utc).await?;
+            let payee = match tx.payee_id {
+                Some(payee_id) => Some(self.find_payee(&self.db, create_req.account_id, payee_id).await?),
+                None => None,
+            };
+            let (category_id, splits) = match (tx.category_id, tx.splits) {
+                (Some(cat_id), None) => (Some(cat_id), vec![]),
+                (None, Some(splits)) => {
+                    (None, self.convert_splits(&self.db, create_req.account_id, tx.amount, am, rate, splits).await?)
+                },
+                (None, None) => (None, vec![]),
+                (Some(_), Some(_)) => return Err(Error::InvalidSplits(String::from("category_id and splits cannot both be set"))),
+            };
+            let tag_ids = self.validate_tags(&self.db, create_req.account_id, tx.tag_ids.unwrap_or_default()).await?;
+            let mut new_tx = NewTransaction { 
+                category_id, 
+                timestamp, 
+                amount: am, 
+                notes: Some(tx.notes), 
+                external_id: None,
+                orig_currency_type: tx.currency_type,
+                orig_amount: tx.amount,
+                exchange_rate: rate,
+                splits,
+                tag_ids,
+                payee_id: tx.payee_id,
+            };
+            apply_rules(&rule_set, payee.as_ref().map(|payee| payee.name.as_str()), &mut new_tx);
+            // Fall back to the payee's default category
+            if new_tx.category_id.is_none() && new_tx.splits.is_empty() {
+                new_tx.category_id = payee.and_then(|payee| payee.default_category_id);
+            }
+            new_txs.push(new_tx);
+        }
 
-            // Create a new transaction to add to the database
-            insertions.push(
-                transactions::ActiveModel { 
-                    account_id:     Set(create_req.account_id), 
-                    user_id:        Set(Some(user_id)), 
-                    category_id:    Set(Some(tx.category_id)), 
-                    timestamp:      Set(self.dt_provider.utc_now()),
-                    amount:         Set(am), 
-                    notes:          Set(Some(tx.notes)), 
-                    is_refill:      Set(false), 
+        let keys = new_txs.iter()
+            .map(|tx| DuplicateKey { amount: tx.amount, timestamp: tx.timestamp, notes: tx.notes.as_deref() })
+            .collect::<Vec<DuplicateKey>>();
+        let mut duplicates = vec![];
+        for (idx, matches) in self.match_duplicates(create_req.account_id, &keys).await?.into_iter().enumerate() {
+            if !matches.is_empty() {
+                duplicates.push(DuplicateWarningModel { idx, duplicate_of: with_details(&self.db, matches).await? });
+            }
+        }
+        if !duplicates.is_empty() && matches!(create_req.on_duplicate, Some(DuplicatePolicy::Reject)) {
+            return Err(Error::ProbableDuplicates(duplicates));
+        }
 
-                    ..Default::default()
+        let db_tx = self.db.begin().await?;
+        let txs = self.insert_transactions(&db_tx, create_req.account_id, user_id, new_txs).await?;
+
+        // Store the result with the key in the same DB transaction, so a retry either
+        // sees both the transactions and the key, or neither
+        if let Some(key) = create_req.idempotency_key {
+            let ins_key = idempotency_keys::ActiveModel {
+                user_id:        Set(user_id),
+                key:            Set(key.clone()),
+                account_id:     Set(create_req.account_id),
+                response:       Set(serde_json::to_string(&txs)?),
+                created_on_utc: Set(self.dt_provider.utc_now()),
+            };
+            if let Err(e) = IdempotencyKeys::insert(ins_key).exec(&db_tx).await {
+                // A concurrent request with the same key committed first, so replay its result
+                if let Some(SqlErr::UniqueConstraintViolation(_)) = e.sql_err() {
+                    db_tx.rollback().await?;
+                    return self.find_idempotent_result(user_id, create_req.account_id, &key).await?
+                        .ok_or(Error::DbErr(e));
                 }
-            );
+                return Err(e.into());
+            }
         }
+        db_tx.commit().await?;
 
-        let db_tx = self.db.begin().await?;
-        Transactions::insert_many(insertions).exec(&db_tx).await?;
-
-        for (cat_id, total) in totals {
-            Categories::update_many()
-                .filter(categories::Column::Id.eq(cat_id))
-                .col_expr(
-                    categories::Column::Balance, 
-                    Expr::col(categories::Column::Balance).add(total)
+        Ok(CreateTransactionsResultModel { txs, replayed: false, duplicates })
+    }
+
+    async fn update_transaction(
+        &self,
+        user_id: Uuid,
+        update_req: UpdateTransactionModel,
+    ) -> Result<TransactionModel> {
+        validate_user_account_role(&self.db, user_id, update_req.account_id, Role::Write).await?;
+        let new_timestamp = update_req.new_timestamp_utc.map(|ts| self.validate_timestamp(ts)).transpose()?;
+        if update_req.new_exchange_rate.is_some_and(|rate| !rate.is_finite() || rate <= 0.0) {
+            return Err(Error::InvalidField("new_exchange_rate", String::from("must be a positive number")));
+        }
+
+        // Fetch the current rate before opening the DB transaction, so it is not held open
+        // during the request to the conversion provider
+        let current = match (update_req.new_amount, update_req.new_exchange_rate) {
+            (Some(am), None) => {
+                // The new amount is in the transaction's original currency unless another is given
+                let currency_type = match update_req.currency_type.clone() {
+                    Some(currency_type) => currency_type,
+                    None => Transactions::find_by_id(update_req.tx_id)
+                        .filter(transactions::Column::AccountId.eq(update_req.account_id))
+                        .one(&self.db).await?
+                        .and_then(|ex_tx| ex_tx.orig_currency_type)
+                        .unwrap_or(USD_CURRENCY_TYPE.to_string()),
+                };
+                let (_, rate) = self.cc_provider.convert_with_rate(&currency_type, USD_CURRENCY_TYPE, am).await?;
+                Some((currency_type, am, rate))
+            },
+            _ => None,
+        };
+
+        // The transaction and its splits are read in the same DB transaction as the update,
+        // so a concurrent update cannot change them between the read and the write
+        let tx = self.db.begin().await?;
+
+        // Find the transaction to update, ensuring it belongs to the account
+        let ex_tx = Transactions::find_by_id(update_req.tx_id)
+            .filter(transactions::Column::AccountId.eq(update_req.account_id))
+            .filter(transactions::Column::DeleteOn.is_null())
+            .one(&tx).await?
+            .ok_or(Error::AccountDoesNotOwnTransaction(update_req.account_id, update_req.tx_id))?;
+
+        // A corrected rate is applied to the new or original amount, otherwise the current rate is used
+        let orig = match (update_req.new_amount, update_req.new_exchange_rate) {
+            (None, None) => None,
+            (am, Some(rate)) => {
+                let orig_am = am.or(ex_tx.orig_amount).ok_or(Error::NoOriginalAmount(ex_tx.id))?;
+                let currency_type = update_req.currency_type.clone()
+                    .or(ex_tx.orig_currency_type.clone())
+                    .unwrap_or(USD_CURRENCY_TYPE.to_string());
+                Some((currency_type, orig_am, rate))
+            },
+            (Some(_), None) => current,
+        };
+        let new_am = orig.as_ref().map_or(ex_tx.amount, |(_, am, rate)| apply_rate(*am, *rate));
+
+        if ex_tx.reconciliation_id.is_some() && (new_am != ex_tx.amount || new_timestamp.is_some() || update_req.new_category_id.is_some() || update_req.new_splits.is_some()) {
+            return Err(Error::ReconciledTransaction(ex_tx.id));
+        }
+        // Both sides of a transfer must stay equal and opposite
+        if ex_tx.transfer_id.is_some() && (new_am != ex_tx.amount || update_req.new_category_id.is_some() || update_req.new_splits.is_some()) {
+            return Err(Error::TransferTransaction(ex_tx.id));
+        }
+
+        // Ensure the new category belongs to the account
+        if let Some(cat_id) = update_req.new_category_id {
+            self.validate_category(&tx, update_req.account_id, cat_id).await?;
+        }
+        if update_req.new_category_id.is_some() && update_req.new_splits.is_some() {
+            return Err(Error::InvalidSplits(String::from("new_category_id and new_splits cannot both be set")));
+        }
+
+        let ex_splits = TransactionSplits::find()
+            .filter(transaction_splits::Column::TransactionId.eq(ex_tx.id))
+            .all(&tx).await?
+            .into_iter()
+            .map(SplitModel::from)
+            .collect::<Vec<_>>();
+
+        let new_splits = match update_req.new_splits {
+            // New splits are in the transaction's original currency
+            Some(splits) => {
+                let (amount, rate) = match &orig {
+                    Some((_, am, rate)) => (*am, *rate),
+                    None => (ex_tx.orig_amount.unwrap_or(ex_tx.amount), ex_tx.exchange_rate.unwrap_or(1.0)),
+                };
+                Some(self.convert_splits(&tx, update_req.account_id, amount, new_am, rate, splits).await?)
+            },
+            // Moving the transaction into a single category removes its splits
+            None if update_req.new_category_id.is_some() => Some(vec![]),
+            // Existing splits keep their share of a changed amount
+            None if new_am != ex_tx.amount && !ex_splits.is_empty() => {
+                Some(
+                    rescale_splits(&ex_splits, ex_tx.amount, new_am)
+                        .ok_or(Error::InvalidSplits(String::from("new_splits must be set when changing the amount of a split transaction")))?
                 )
-                .exec(&db_tx).await?;
+            },
+            None => None,
+        };
+        let splits = new_splits.clone().unwrap_or_else(|| ex_splits.clone());
+        if let Some(payee_id) = update_req.new_payee_id {
+            self.find_payee(&tx, update_req.account_id, payee_id).await?;
         }
-        db_tx.commit().await?;
+        let new_tag_ids = match update_req.new_tag_ids {
+            Some(tag_ids) => Some(self.validate_tags(&tx, update_req.account_id, tag_ids).await?),
+            None => None,
+        };
+        let new_cat_id = if splits.is_empty() { update_req.new_category_id.or(ex_tx.category_id) } else { None };
 
-        Ok(())
+        // Reverse the transaction from its old categories, and apply it to the new ones
+        let mut totals = HashMap::new();
+        *totals.entry(ex_tx.category_id).or_insert(0i64) -= ex_tx.amount;
+        for split in &ex_splits {
+            *totals.entry(Some(split.cat_id)).or_insert(0i64) -= split.am;
+        }
+        *totals.entry(new_cat_id).or_insert(0i64) += new_am;
+        for split in &splits {
+            *totals.entry(Some(split.cat_id)).or_insert(0i64) += split.am;
+        }
+
+        if let Some(new_splits) = new_splits {
+            TransactionSplits::delete_many()
+                .filter(transaction_splits::Column::TransactionId.eq(ex_tx.id))
+                .exec(&tx).await?;
+            self.insert_splits(&tx, ex_tx.id, new_splits).await?;
+        }
+        if let Some(new_tag_ids) = new_tag_ids {
+            TransactionTags::delete_many()
+                .filter(transaction_tags::Column::TransactionId.eq(ex_tx.id))
+                .exec(&tx).await?;
+            self.insert_tags(&tx, ex_tx.id, new_tag_ids).await?;
+        }
+
+        let mut ex_tx = ex_tx.into_active_model();
+        ex_tx.amount = Set(new_am);
+        ex_tx.category_id = Set(new_cat_id);
+        ex_tx.notes = if let Some(notes) = update_req.new_notes { Set(Some(notes)) } else { NotSet };
+        ex_tx.timestamp = if let Some(ts) = new_timestamp { Set(ts) } else { NotSet };
+        ex_tx.payee_id = if let Some(payee_id) = update_req.new_payee_id { Set(Some(payee_id)) } else { NotSet };
+        if let Some((currency_type, orig_am, rate)) = orig {
+            ex_tx.orig_currency_type = Set(Some(currency_type));
+            ex_tx.orig_amount = Set(Some(orig_am));
+            ex_tx.exchange_rate = Set(Some(rate));
+        }
+        let updated = Transactions::update(ex_tx).exec(&tx).await?;
+
+        adjust_category_balances(&tx, totals, &self.ledger_ctx(LedgerSource::Update, user_id)).await?;
+        let updated = with_details(&tx, vec![updated]).await?.remove(0);
+        tx.commit().await?;
+
+        Ok(updated)
+    }
+
+    async fn bulk_update_transactions(
+        &self,
+        user_id: Uuid,
+        update_req: BulkUpdateTransactionsModel,
+    ) -> Result<BulkUpdateResultModel> {
+        validate_user_account_role(&self.db, user_id, update_req.account_id, Role::Write).await?;
+
+        // The targets are loaded and checked in the same DB transaction as the update,
+        // so a transaction reconciled or transferred meanwhile cannot be recategorized
+        let tx = self.db.begin().await?;
+
+        if let Some(cat_id) = update_req.new_category_id {
+            self.validate_category(&tx, update_req.account_id, cat_id).await?;
+        }
+        let add_tag_ids = self.validate_tags(&tx, update_req.account_id, update_req.add_tag_ids.unwrap_or_default()).await?;
+        let remove_tag_ids = update_req.remove_tag_ids.unwrap_or_default();
+        if let Some(tag_id) = add_tag_ids.iter().find(|tag_id| remove_tag_ids.contains(tag_id)) {
+            return Err(Error::InvalidField("remove_tag_ids", format!("tag {} cannot be both added and removed", tag_id)));
+        }
+
+        let txs = match (update_req.tx_ids, update_req.filters) {
+            (Some(tx_ids), None) => {
+                let txs = Transactions::find()
+                    .filter(transactions::Column::AccountId.eq(update_req.account_id))
+                    .filter(transactions::Column::DeleteOn.is_null())
+                    .filter(transactions::Column::Id.is_in(tx_ids.clone()))
+                    .all(&tx).await?;
+                if let Some(tx_id) = tx_ids.into_iter().find(|id| !txs.iter().any(|tx| tx.id == *id)) {
+                    return Err(Error::AccountDoesNotOwnTransaction(update_req.account_id, tx_id));
+                }
+                if update_req.new_category_id.is_some() {
+                    if let Some(tx) = txs.iter().find(|tx| tx.transfer_id.is_some()) {
+                        return Err(Error::TransferTransaction(tx.id));
+                    }
+                    if let Some(tx) = txs.iter().find(|tx| tx.reconciliation_id.is_some()) {
+                        return Err(Error::ReconciledTransaction(tx.id));
+                    }
+                }
+                txs
+            },
+            (None, Some(filters)) => {
+                let mut cond = filters.into_iter()
+                    .fold(
+                        Condition::all()
+                            .add(transactions::Column::AccountId.eq(update_req.account_id))
+                            .add(transactions::Column::DeleteOn.is_null()),
+                        |cond, filter| cond.add(filter.into_condition())
+                    );
+                if update_req.new_category_id.is_some() {
+                    cond = cond
+                        .add(transactions::Column::TransferId.is_null())
+                        .add(transactions::Column::ReconciliationId.is_null());
+                }
+                Transactions::find().filter(cond).all(&tx).await?
+            },
+            _ => return Err(Error::InvalidField("tx_ids", String::from("exactly one of tx_ids and filters must be set"))),
+        };
+        let tx_ids = txs.iter().map(|tx| tx.id).collect::<Vec<i32>>();
+        if tx_ids.is_empty() {
+            return Ok(BulkUpdateResultModel { tx_ids });
+        }
+
+        // Reverse each transaction from its old categories, and apply it to the new one
+        let mut totals = HashMap::new();
+        if let Some(cat_id) = update_req.new_category_id {
+            let splits = TransactionSplits::find()
+                .filter(transaction_splits::Column::TransactionId.is_in(tx_ids.clone()))
+                .all(&tx).await?;
+            for ex_tx in &txs {
+                *totals.entry(ex_tx.category_id).or_insert(0i64) -= ex_tx.amount;
+                *totals.entry(Some(cat_id)).or_insert(0i64) += ex_tx.amount;
+            }
+            for split in &splits {
+                *totals.entry(Some(split.category_id)).or_insert(0i64) -= split.amount;
+            }
+        }
+
+        // Only link the added tags each transaction does not already have
+        let ex_tags = if add_tag_ids.is_empty() {
+            HashSet::new()
+        } else {
+            TransactionTags::find()
+                .filter(transaction_tags::Column::TransactionId.is_in(tx_ids.clone()))
+                .filter(transaction_tags::Column::TagId.is_in(add_tag_ids.clone()))
+                .all(&tx).await?
+                .into_iter()
+                .map(|tx_tag| (tx_tag.transaction_id, tx_tag.tag_id))
+                .collect::<HashSet<(i32, Uuid)>>()
+        };
+        let new_tags = tx_ids.iter()
+            .flat_map(|tx_id| add_tag_ids.iter().map(move |tag_id| (*tx_id, *tag_id)))
+            .filter(|tx_tag| !ex_tags.contains(tx_tag))
+            .map(|(tx_id, tag_id)| transaction_tags::ActiveModel { transaction_id: Set(tx_id), tag_id: Set(tag_id) })
+            .collect::<Vec<_>>();
+
+        if let Some(cat_id) = update_req.new_category_id {
+            TransactionSplits::delete_many()
+                .filter(transaction_splits::Column::TransactionId.is_in(tx_ids.clone()))
+                .exec(&tx).await?;
+            Transactions::update_many()
+                .col_expr(transactions::Column::CategoryId, Expr::value(cat_id))
+                .filter(transactions::Column::Id.is_in(tx_ids.clone()))
+                .exec(&tx).await?;
+        }
+        if let Some(notes) = update_req.new_notes {
+            Transactions::update_many()
+                .col_expr(transactions::Column::Notes, Expr::value(notes))
+                .filter(transactions::Column::Id.is_in(tx_ids.clone()))
+                .exec(&tx).await?;
+        }
+        if !remove_tag_ids.is_empty() {
+            TransactionTags::delete_many()
+                .filter(transaction_tags::Column::TransactionId.is_in(tx_ids.clone()))
+                .filter(transaction_tags::Column::TagId.is_in(remove_tag_ids))
+                .exec(&tx).await?;
+        }
+        if !new_tags.is_empty() {
+            TransactionTags::insert_many(new_tags).exec(&tx).await?;
+        }
+        adjust_category_balances(&tx, totals, &self.ledger_ctx(LedgerSource::Update, user_id)).await?;
+        tx.commit().await?;
+
+        Ok(BulkUpdateResultModel { tx_ids })
     }
 
     async fn delete_transactions(
         &self, 
         user_id: Uuid, 
         delete_req: DeleteTransactionsModel,
-    ) -> Result<()> {
+    ) -> Result<DateTimeUtc> {
         validate_user_account_role(&self.db, user_id, delete_req.account_id, Role::Write).await?;
 
+        // The transactions are loaded and checked in the same DB transaction as they are deleted,
+        // so concurrent deletes cannot both reverse them from their categories
+        let tx = self.db.begin().await?;
+
         // Get all transactions attempting to be deleted
-        let txs = Transactions::find().filter(transactions::Column::Id.is_in(delete_req.tx_ids.clone()))
-            .all(&self.db).await?;
+        let mut txs = Transactions::find()
+            .filter(transactions::Column::Id.is_in(delete_req.tx_ids.clone()))
+            .filter(transactions::Column::DeleteOn.is_null())
+            .all(&tx).await?;
 
         // If any transactions do not belong to the particular user's account, return error
         if let Some(tx) = txs.iter().filter(|tx| tx.account_id != delete_req.account_id).next() {
@@ -158,28 +1196,378 @@ impl TransactionService for DbConnTransactionService {
             return Err(Error::AccountDoesNotOwnTransaction(delete_req.account_id, tx_id)) ;
         }
 
+        // Deleting either side of a transfer deletes both, so the categories stay balanced
+        let transfer_ids = txs.iter().filter_map(|tx| tx.transfer_id).collect::<Vec<Uuid>>();
+        if !transfer_ids.is_empty() {
+            let tx_ids = txs.iter().map(|tx| tx.id).collect::<HashSet<i32>>();
+            txs.extend(
+                Transactions::find()
+                    .filter(transactions::Column::AccountId.eq(delete_req.account_id))
+                    .filter(transactions::Column::DeleteOn.is_null())
+                    .filter(transactions::Column::TransferId.is_in(transfer_ids))
+                    .all(&tx).await?
+                    .into_iter()
+                    .filter(|tx| !tx_ids.contains(&tx.id))
+            );
+        }
+        // Transfer partners are checked as well, as they are deleted too
+        if let Some(tx) = txs.iter().find(|tx| tx.reconciliation_id.is_some()) {
+            return Err(Error::ReconciledTransaction(tx.id));
+        }
+
+        // Splits, tags and attachments are kept until the transactions are purged, so they can be restored.
+        // Only the transactions this request moved to the trash are reversed from their categories
+        let delete_on = self.dt_provider.utc_now() + Duration::seconds(self.config.trash_retention_s);
+        let deleted = Transactions::update_many()
+            .col_expr(transactions::Column::DeleteOn, Expr::value(delete_on))
+            .filter(transactions::Column::Id.is_in(txs.iter().map(|tx| tx.id)))
+            .filter(transactions::Column::DeleteOn.is_null())
+            .exec_with_returning(&tx).await?;
+        let splits = TransactionSplits::find()
+            .filter(transaction_splits::Column::TransactionId.is_in(deleted.iter().map(|tx| tx.id)))
+            .all(&tx).await?;
+
         // Get grouped total balance changes for each category
         let mut totals = HashMap::new();
-        for tx in &txs {
-            *totals.entry(tx.category_id).or_insert(0i64) += tx.amount;
+        for deleted_tx in &deleted {
+            *totals.entry(deleted_tx.category_id).or_insert(0i64) -= deleted_tx.amount;
         }
+        for split in &splits {
+            *totals.entry(Some(split.category_id)).or_insert(0i64) -= split.amount;
+        }
+        adjust_category_balances(&tx, totals, &self.ledger_ctx(LedgerSource::Delete, user_id)).await?;
+        tx.commit().await?;
 
-        let tx = self.db.begin().await?;
-        for (cat_id, total) in totals {
-            Categories::update_many()
-                .filter(categories::Column::Id.eq(cat_id))
-                .col_expr(
-                    categories::Column::Balance, 
-                    Expr::col(categories::Column::Balance).sub(total)
-                )
-                .exec(&tx).await?;
+        Ok(delete_on) 
+    }
+
+    async fn get_trash(&self, user_id: Uuid, account_id: Uuid) -> Result<Vec<TrashedTransactionModel>> {
+        validate_user_account_role(&self.db, user_id, account_id, Role::Read).await?;
+
+        let txs = Transactions::find()
+            .filter(transactions::Column::AccountId.eq(account_id))
+            .filter(transactions::Column::DeleteOn.is_not_null())
+            .order_by_desc(transactions::Column::DeleteOn)
+            .order_by_desc(transactions::Column::Id)
+            .all(&self.db).await?;
+        let delete_ons = txs.iter().map(|tx| tx.delete_on).collect::<Vec<_>>();
+
+        Ok(
+            with_details(&self.db, txs).await?
+                .into_iter()
+                .zip(delete_ons)
+                .filter_map(|(tx, delete_on)| delete_on.map(|delete_on| TrashedTransactionModel { tx, delete_on }))
+                .collect()
+        )
+    }
+
+    async fn restore_transactions(
+        &self,
+        user_id: Uuid,
+        restore_req: RestoreTransactionsModel,
+    ) -> Result<Vec<TransactionModel>> {
+        validate_user_account_role(&self.db, user_id, restore_req.account_id, Role::Write).await?;
+
+        // As with deletes, the transactions are loaded in the same DB transaction as they are restored
+        let db_tx = self.db.begin().await?;
+
+        let mut txs = Transactions::find()
+            .filter(transactions::Column::AccountId.eq(restore_req.account_id))
+            .filter(transactions::Column::DeleteOn.is_not_null())
+            .filter(transactions::Column::Id.is_in(restore_req.tx_ids.clone()))
+            .all(&db_tx).await?;
+        if let Some(tx_id) = restore_req.tx_ids.into_iter().find(|id| !txs.iter().any(|tx| tx.id == *id)) {
+            return Err(Error::TransactionNotInTrash(tx_id));
         }
 
-        Transactions::delete_many()
+        // Restoring either side of a transfer restores both
+        let transfer_ids = txs.iter().filter_map(|tx| tx.transfer_id).collect::<Vec<Uuid>>();
+        if !transfer_ids.is_empty() {
+            let tx_ids = txs.iter().map(|tx| tx.id).collect::<HashSet<i32>>();
+            txs.extend(
+                Transactions::find()
+                    .filter(transactions::Column::AccountId.eq(restore_req.account_id))
+                    .filter(transactions::Column::DeleteOn.is_not_null())
+                    .filter(transactions::Column::TransferId.is_in(transfer_ids))
+                    .all(&db_tx).await?
+                    .into_iter()
+                    .filter(|tx| !tx_ids.contains(&tx.id))
+            );
+        }
+
+        // Only the transactions this request moved out of the trash are applied to their categories again
+        let restored = Transactions::update_many()
+            .col_expr(transactions::Column::DeleteOn, Expr::value(Option::<DateTimeUtc>::None))
             .filter(transactions::Column::Id.is_in(txs.iter().map(|tx| tx.id)))
+            .filter(transactions::Column::DeleteOn.is_not_null())
+            .exec_with_returning(&db_tx).await?;
+        let splits = TransactionSplits::find()
+            .filter(transaction_splits::Column::TransactionId.is_in(restored.iter().map(|tx| tx.id)))
+            .all(&db_tx).await?;
+
+        let mut totals = HashMap::new();
+        for tx in &restored {
+            *totals.entry(tx.category_id).or_insert(0i64) += tx.amount;
+        }
+        for split in &splits {
+            *totals.entry(Some(split.category_id)).or_insert(0i64) += split.amount;
+        }
+        adjust_category_balances(&db_tx, totals, &self.ledger_ctx(LedgerSource::Restore, user_id)).await?;
+
+        // Keep the order the transactions were loaded in
+        let restored_ids = restored.iter().map(|tx| tx.id).collect::<HashSet<i32>>();
+        let txs = txs.into_iter()
+            .filter(|tx| restored_ids.contains(&tx.id))
+            .map(|tx| transactions::Model { delete_on: None, ..tx })
+            .collect();
+        let restored = with_details(&db_tx, txs).await?;
+        db_tx.commit().await?;
+
+        Ok(restored)
+    }
+
+    async fn purge_trash(&self) -> Result<u64> {
+        let tx_ids = Transactions::find()
+            .filter(transactions::Column::DeleteOn.lte(self.dt_provider.utc_now()))
+            .all(&self.db).await?
+            .into_iter()
+            .map(|tx| tx.id)
+            .collect::<Vec<i32>>();
+        if tx_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let tx = self.db.begin().await?;
+        TransactionSplits::delete_many()
+            .filter(transaction_splits::Column::TransactionId.is_in(tx_ids.clone()))
+            .exec(&tx).await?;
+        TransactionTags::delete_many()
+            .filter(transaction_tags::Column::TransactionId.is_in(tx_ids.clone()))
+            .exec(&tx).await?;
+        let attachment_keys = delete_tx_attachments(&tx, tx_ids.clone()).await?;
+        let res = Transactions::delete_many()
+            .filter(transactions::Column::Id.is_in(tx_ids))
             .exec(&tx).await?;
         tx.commit().await?;
 
-        Ok(()) 
+        // Files are only removed once their rows are gone for good
+        delete_files(&self.storage, attachment_keys).await;
+
+        Ok(res.rows_affected)
     }
-}
\ No newline at end of file
+
+    async fn import_csv(
+        &self,
+        user_id: Uuid,
+        import_req: ImportCsvModel,
+    ) -> Result<ImportReportModel> {
+        validate_user_account_role(&self.db, user_id, import_req.account_id, Role::Write).await?;
+
+        let rows = import::csv::parse_csv(&import_req.contents, &import_req.mapping)
+            .map_err(Error::InvalidMapping)?;
+
+        self.import_rows(
+            user_id, 
+            import_req.account_id, 
+            import_req.mapping.category_id, 
+            rows, 
+            import_req.preview
+        ).await
+    }
+    async fn import_ofx(
+        &self,
+        user_id: Uuid,
+        import_req: ImportOfxModel,
+    ) -> Result<ImportReportModel> {
+        validate_user_account_role(&self.db, user_id, import_req.account_id, Role::Write).await?;
+
+        let rows = import::ofx::parse_ofx(&String::from_utf8_lossy(&import_req.contents))
+            .map_err(Error::InvalidMapping)?;
+
+        self.import_rows(
+            user_id, 
+            import_req.account_id, 
+            import_req.category_id, 
+            rows, 
+            import_req.preview
+        ).await
+    }
+
+    async fn export_transactions(
+        &self,
+        user_id: Uuid,
+        export_req: ExportTransactionsReqModel,
+    ) -> Result<TransactionExportStream> {
+        validate_user_account_role(&self.db, user_id, export_req.account_id, Role::Read).await?;
+
+        let cond = export_req.filters.unwrap_or(vec![]).into_iter()
+            .fold(
+                Condition::all()
+                    .add(transactions::Column::AccountId.eq(export_req.account_id))
+                    .add(transactions::Column::DeleteOn.is_null()),
+                |cond, filter| cond.add(filter.into_condition())
+            );
+
+        let cat_names = Categories::find()
+            .filter(categories::Column::AccountId.eq(export_req.account_id))
+            .all(&self.db).await?
+            .into_iter()
+            .map(|cat| (cat.id, cat.name))
+            .collect::<HashMap<_, _>>();
+
+        Ok(export_stream(self.db.clone(), cond, export_req.format, cat_names))
+    }
+
+    async fn find_duplicates(
+        &self,
+        user_id: Uuid,
+        find_req: FindDuplicatesReqModel,
+    ) -> Result<Vec<SuspectedDuplicateModel>> {
+        validate_user_account_role(&self.db, user_id, find_req.account_id, Role::Read).await?;
+
+        let cond = find_req.filters.unwrap_or(vec![]).into_iter()
+            .fold(
+                Condition::all()
+                    .add(transactions::Column::AccountId.eq(find_req.account_id))
+                    .add(transactions::Column::DeleteOn.is_null())
+                    .add(transactions::Column::TransferId.is_null())
+                    .add(transactions::Column::IsRefill.eq(false)),
+                |cond, filter| cond.add(filter.into_condition())
+            );
+        let txs = Transactions::find().filter(cond)
+            .order_by_asc(transactions::Column::Timestamp)
+            .order_by_asc(transactions::Column::Id)
+            .all(&self.db).await?;
+
+        // Compare each transaction with the earlier ones inside the window
+        let window = Duration::seconds(self.config.duplicate_window_s);
+        let mut found = vec![];
+        for (idx, tx) in txs.iter().enumerate() {
+            let key = DuplicateKey::from(tx);
+            let matches = txs[..idx].iter().rev()
+                .take_while(|ex_tx| tx.timestamp - ex_tx.timestamp <= window)
+                .filter(|ex_tx| is_probable_duplicate(&self.config, &key, &DuplicateKey::from(*ex_tx)))
+                .map(|ex_tx| ex_tx.id)
+                .collect::<Vec<i32>>();
+            if !matches.is_empty() {
+                found.push((tx.id, matches));
+            }
+        }
+
+        let found_ids = found.iter()
+            .flat_map(|(id, matches)| std::iter::once(*id).chain(matches.iter().copied()))
+            .collect::<HashSet<i32>>();
+        let models = with_details(&self.db, txs.into_iter().filter(|tx| found_ids.contains(&tx.id)).collect()).await?
+            .into_iter()
+            .map(|tx| (tx.id, tx))
+            .collect::<HashMap<i32, TransactionModel>>();
+
+        Ok(
+            found.into_iter()
+                .map(|(id, matches)| SuspectedDuplicateModel {
+                    tx: models[&id].clone(),
+                    duplicate_of: matches.into_iter().rev().map(|id| models[&id].clone()).collect(),
+                })
+                .collect()
+        )
+    }
+
+    async fn purge_idempotency_keys(&self) -> Result<u64> {
+        let expired_before = self.dt_provider.utc_now() - Duration::seconds(self.config.idempotency_key_lt_s);
+        let res = IdempotencyKeys::delete_many()
+            .filter(idempotency_keys::Column::CreatedOnUtc.lt(expired_before))
+            .exec(&self.db).await?;
+
+        Ok(res.rows_affected)
+    }
+}
+
+///
+/// Converts the transactions to models, loading the splits and tags of each
+/// 
+pub(crate) async fn with_details(
+    db: &impl ConnectionTrait,
+    txs: Vec<transactions::Model>
+) -> std::result::Result<Vec<TransactionModel>, DbErr> {
+    let mut splits = HashMap::<i32, Vec<SplitModel>>::new();
+    let mut tag_ids = HashMap::<i32, Vec<Uuid>>::new();
+    if !txs.is_empty() {
+        for split in TransactionSplits::find()
+            .filter(transaction_splits::Column::TransactionId.is_in(txs.iter().map(|tx| tx.id)))
+            .order_by_asc(transaction_splits::Column::Id)
+            .all(db).await?
+        {
+            splits.entry(split.transaction_id).or_default().push(split.into());
+        }
+        for tx_tag in TransactionTags::find()
+            .filter(transaction_tags::Column::TransactionId.is_in(txs.iter().map(|tx| tx.id)))
+            .order_by_asc(transaction_tags::Column::TagId)
+            .all(db).await?
+        {
+            tag_ids.entry(tx_tag.transaction_id).or_default().push(tx_tag.tag_id);
+        }
+    }
+
+    Ok(
+        txs.into_iter()
+            .map(|tx| {
+                let tx_splits = splits.remove(&tx.id).unwrap_or_default();
+                let tx_tag_ids = tag_ids.remove(&tx.id).unwrap_or_default();
+                TransactionModel { splits: tx_splits, tag_ids: tx_tag_ids, ..tx.into() }
+            })
+            .collect()
+    )
+}
+
+///
+/// Applies the account's categorization rules to a new transaction. An explicit category
+/// or splits take precedence over a rule's category, while rule tags are added to any given.
+/// 
+fn apply_rules(rule_set: &RuleSet, payee_name: Option<&str>, new_tx: &mut NewTransaction) {
+    if rule_set.is_empty() {
+        return;
+    }
+
+    let outcome = rule_set.evaluate(&RuleInput {
+        notes: new_tx.notes.as_deref(),
+        payee_name,
+        amount: new_tx.orig_amount,
+        currency_type: &new_tx.orig_currency_type,
+    });
+    if new_tx.category_id.is_none() && new_tx.splits.is_empty() {
+        new_tx.category_id = outcome.category_id;
+    }
+    for tag_id in outcome.tag_ids {
+        if !new_tx.tag_ids.contains(&tag_id) {
+            new_tx.tag_ids.push(tag_id);
+        }
+    }
+    new_tx.tag_ids.sort();
+    if outcome.notes.is_some() {
+        new_tx.notes = outcome.notes;
+    }
+}
+
+///
+/// Scales each split by `to_am / from_am`, adding rounding differences to the last split
+/// so the splits sum to `to_am`. Returns `None` if `from_am` is 0.
+/// 
+fn rescale_splits(splits: &[SplitModel], from_am: i64, to_am: i64) -> Option<Vec<SplitModel>> {
+    if from_am == 0 {
+        return None;
+    }
+
+    let mut scaled = splits.iter()
+        .map(|split| SplitModel {
+            cat_id: split.cat_id,
+            am: (split.am as i128 * to_am as i128 / from_am as i128) as i64,
+        })
+        .collect::<Vec<_>>();
+
+    let diff = to_am - scaled.iter().map(|split| split.am).sum::<i64>();
+    if let Some(last) = scaled.last_mut() {
+        last.am += diff;
+    }
+
+    Some(scaled)
+}
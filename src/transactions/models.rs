@@ -1,7 +1,9 @@
-use schmeconomics_entities::transactions;
-use sea_orm::{ColumnTrait, prelude::{DateTimeUtc, Uuid}, QueryFilter, Select};
+use schmeconomics_entities::{prelude::*, transaction_splits, transaction_tags, transactions};
+use sea_orm::{ColumnTrait, Condition, Order, prelude::{DateTimeUtc, Expr, Uuid}, QueryFilter, QueryOrder, sea_query::{ExprTrait, Func, LikeExpr, Query, SelectStatement}, Select};
 use serde::{Deserialize, Serialize};
 
+use super::import::csv::CsvMappingModel;
+
 #[derive(Deserialize)]
 pub struct GetTransactionsQueryParams {
     pub page_size: Option<u64>,
@@ -12,14 +14,43 @@ pub struct GetTransactionsQueryParams {
 ///
 /// Model representing a single transaction
 /// 
-#[derive(Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TransactionModel {
     pub id: i32,
     pub am: i64,
     pub cat_id: Option<Uuid>,
     pub user_id: Option<Uuid>,
     pub timestamp_utc: DateTimeUtc,
-    pub notes: Option<String>
+    pub notes: Option<String>,
+    ///
+    /// Portions of the transaction assigned to each category.
+    /// Empty unless the transaction is split, in which case `cat_id` is `None`.
+    /// 
+    #[serde(default)]
+    pub splits: Vec<SplitModel>,
+    ///
+    /// Shared by the debit and credit transactions of a category transfer
+    /// 
+    pub transfer_id: Option<Uuid>,
+    ///
+    /// Currency and amount the transaction was made in, and the rate used to convert it to USD.
+    /// `None` for refills, transfers, and transactions created before these were recorded.
+    /// 
+    pub orig_currency_type: Option<String>,
+    pub orig_am: Option<i64>,
+    pub exchange_rate: Option<f64>,
+    #[serde(default)]
+    pub tag_ids: Vec<Uuid>,
+    pub payee_id: Option<Uuid>,
+    ///
+    /// Whether the transaction has appeared on a bank statement
+    /// 
+    #[serde(default)]
+    pub is_cleared: bool,
+    ///
+    /// Set once the transaction is reconciled, locking its amount, timestamp and categories
+    /// 
+    pub reconciliation_id: Option<Uuid>,
 }
 
 impl From<transactions::Model> for TransactionModel {
@@ -30,39 +61,220 @@ impl From<transactions::Model> for TransactionModel {
             cat_id: value.category_id,
             user_id: value.user_id, 
             timestamp_utc: value.timestamp, 
-            notes: value.notes
+            notes: value.notes,
+            splits: vec![],
+            transfer_id: value.transfer_id,
+            orig_currency_type: value.orig_currency_type,
+            orig_am: value.orig_amount,
+            exchange_rate: value.exchange_rate,
+            tag_ids: vec![],
+            payee_id: value.payee_id,
+            is_cleared: value.is_cleared,
+            reconciliation_id: value.reconciliation_id,
         }
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SplitModel {
+    pub cat_id: Uuid,
+    pub am: i64,
+}
+
+impl From<transaction_splits::Model> for SplitModel {
+    fn from(value: transaction_splits::Model) -> Self {
+        SplitModel { cat_id: value.category_id, am: value.amount }
+    }
+}
+
+///
+/// A single page of transactions matching a query
+/// 
+#[derive(Serialize)]
+pub struct TransactionPageModel {
+    pub items: Vec<TransactionModel>,
+    ///
+    /// Number of transactions matching the filters, across all pages
+    /// 
+    pub total_count: u64,
+    pub page_count: u64,
+    ///
+    /// Sum of the amounts of every transaction matching the filters, across all pages
+    /// 
+    pub total_am: i64,
+}
+
 #[derive(Deserialize)]
 pub struct GetTransactionReqModel {
     pub account_id: Uuid,
     pub page_size: Option<u64>, 
     pub page_idx: Option<u64>, 
-    pub filters: Option<Vec<TransactionFilter>>
+    pub filters: Option<Vec<TransactionFilter>>,
+    ///
+    /// Order of the returned transactions. Defaults to ascending ID
+    /// 
+    pub sort: Option<TransactionSort>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum ScrollDir { Older, Newer, }
+
+#[derive(Deserialize)]
+pub struct ScrollTransactionsReqModel {
+    pub account_id: Uuid,
+    pub limit: Option<u64>,
+    ///
+    /// Cursor returned by a previous scroll. When `None`, scrolling
+    /// starts from the newest transaction in the account
+    /// 
+    pub cursor: Option<String>,
+    ///
+    /// Whether to fetch transactions older or newer than the cursor. Defaults to `Older`
+    /// 
+    pub dir: Option<ScrollDir>,
+    pub filters: Option<Vec<TransactionFilter>>,
+}
+
+#[derive(Serialize)]
+pub struct TransactionScrollModel {
+    ///
+    /// Transactions ordered newest-first
+    /// 
+    pub items: Vec<TransactionModel>,
+    ///
+    /// Cursor to fetch the transactions before this page.
+    /// `None` if there are no older transactions
+    /// 
+    pub older_cursor: Option<String>,
+    ///
+    /// Cursor to fetch transactions added after this page
+    /// 
+    pub newer_cursor: Option<String>,
+}
+
+///
+/// Opaque position in an account's transaction history,
+/// built on the monotonically increasing transaction ID
+/// 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransactionCursor { pub id: i32, }
+
+impl TransactionCursor {
+    const PREFIX: &'static str = "tx1:";
+
+    pub fn encode(&self) -> String {
+        format!("{}{}", Self::PREFIX, self.id).bytes()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        if cursor.len() % 2 != 0 || !cursor.is_ascii() { return None; }
+        let bytes = (0..cursor.len()).step_by(2)
+            .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+
+        let id = String::from_utf8(bytes).ok()?
+            .strip_prefix(Self::PREFIX)?
+            .parse::<i32>().ok()?;
+        Some(TransactionCursor { id })
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum TransactionSortField { Timestamp, Amount, Id, }
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum SortDir { Asc, Desc, }
+
+#[derive(Deserialize)]
+pub struct TransactionSort {
+    pub field: TransactionSortField,
+    pub dir: SortDir,
+}
+
+impl TransactionSort {
+    ///
+    /// Orders the query by the sort field, breaking ties by ID
+    /// so pages are stable
+    /// 
+    pub fn apply(&self, query: Select<transactions::Entity>) -> Select<transactions::Entity> {
+        let order = match self.dir {
+            SortDir::Asc => Order::Asc,
+            SortDir::Desc => Order::Desc,
+        };
+        let query = match self.field {
+            TransactionSortField::Timestamp => query.order_by(transactions::Column::Timestamp, order.clone()),
+            TransactionSortField::Amount => query.order_by(transactions::Column::Amount, order.clone()),
+            TransactionSortField::Id => query,
+        };
+        query.order_by(transactions::Column::Id, order)
+    }
+}
+
+impl Default for TransactionSort {
+    fn default() -> Self {
+        TransactionSort { field: TransactionSortField::Id, dir: SortDir::Asc }
+    }
 }
 
 pub trait ToSelectQuery {
     fn into_select_query(self, query: Select<transactions::Entity>) -> Select<transactions::Entity>;
 }
 
+pub trait ToCondition {
+    fn into_condition(self) -> Condition;
+}
+
 #[derive(Deserialize)]
 pub enum Cmp { Lt, Lte, Eq, Gte, Gt, }
 
+///
+/// Filter applied to a transaction query.
+/// Timestamp ranges are half-open: `After` and `from` are inclusive, `Before` and `to` are exclusive.
+/// 
 #[derive(Deserialize)]
 #[serde(tag = "filter")]
 pub enum TransactionFilter {
     CategoryEq { id: Uuid },
     Cmp { cmp: Cmp, val: i64 },
+    Before { ts: DateTimeUtc },
+    After { ts: DateTimeUtc },
+    Between { from: DateTimeUtc, to: DateTimeUtc },
+    UserEq { id: Uuid },
+    PayeeEq { id: Uuid },
+    ///
+    /// Case-insensitive substring match on the transaction notes
+    /// 
+    NotesContains { text: String },
+    ///
+    /// Matches transactions with every one of the tags
+    /// 
+    HasTags { ids: Vec<Uuid> },
+    IsRefill { val: bool },
+    Uncategorized,
+    Any { filters: Vec<TransactionFilter> },
+    All { filters: Vec<TransactionFilter> },
+    Not { filter: Box<TransactionFilter> },
 }
 
 impl ToSelectQuery for TransactionFilter {
     fn into_select_query(self, query: Select<transactions::Entity>) -> Select<transactions::Entity> {
+        query.filter(self.into_condition())
+    }
+}
+
+impl ToCondition for TransactionFilter {
+    fn into_condition(self) -> Condition {
         match self {
-            TransactionFilter::CategoryEq { id } => query.filter(transactions::Column::CategoryId.eq(id)),
+            TransactionFilter::CategoryEq { id } => {
+                // Split transactions match if any of their splits are in the category
+                Condition::any()
+                    .add(transactions::Column::CategoryId.eq(id))
+                    .add(transactions::Column::Id.in_subquery(split_tx_ids().and_where(transaction_splits::Column::CategoryId.eq(id)).to_owned()))
+            },
             TransactionFilter::Cmp { cmp, val } => {
-                query.filter(
+                Condition::all().add(
                     match cmp {
                         Cmp::Gt => transactions::Column::Amount.gt(val),
                         Cmp::Gte => transactions::Column::Amount.gte(val),
@@ -71,27 +283,297 @@ impl ToSelectQuery for TransactionFilter {
                         Cmp::Lte => transactions::Column::Amount.lte(val),
                     }
                 )
-            }
+            },
+            TransactionFilter::Before { ts } => Condition::all().add(transactions::Column::Timestamp.lt(ts)),
+            TransactionFilter::After { ts } => Condition::all().add(transactions::Column::Timestamp.gte(ts)),
+            TransactionFilter::Between { from, to } => {
+                Condition::all()
+                    .add(transactions::Column::Timestamp.gte(from))
+                    .add(transactions::Column::Timestamp.lt(to))
+            },
+            TransactionFilter::UserEq { id } => Condition::all().add(transactions::Column::UserId.eq(id)),
+            TransactionFilter::PayeeEq { id } => Condition::all().add(transactions::Column::PayeeId.eq(id)),
+            TransactionFilter::NotesContains { text } => {
+                // Escape LIKE wildcards so the text is matched literally
+                let text = text.to_lowercase()
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_");
+                Condition::all().add(
+                    Func::lower(Expr::col(transactions::Column::Notes))
+                        .like(LikeExpr::new(format!("%{}%", text)).escape('\\'))
+                )
+            },
+            TransactionFilter::HasTags { ids } => {
+                ids.into_iter().fold(Condition::all(), |cond, id| {
+                    cond.add(
+                        transactions::Column::Id.in_subquery(
+                            Query::select()
+                                .column(transaction_tags::Column::TransactionId)
+                                .from(TransactionTags)
+                                .and_where(transaction_tags::Column::TagId.eq(id))
+                                .to_owned()
+                        )
+                    )
+                })
+            },
+            TransactionFilter::IsRefill { val } => Condition::all().add(transactions::Column::IsRefill.eq(val)),
+            TransactionFilter::Uncategorized => {
+                Condition::all()
+                    .add(transactions::Column::CategoryId.is_null())
+                    .add(transactions::Column::Id.not_in_subquery(split_tx_ids()))
+            },
+            TransactionFilter::Any { filters } => {
+                filters.into_iter().fold(Condition::any(), |cond, f| cond.add(f.into_condition()))
+            },
+            TransactionFilter::All { filters } => {
+                filters.into_iter().fold(Condition::all(), |cond, f| cond.add(f.into_condition()))
+            },
+            TransactionFilter::Not { filter } => filter.into_condition().not(),
         }
     }
 }
 
+///
+/// Selects the IDs of every transaction with splits
+/// 
+fn split_tx_ids() -> SelectStatement {
+    Query::select()
+        .column(transaction_splits::Column::TransactionId)
+        .from(TransactionSplits)
+        .to_owned()
+}
+
 #[derive(Deserialize)]
 pub struct CreateTransactionsModel {
     pub account_id: Uuid,
     pub txs: Vec<CreateTransactionModel>,
+    ///
+    /// Unique key for the request, also accepted as the `Idempotency-Key` header.
+    /// Retrying with the same key replays the original result instead of inserting again.
+    /// 
+    pub idempotency_key: Option<String>,
+    ///
+    /// What to do when transactions look like ones already in the account. Defaults to `Warn`
+    /// 
+    pub on_duplicate: Option<DuplicatePolicy>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum DuplicatePolicy {
+    ///
+    /// Create the transactions, returning the probable duplicates as warnings
+    /// 
+    Warn,
+    ///
+    /// Create nothing if any transaction is a probable duplicate, so the user can confirm first
+    /// 
+    Reject,
+}
+
+#[derive(Serialize)]
+pub struct CreateTransactionsResultModel {
+    pub txs: Vec<TransactionModel>,
+    ///
+    /// `true` if the result was replayed from an earlier request with the same idempotency key
+    /// 
+    pub replayed: bool,
+    ///
+    /// Transactions which look like ones already in the account. Empty when replayed
+    /// 
+    pub duplicates: Vec<DuplicateWarningModel>,
+}
+
+///
+/// A new transaction which is probably a duplicate of existing ones
+/// 
+#[derive(Debug, Serialize)]
+pub struct DuplicateWarningModel {
+    ///
+    /// Index of the new transaction in the request
+    /// 
+    pub idx: usize,
+    pub duplicate_of: Vec<TransactionModel>,
+}
+
+#[derive(Deserialize)]
+pub struct FindDuplicatesReqModel {
+    pub account_id: Uuid,
+    pub filters: Option<Vec<TransactionFilter>>,
+}
+
+///
+/// A transaction which is probably a duplicate of earlier transactions in the account
+/// 
+#[derive(Serialize)]
+pub struct SuspectedDuplicateModel {
+    pub tx: TransactionModel,
+    pub duplicate_of: Vec<TransactionModel>,
 }
 
 #[derive(Deserialize)]
 pub struct CreateTransactionModel {
-    pub category_id: Uuid,
+    ///
+    /// Category of the whole transaction. At most one of `category_id` and `splits` may be set.
+    /// When neither is, the payee's default category is used.
+    /// 
+    pub category_id: Option<Uuid>,
     pub currency_type: String,
     pub amount: i64,
     pub notes: String,
+    ///
+    /// Splits the transaction across categories. Split amounts are in the
+    /// transaction's currency, and must sum to `amount`
+    /// 
+    pub splits: Option<Vec<CreateSplitModel>>,
+    ///
+    /// When the transaction happened, for backdating. Defaults to now
+    /// 
+    pub timestamp_utc: Option<DateTimeUtc>,
+    pub tag_ids: Option<Vec<Uuid>>,
+    pub payee_id: Option<Uuid>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct CreateSplitModel {
+    pub category_id: Uuid,
+    pub amount: i64,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateTransactionModel {
+    pub account_id: Uuid,
+    pub tx_id: i32,
+    pub new_amount: Option<i64>,
+    ///
+    /// Currency type of `new_amount`. Defaults to the transaction's original currency, or USD
+    /// 
+    pub currency_type: Option<String>,
+    ///
+    /// Moves the whole transaction into the category, removing any splits
+    /// 
+    pub new_category_id: Option<Uuid>,
+    pub new_notes: Option<String>,
+    pub new_timestamp_utc: Option<DateTimeUtc>,
+    ///
+    /// Replaces the transaction's splits. Amounts are in `currency_type`,
+    /// and must sum to the transaction's amount
+    /// 
+    pub new_splits: Option<Vec<CreateSplitModel>>,
+    ///
+    /// Corrects the rate the original amount was converted to USD at, such as
+    /// once the card statement posts. The USD amount is recalculated from it.
+    /// 
+    pub new_exchange_rate: Option<f64>,
+    ///
+    /// Replaces the transaction's tags
+    /// 
+    pub new_tag_ids: Option<Vec<Uuid>>,
+    pub new_payee_id: Option<Uuid>,
+}
+
+///
+/// Changes the category, tags or notes of many transactions at once
+/// 
+#[derive(Deserialize)]
+pub struct BulkUpdateTransactionsModel {
+    pub account_id: Uuid,
+    ///
+    /// Transactions to update. Exactly one of `tx_ids` and `filters` must be set
+    /// 
+    pub tx_ids: Option<Vec<i32>>,
+    ///
+    /// Updates every transaction matching the filters. Transfers and reconciled transactions
+    /// are left out when `new_category_id` is set, as their categories cannot be changed
+    /// 
+    pub filters: Option<Vec<TransactionFilter>>,
+    ///
+    /// Moves each transaction into the category, removing any splits
+    /// 
+    pub new_category_id: Option<Uuid>,
+    pub add_tag_ids: Option<Vec<Uuid>>,
+    pub remove_tag_ids: Option<Vec<Uuid>>,
+    pub new_notes: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct BulkUpdateResultModel {
+    pub tx_ids: Vec<i32>,
 }
 
 #[derive(Deserialize)]
 pub struct DeleteTransactionsModel {
     pub account_id: Uuid,
     pub tx_ids: Vec<i32>,
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+pub struct RestoreTransactionsModel {
+    pub account_id: Uuid,
+    pub tx_ids: Vec<i32>,
+}
+
+///
+/// A deleted transaction, which can be restored until it is purged
+/// 
+#[derive(Serialize)]
+pub struct TrashedTransactionModel {
+    #[serde(flatten)]
+    pub tx: TransactionModel,
+    pub delete_on: DateTimeUtc,
+}
+///
+/// CSV statement upload, read from a multipart form
+/// 
+pub struct ImportCsvModel {
+    pub account_id: Uuid,
+    pub mapping: CsvMappingModel,
+    ///
+    /// When `true`, rows are parsed and reported but not committed
+    /// 
+    pub preview: bool,
+    pub contents: Vec<u8>,
+}
+
+///
+/// OFX or QFX statement upload, read from a multipart form
+/// 
+pub struct ImportOfxModel {
+    pub account_id: Uuid,
+    ///
+    /// Category assigned to every imported transaction
+    /// 
+    pub category_id: Option<Uuid>,
+    ///
+    /// When `true`, transactions are parsed and reported but not committed
+    /// 
+    pub preview: bool,
+    pub contents: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum ExportFormat { Csv, Ndjson, }
+
+#[derive(Deserialize)]
+pub struct ExportTransactionsReqModel {
+    pub account_id: Uuid,
+    pub format: ExportFormat,
+    pub filters: Option<Vec<TransactionFilter>>,
+}
+
+///
+/// A single exported transaction, with its category name resolved
+/// 
+#[derive(Serialize)]
+pub struct ExportedTransactionModel {
+    pub id: i32,
+    pub timestamp_utc: DateTimeUtc,
+    pub am: i64,
+    ///
+    /// Category name, or the `; ` separated names of each split category
+    /// 
+    pub category: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub notes: Option<String>,
+    pub is_refill: bool,
+}
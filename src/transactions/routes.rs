@@ -1,14 +1,26 @@
-use axum::{extract::State, routing::{delete, post}, Json, Router};
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use axum::{body::Body, extract::{Multipart, Path, State}, http::{header, HeaderMap}, response::IntoResponse, routing::{delete, get, post, put}, Json, Router};
+use sea_orm::prelude::{DateTimeUtc, Uuid};
 
 use crate::{auth::middleware::AuthUser, state::AppState};
 
-use super::{error::Result, models::{CreateTransactionsModel, DeleteTransactionsModel, GetTransactionReqModel, TransactionModel}, DynTransactionService};
+use super::{error::{Error, Result}, import::ImportReportModel, models::{BulkUpdateResultModel, BulkUpdateTransactionsModel, CreateTransactionsModel, CreateTransactionsResultModel, DeleteTransactionsModel, ExportFormat, ExportTransactionsReqModel, FindDuplicatesReqModel, GetTransactionReqModel, ImportCsvModel, ImportOfxModel, RestoreTransactionsModel, ScrollTransactionsReqModel, SuspectedDuplicateModel, TransactionModel, TransactionPageModel, TransactionScrollModel, TrashedTransactionModel, UpdateTransactionModel}, DynTransactionService};
 
 pub fn routes(app_state: AppState) -> Router {
     Router::new()
         .route("/query", post(get_transactions))
+        .route("/scroll", post(scroll_transactions))
         .route("/create", post(post_transactions))  
+        .route("/update", put(update_transaction))
+        .route("/bulk-update", put(bulk_update_transactions))
         .route("/delete", delete(delete_transactions))
+        .route("/trash/{account_id}", get(get_trash))
+        .route("/restore", post(restore_transactions))
+        .route("/import/csv", post(import_csv))
+        .route("/import/ofx", post(import_ofx))
+        .route("/export", post(export_transactions))
+        .route("/duplicates", post(find_duplicates))
         .with_state(app_state)
 }
 
@@ -16,24 +28,172 @@ pub async fn get_transactions(
     State(tx_svc): State<DynTransactionService>,
     user: AuthUser,
     Json(req): Json<GetTransactionReqModel>,
-) -> Result<Json<Vec<TransactionModel>>> {
+) -> Result<Json<TransactionPageModel>> {
     Ok(Json(tx_svc.get_transactions(user.id, req).await?))
 }
 
+pub async fn scroll_transactions(
+    State(tx_svc): State<DynTransactionService>,
+    user: AuthUser,
+    Json(req): Json<ScrollTransactionsReqModel>,
+) -> Result<Json<TransactionScrollModel>> {
+    Ok(Json(tx_svc.scroll_transactions(user.id, req).await?))
+}
+
 pub async fn post_transactions(
     State(tx_svc): State<DynTransactionService>,
     user: AuthUser,
-    Json(body): Json<CreateTransactionsModel>,
-) -> Result<()> {
-    tx_svc.create_transactions(user.id, body).await?;
-    Ok(())
+    headers: HeaderMap,
+    Json(mut body): Json<CreateTransactionsModel>,
+) -> Result<Json<CreateTransactionsResultModel>> {
+    // The Idempotency-Key header takes precedence over the body field
+    if let Some(key) = headers.get("Idempotency-Key") {
+        let key = key.to_str()
+            .map_err(|e| Error::InvalidField("Idempotency-Key", e.to_string()))?;
+        body.idempotency_key = Some(key.to_string());
+    }
+
+    Ok(Json(tx_svc.create_transactions(user.id, body).await?))
+}
+
+pub async fn update_transaction(
+    State(tx_svc): State<DynTransactionService>,
+    user: AuthUser,
+    Json(body): Json<UpdateTransactionModel>,
+) -> Result<Json<TransactionModel>> {
+    Ok(Json(tx_svc.update_transaction(user.id, body).await?))
+}
+
+pub async fn bulk_update_transactions(
+    State(tx_svc): State<DynTransactionService>,
+    user: AuthUser,
+    Json(body): Json<BulkUpdateTransactionsModel>,
+) -> Result<Json<BulkUpdateResultModel>> {
+    Ok(Json(tx_svc.bulk_update_transactions(user.id, body).await?))
 }
 
 pub async fn delete_transactions(
     State(tx_svc): State<DynTransactionService>,
     user: AuthUser,
     Json(body): Json<DeleteTransactionsModel>
-) -> Result<()> {
-    tx_svc.delete_transactions(user.id, body).await?;
-    Ok(())
+) -> Result<Json<DateTimeUtc>> {
+    Ok(Json(tx_svc.delete_transactions(user.id, body).await?))
+}
+
+pub async fn get_trash(
+    State(tx_svc): State<DynTransactionService>,
+    Path(account_id): Path<Uuid>,
+    user: AuthUser,
+) -> Result<Json<Vec<TrashedTransactionModel>>> {
+    Ok(Json(tx_svc.get_trash(user.id, account_id).await?))
+}
+
+pub async fn restore_transactions(
+    State(tx_svc): State<DynTransactionService>,
+    user: AuthUser,
+    Json(body): Json<RestoreTransactionsModel>,
+) -> Result<Json<Vec<TransactionModel>>> {
+    Ok(Json(tx_svc.restore_transactions(user.id, body).await?))
+}
+
+///
+/// Imports a CSV statement. Expects a multipart form with the fields
+/// `account_id`, `mapping` (JSON `CsvMappingModel`), `file`, and optionally `preview`
+/// 
+pub async fn import_csv(
+    State(tx_svc): State<DynTransactionService>,
+    user: AuthUser,
+    multipart: Multipart,
+) -> Result<Json<ImportReportModel>> {
+    let fields = read_form(multipart).await?;
+    let import_req = ImportCsvModel {
+        account_id: form_field(&fields, "account_id")?,
+        mapping: serde_json::from_slice(form_bytes(&fields, "mapping")?)
+            .map_err(|e| Error::InvalidField("mapping", e.to_string()))?,
+        preview: if fields.contains_key("preview") { form_field(&fields, "preview")? } else { false },
+        contents: form_bytes(&fields, "file")?.to_vec(),
+    };
+
+    Ok(Json(tx_svc.import_csv(user.id, import_req).await?))
+}
+
+///
+/// Imports an OFX or QFX statement. Expects a multipart form with the fields
+/// `account_id`, `file`, and optionally `category_id` and `preview`
+/// 
+pub async fn import_ofx(
+    State(tx_svc): State<DynTransactionService>,
+    user: AuthUser,
+    multipart: Multipart,
+) -> Result<Json<ImportReportModel>> {
+    let fields = read_form(multipart).await?;
+    let import_req = ImportOfxModel {
+        account_id: form_field(&fields, "account_id")?,
+        category_id: if fields.contains_key("category_id") { Some(form_field(&fields, "category_id")?) } else { None },
+        preview: if fields.contains_key("preview") { form_field(&fields, "preview")? } else { false },
+        contents: form_bytes(&fields, "file")?.to_vec(),
+    };
+
+    Ok(Json(tx_svc.import_ofx(user.id, import_req).await?))
+}
+
+///
+/// Downloads the matching transactions as a CSV or NDJSON attachment.
+/// The body is streamed, so large accounts are not buffered in memory.
+/// 
+pub async fn export_transactions(
+    State(tx_svc): State<DynTransactionService>,
+    user: AuthUser,
+    Json(req): Json<ExportTransactionsReqModel>,
+) -> Result<impl IntoResponse> {
+    let (content_type, ext) = match req.format {
+        ExportFormat::Csv => ("text/csv", "csv"),
+        ExportFormat::Ndjson => ("application/x-ndjson", "ndjson"),
+    };
+    let stream = tx_svc.export_transactions(user.id, req).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"transactions.{}\"", ext)),
+        ],
+        Body::from_stream(stream),
+    ))
+}
+
+pub async fn find_duplicates(
+    State(tx_svc): State<DynTransactionService>,
+    user: AuthUser,
+    Json(req): Json<FindDuplicatesReqModel>,
+) -> Result<Json<Vec<SuspectedDuplicateModel>>> {
+    Ok(Json(tx_svc.find_duplicates(user.id, req).await?))
+}
+
+///
+/// Reads every field of a multipart form into memory, keyed by field name
+/// 
+async fn read_form(mut multipart: Multipart) -> Result<HashMap<String, Vec<u8>>> {
+    let mut fields = HashMap::new();
+    while let Some(field) = multipart.next_field().await? {
+        if let Some(name) = field.name().map(|name| name.to_string()) {
+            fields.insert(name, field.bytes().await?.to_vec());
+        }
+    }
+    Ok(fields)
+}
+
+fn form_bytes<'a>(fields: &'a HashMap<String, Vec<u8>>, name: &'static str) -> Result<&'a [u8]> {
+    fields.get(name)
+        .map(|value| value.as_slice())
+        .ok_or(Error::InvalidField(name, String::from("field is missing")))
+}
+
+fn form_field<T>(fields: &HashMap<String, Vec<u8>>, name: &'static str) -> Result<T> 
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    String::from_utf8_lossy(form_bytes(fields, name)?).trim()
+        .parse::<T>()
+        .map_err(|e| Error::InvalidField(name, e.to_string()))
 }
\ No newline at end of file
@@ -1,16 +1,17 @@
 use std::sync::Arc;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use futures::TryStreamExt;
 use lazy_static::lazy_static;
 use mockall::predicate::{always, eq};
-use sea_orm::{prelude::Uuid, sea_query::TableCreateStatement, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, Schema, Set};
+use sea_orm::{prelude::Uuid, sea_query::TableCreateStatement, ConnectionTrait, Database, DbBackend, DbConn, EntityTrait, IntoActiveModel, Schema, Set};
 
-use schmeconomics_entities::{account_users, accounts, categories, prelude::*, users};
+use schmeconomics_entities::{account_users, accounts, attachments, categories, categorization_rules, payees, prelude::*, tags, transactions, users};
 use utils_rs::date_time_provider::MockDateTimeProvider;
 
-use crate::{currency_conv_provider::{MockCurrencyConversionProvider, USD_CURRENCY_TYPE}, db_utils::{DbUtilsError, Role}, transactions::{models::{DeleteTransactionsModel, GetTransactionReqModel}, CreateTransactionModel, Error, TransactionService}};
+use crate::{attachments::storage::MockAttachmentStorage, currency_conv_provider::{MockCurrencyConversionProvider, USD_CURRENCY_TYPE}, db_utils::{DbUtilsError, Role}, transactions::{models::{DeleteTransactionsModel, GetTransactionReqModel, UpdateTransactionModel}, CreateTransactionModel, Error, TransactionService}};
 
-use super::{models::CreateTransactionsModel, DbConnTransactionService, TransactionFilter};
+use super::{import::csv::{CsvColumn, CsvMappingModel, SignConvention}, models::{BulkUpdateTransactionsModel, Cmp, RestoreTransactionsModel, CreateSplitModel, CreateTransactionsModel, DuplicatePolicy, FindDuplicatesReqModel, ExportFormat, ExportTransactionsReqModel, ImportCsvModel, ImportOfxModel, ScrollDir, ScrollTransactionsReqModel, SortDir, SplitModel, TransactionCursor, TransactionSort, TransactionSortField}, Config, DbConnTransactionService, TransactionFilter};
 
 lazy_static! {
     static ref TEST_USER_1_ID: Uuid = Uuid::parse_str("be5ca263-2307-4e5a-acbd-3281fb81ea60").unwrap();
@@ -38,12 +39,28 @@ async fn create_test_db() -> anyhow::Result<DbConn> {
     let account_user_stmt: TableCreateStatement = schema.create_table_from_entity(AccountUsers);
     let category_stmt: TableCreateStatement = schema.create_table_from_entity(Categories);
     let tx_stmt: TableCreateStatement = schema.create_table_from_entity(Transactions);
+    let split_stmt: TableCreateStatement = schema.create_table_from_entity(TransactionSplits);
+    let idempotency_stmt: TableCreateStatement = schema.create_table_from_entity(IdempotencyKeys);
+    let tag_stmt: TableCreateStatement = schema.create_table_from_entity(Tags);
+    let tx_tag_stmt: TableCreateStatement = schema.create_table_from_entity(TransactionTags);
+    let attachment_stmt: TableCreateStatement = schema.create_table_from_entity(Attachments);
+    let payee_stmt: TableCreateStatement = schema.create_table_from_entity(Payees);
+    let rule_stmt: TableCreateStatement = schema.create_table_from_entity(CategorizationRules);
+    let ledger_stmt: TableCreateStatement = schema.create_table_from_entity(CategoryLedger);
 
     db.execute(db.get_database_backend().build(&user_stmt)).await?;
     db.execute(db.get_database_backend().build(&account_stmt)).await?;
     db.execute(db.get_database_backend().build(&account_user_stmt)).await?;
     db.execute(db.get_database_backend().build(&category_stmt)).await?;
     db.execute(db.get_database_backend().build(&tx_stmt)).await?;
+    db.execute(db.get_database_backend().build(&split_stmt)).await?;
+    db.execute(db.get_database_backend().build(&idempotency_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tag_stmt)).await?;
+    db.execute(db.get_database_backend().build(&tx_tag_stmt)).await?;
+    db.execute(db.get_database_backend().build(&attachment_stmt)).await?;
+    db.execute(db.get_database_backend().build(&payee_stmt)).await?;
+    db.execute(db.get_database_backend().build(&rule_stmt)).await?;
+    db.execute(db.get_database_backend().build(&ledger_stmt)).await?;
 
     // Insert 1st test user
     let new_user = users::ActiveModel {
@@ -130,23 +147,41 @@ async fn create_test_service() -> anyhow::Result<(DbConnTransactionService, DbCo
     // CurrencyConversionProvider
     // Conversion taken from Feb. 3rd, 2025 (rounded to nearest half for testing)
     let mut mock_cc_provider = MockCurrencyConversionProvider::new();
-    mock_cc_provider.expect_convert()
+    mock_cc_provider.expect_convert_with_rate()
         .with(eq(USD_CURRENCY_TYPE), eq("CAD"), always())
-        .returning(|_, _, am| Ok((am as f64 * 2.0).floor() as i64));
-    mock_cc_provider.expect_convert()
+        .returning(|_, _, am| Ok(((am as f64 * 2.0).floor() as i64, 2.0)));
+    mock_cc_provider.expect_convert_with_rate()
         .with(eq("CAD"), eq(USD_CURRENCY_TYPE), always())
-        .returning(|_, _, am| Ok((am as f64 * 0.5).floor() as i64));
-    mock_cc_provider.expect_convert()
+        .returning(|_, _, am| Ok(((am as f64 * 0.5).floor() as i64, 0.5)));
+    mock_cc_provider.expect_convert_with_rate()
         .with(eq(USD_CURRENCY_TYPE), eq(USD_CURRENCY_TYPE), always())
-        .returning(|_, _, am| Ok(am));
+        .returning(|_, _, am| Ok((am, 1.0)));
+    // Historical rates are only known for Nov. 1st, 2024
+    mock_cc_provider.expect_get_historical_conversion()
+        .with(eq("CAD"), eq(USD_CURRENCY_TYPE), always())
+        .returning(|_, _, date| Ok((date == NaiveDate::from_ymd_opt(2024, 11, 1).unwrap()).then_some(0.4)));
 
     let mock_cc_provider = Arc::new(mock_cc_provider);
 
+    // AttachmentStorage
+    let mut mock_storage = MockAttachmentStorage::new();
+    mock_storage.expect_delete().returning(|_| Ok(()));
+
     // Service
     let svc = DbConnTransactionService {
         db: db.clone(),
         dt_provider: mock_dt_service,
         cc_provider: mock_cc_provider,
+        storage: Arc::new(mock_storage),
+        config: Config {
+            idempotency_key_lt_s: 3600,
+            idempotency_purge_interval_s: 3600,
+            max_future_timestamp_s: 300,
+            duplicate_window_s: 86400,
+            duplicate_am_tolerance_pct: 2.0,
+            trash_retention_s: 3600,
+            trash_purge_interval_s: 3600,
+        },
     };
 
     Ok((svc, db))
@@ -159,12 +194,18 @@ async fn test_transact_1(svc: &DbConnTransactionService) -> anyhow::Result<()> {
             account_id: *TEST_ACCOUNT_1_ID, 
             txs: vec![
                 CreateTransactionModel { 
-                    category_id: TEST_CAT_1_ID.clone(),
+                    category_id: Some(TEST_CAT_1_ID.clone()),
                     amount: 1000, 
                     notes: String::from("Notes1"),
-                    currency_type: USD_CURRENCY_TYPE.to_string()
+                    currency_type: USD_CURRENCY_TYPE.to_string(),
+                    splits: None,
+                    timestamp_utc: None,
+                    tag_ids: None,
+                    payee_id: None,
                 }
-            ]
+            ],
+            idempotency_key: None,
+            on_duplicate: None,
         }
     ).await?;
 
@@ -178,30 +219,48 @@ async fn test_transact_2(svc: &DbConnTransactionService) -> anyhow::Result<()> {
             account_id: *TEST_ACCOUNT_1_ID, 
             txs: vec![
                 CreateTransactionModel { 
-                    category_id: *TEST_CAT_1_ID,
+                    category_id: Some(*TEST_CAT_1_ID),
                     amount: 3000, 
                     notes: String::from("Notes2"),
                     currency_type: USD_CURRENCY_TYPE.to_string(),
+                    splits: None,
+                    timestamp_utc: None,
+                    tag_ids: None,
+                    payee_id: None,
                 },
                 CreateTransactionModel { 
-                    category_id: *TEST_CAT_1_ID,
+                    category_id: Some(*TEST_CAT_1_ID),
                     amount: 5000, 
                     notes: String::from("Notes3"),
                     currency_type: USD_CURRENCY_TYPE.to_string(),
+                    splits: None,
+                    timestamp_utc: None,
+                    tag_ids: None,
+                    payee_id: None,
                 },
                 CreateTransactionModel { 
-                    category_id: *TEST_CAT_1_ID,
+                    category_id: Some(*TEST_CAT_1_ID),
                     amount: -1500, 
                     notes: String::from("Notes4"),
                     currency_type: USD_CURRENCY_TYPE.to_string(),
+                    splits: None,
+                    timestamp_utc: None,
+                    tag_ids: None,
+                    payee_id: None,
                 },
                 CreateTransactionModel { 
-                    category_id: *TEST_CAT_2_ID,
+                    category_id: Some(*TEST_CAT_2_ID),
                     amount: -300, 
                     notes: String::from("Notes5"),
                     currency_type: USD_CURRENCY_TYPE.to_string(),
+                    splits: None,
+                    timestamp_utc: None,
+                    tag_ids: None,
+                    payee_id: None,
                 }
-            ]
+            ],
+            idempotency_key: None,
+            on_duplicate: None,
         }
     ).await?;
 
@@ -289,12 +348,18 @@ async fn test_cc_txs() -> anyhow::Result<()> {
             account_id: *TEST_ACCOUNT_1_ID, 
             txs: vec![
                 CreateTransactionModel { 
-                    category_id: *TEST_CAT_1_ID, 
+                    category_id: Some(*TEST_CAT_1_ID), 
                     currency_type: "CAD".to_string(), 
                     amount: 1000, 
                     notes: String::new(),
+                    splits: None,
+                    timestamp_utc: None,
+                    tag_ids: None,
+                    payee_id: None,
                 }
-            ]
+            ],
+            idempotency_key: None,
+            on_duplicate: None,
         }
     ).await?;
     let tx = Transactions::find_by_id(1).one(&db).await?;
@@ -310,21 +375,147 @@ async fn test_cc_txs() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_backdated_transactions() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let create_tx = |timestamp_utc: DateTime<Utc>| CreateTransactionModel {
+        category_id: Some(*TEST_CAT_1_ID),
+        currency_type: "CAD".to_string(),
+        amount: 1000,
+        notes: String::new(),
+        splits: None,
+        timestamp_utc: Some(timestamp_utc),
+        tag_ids: None,
+        payee_id: None,
+    };
+
+    // The historical rate is used when the provider has one for the date, otherwise the latest
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        CreateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            txs: vec![create_tx(*TEST_DT - Duration::days(9)), create_tx(*TEST_DT - Duration::days(5))],
+            idempotency_key: None,
+            on_duplicate: None,
+        }
+    ).await?;
+    assert_eq!(*TEST_DT - Duration::days(9), res.txs[0].timestamp_utc);
+    assert_eq!(400, res.txs[0].am);
+    assert_eq!(Some(0.4), res.txs[0].exchange_rate);
+    assert_eq!(*TEST_DT - Duration::days(5), res.txs[1].timestamp_utc);
+    assert_eq!(500, res.txs[1].am);
+
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        CreateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            txs: vec![create_tx(*TEST_DT + Duration::days(1))],
+            idempotency_key: None,
+            on_duplicate: None,
+        }
+    ).await;
+    assert!(matches!(res, Err(Error::InvalidField("timestamp_utc", _))));
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL + 900, cats[0].balance);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_correct_exchange_rate() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        CreateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            txs: vec![
+                CreateTransactionModel {
+                    category_id: Some(*TEST_CAT_1_ID),
+                    currency_type: "CAD".to_string(),
+                    amount: 1000,
+                    notes: String::new(),
+                    splits: None,
+                    timestamp_utc: None,
+                    tag_ids: None,
+                    payee_id: None,
+                }
+            ],
+            idempotency_key: None,
+            on_duplicate: None,
+        }
+    ).await?;
+    assert_eq!(Some(String::from("CAD")), res.txs[0].orig_currency_type);
+    assert_eq!(Some(1000), res.txs[0].orig_am);
+    assert_eq!(Some(0.5), res.txs[0].exchange_rate);
+
+    // The stored original amount is re-converted with the corrected rate
+    let updated = svc.update_transaction(
+        *TEST_USER_1_ID,
+        UpdateTransactionModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            tx_id: 1,
+            new_amount: None,
+            currency_type: None,
+            new_category_id: None,
+            new_notes: None,
+            new_timestamp_utc: None,
+            new_splits: None,
+            new_exchange_rate: Some(0.6),
+            new_tag_ids: None,
+            new_payee_id: None,
+        }
+    ).await?;
+    assert_eq!(600, updated.am);
+    assert_eq!(Some(1000), updated.orig_am);
+    assert_eq!(Some(0.6), updated.exchange_rate);
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL + 600, cats[0].balance);
+
+    let res = svc.update_transaction(
+        *TEST_USER_1_ID,
+        UpdateTransactionModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            tx_id: 1,
+            new_amount: None,
+            currency_type: None,
+            new_category_id: None,
+            new_notes: None,
+            new_timestamp_utc: None,
+            new_splits: None,
+            new_exchange_rate: Some(-1.0),
+            new_tag_ids: None,
+            new_payee_id: None,
+        }
+    ).await;
+    assert!(matches!(res, Err(Error::InvalidField("new_exchange_rate", _))));
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_delete_transactions() -> anyhow::Result<()> {
     let (svc, db) = create_test_service().await?;
     test_transact_1(&svc).await?;
     test_transact_2(&svc).await?;
 
-    svc.delete_transactions(
+    let delete_on = svc.delete_transactions(
         *TEST_USER_1_ID, 
         DeleteTransactionsModel { 
             account_id: *TEST_ACCOUNT_1_ID, 
             tx_ids: vec![1, 3, 5]
         }
     ).await?;
+    assert_eq!(*TEST_DT + Duration::hours(1), delete_on);
 
-    let txs = Transactions::find().all(&db).await?;
+    // Deleted transactions stay in the trash until purged
+    let (trashed, txs): (Vec<_>, Vec<_>) = Transactions::find().all(&db).await?
+        .into_iter()
+        .partition(|tx| tx.delete_on.is_some());
+    assert_eq!(vec![1, 3, 5], trashed.iter().map(|tx| tx.id).collect::<Vec<i32>>());
     assert_eq!(2, txs.len());
 
     assert_eq!(2, txs[0].id);
@@ -415,8 +606,9 @@ async fn test_get_txs_with_cat_filter() -> anyhow::Result<()> {
             page_size: Some(25), 
             page_idx: Some(0), 
             filters: Some(vec![TransactionFilter::CategoryEq { id: *TEST_CAT_1_ID }]),
+            sort: None,
         }
-    ).await?;
+    ).await?.items;
 
     assert_eq!(4, cat_1_txs.len());
     assert_eq!(1, cat_1_txs[0].id);
@@ -445,9 +637,10 @@ async fn test_get_txs_with_cat_filter() -> anyhow::Result<()> {
             account_id: *TEST_ACCOUNT_1_ID,
             page_size: Some(25), 
             page_idx: Some(0), 
-            filters: Some(vec![TransactionFilter::CategoryEq { id: *TEST_CAT_2_ID }]) 
+            filters: Some(vec![TransactionFilter::CategoryEq { id: *TEST_CAT_2_ID }]),
+            sort: None,
         }
-    ).await?;
+    ).await?.items;
 
     assert_eq!(4, cat_1_txs.len());
     assert_eq!(5, cat_2_txs[0].id);
@@ -456,4 +649,1083 @@ async fn test_get_txs_with_cat_filter() -> anyhow::Result<()> {
     assert_eq!(Some(String::from("Notes5")), cat_2_txs[0].notes);
 
     Ok(())
-}
\ No newline at end of file
+}
+#[tokio::test]
+async fn test_update_transaction() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+    test_transact_1(&svc).await?;
+
+    let updated = svc.update_transaction(
+        *TEST_USER_1_ID,
+        UpdateTransactionModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            tx_id: 1,
+            new_amount: Some(1200),
+            currency_type: Some(String::from("CAD")),
+            new_category_id: Some(*TEST_CAT_2_ID),
+            new_notes: Some(String::from("NewNotes1")),
+            new_timestamp_utc: None,
+            new_splits: None,
+            new_exchange_rate: None,
+            new_tag_ids: None,
+            new_payee_id: None,
+        }
+    ).await?;
+
+    assert_eq!(1, updated.id);
+    assert_eq!(600, updated.am);
+    assert_eq!(Some(*TEST_CAT_2_ID), updated.cat_id);
+    assert_eq!(*TEST_DT, updated.timestamp_utc);
+    assert_eq!(Some(String::from("NewNotes1")), updated.notes);
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL, cats[0].balance);
+    assert_eq!(*TEST_CAT_2_ORIG_BAL + 600, cats[1].balance);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_transaction_keeps_orig_currency() -> anyhow::Result<()> {
+    let (svc, _) = create_test_service().await?;
+    test_transact_1(&svc).await?;
+
+    let update_req = |new_amount, currency_type| UpdateTransactionModel {
+        account_id: *TEST_ACCOUNT_1_ID,
+        tx_id: 1,
+        new_amount: Some(new_amount),
+        currency_type,
+        new_category_id: None,
+        new_notes: None,
+        new_timestamp_utc: None,
+        new_splits: None,
+        new_exchange_rate: None,
+        new_tag_ids: None,
+        new_payee_id: None,
+    };
+    svc.update_transaction(*TEST_USER_1_ID, update_req(1200, Some(String::from("CAD")))).await?;
+
+    // The new amount is taken to be in the transaction's original currency
+    let updated = svc.update_transaction(*TEST_USER_1_ID, update_req(1000, None)).await?;
+    assert_eq!(500, updated.am);
+    assert_eq!(Some(1000), updated.orig_am);
+    assert_eq!(Some(String::from("CAD")), updated.orig_currency_type);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_transaction_category_not_in_account() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+    test_transact_1(&svc).await?;
+
+    let test_id = Uuid::now_v7();
+    let res = svc.update_transaction(
+        *TEST_USER_1_ID,
+        UpdateTransactionModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            tx_id: 1,
+            new_amount: None,
+            currency_type: None,
+            new_category_id: Some(test_id),
+            new_notes: None,
+            new_timestamp_utc: None,
+            new_splits: None,
+            new_exchange_rate: None,
+            new_tag_ids: None,
+            new_payee_id: None,
+        }
+    ).await;
+
+    assert!(matches!(res, Err(Error::CategoryNotFound(id)) if id == test_id));
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL + 1000, cats[0].balance);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_txs_with_combined_filters() -> anyhow::Result<()> {
+    let (svc, _db) = create_test_service().await?;
+    test_transact_1(&svc).await?;
+    test_transact_2(&svc).await?;
+
+    let get_ids = |filters: Vec<TransactionFilter>| {
+        let svc = &svc;
+        async move {
+            svc.get_transactions(
+                *TEST_USER_1_ID,
+                GetTransactionReqModel {
+                    account_id: *TEST_ACCOUNT_1_ID,
+                    page_size: Some(25),
+                    page_idx: Some(0),
+                    filters: Some(filters),
+                    sort: None,
+                }
+            ).await.map(|page| page.items.into_iter().map(|tx| tx.id).collect::<Vec<i32>>())
+        }
+    };
+
+    // Notes matching is case-insensitive
+    assert_eq!(vec![3], get_ids(vec![TransactionFilter::NotesContains { text: String::from("nOTES3") }]).await?);
+
+    assert_eq!(
+        vec![5], 
+        get_ids(vec![
+            TransactionFilter::Not { filter: Box::new(TransactionFilter::CategoryEq { id: *TEST_CAT_1_ID }) }
+        ]).await?
+    );
+
+    assert_eq!(
+        vec![1, 4, 5], 
+        get_ids(vec![
+            TransactionFilter::Any { 
+                filters: vec![
+                    TransactionFilter::Cmp { cmp: Cmp::Lt, val: 0 },
+                    TransactionFilter::NotesContains { text: String::from("1") },
+                ]
+            }
+        ]).await?
+    );
+
+    assert_eq!(
+        vec![1, 2, 3, 4, 5], 
+        get_ids(vec![
+            TransactionFilter::Between { from: *TEST_DT, to: *TEST_DT + Duration::days(1) },
+            TransactionFilter::UserEq { id: *TEST_USER_1_ID },
+            TransactionFilter::IsRefill { val: false },
+        ]).await?
+    );
+
+    assert!(get_ids(vec![TransactionFilter::Before { ts: *TEST_DT }]).await?.is_empty());
+    assert!(get_ids(vec![TransactionFilter::Uncategorized]).await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_get_txs_page_totals_and_sort() -> anyhow::Result<()> {
+    let (svc, _db) = create_test_service().await?;
+    test_transact_1(&svc).await?;
+    test_transact_2(&svc).await?;
+
+    let page = svc.get_transactions(
+        *TEST_USER_1_ID,
+        GetTransactionReqModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            page_size: Some(2),
+            page_idx: Some(0),
+            filters: Some(vec![TransactionFilter::CategoryEq { id: *TEST_CAT_1_ID }]),
+            sort: Some(TransactionSort { field: TransactionSortField::Amount, dir: SortDir::Desc }),
+        }
+    ).await?;
+
+    assert_eq!(4, page.total_count);
+    assert_eq!(2, page.page_count);
+    assert_eq!(1000 + 3000 + 5000 - 1500, page.total_am);
+
+    assert_eq!(2, page.items.len());
+    assert_eq!(3, page.items[0].id);
+    assert_eq!(2, page.items[1].id);
+
+    let res = svc.get_transactions(
+        *TEST_USER_1_ID,
+        GetTransactionReqModel { account_id: *TEST_ACCOUNT_1_ID, page_size: Some(0), page_idx: Some(0), filters: None, sort: None }
+    ).await;
+    assert!(matches!(res, Err(Error::InvalidField("page_size", _))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scroll_transactions() -> anyhow::Result<()> {
+    let (svc, _db) = create_test_service().await?;
+    test_transact_2(&svc).await?;
+
+    let scroll_with_limit = |cursor: Option<String>, dir: ScrollDir, limit: u64| {
+        let svc = &svc;
+        async move {
+            svc.scroll_transactions(
+                *TEST_USER_1_ID,
+                ScrollTransactionsReqModel {
+                    account_id: *TEST_ACCOUNT_1_ID,
+                    limit: Some(limit),
+                    cursor,
+                    dir: Some(dir),
+                    filters: None,
+                }
+            ).await
+        }
+    };
+    let scroll = |cursor: Option<String>, dir: ScrollDir| scroll_with_limit(cursor, dir, 3);
+
+    let page = scroll(None, ScrollDir::Older).await?;
+    assert_eq!(vec![4, 3, 2], page.items.iter().map(|tx| tx.id).collect::<Vec<i32>>());
+
+    // A transaction added while scrolling does not shift the next page
+    test_transact_1(&svc).await?;
+    let newer_cursor = page.newer_cursor.clone();
+    let page = scroll(page.older_cursor, ScrollDir::Older).await?;
+    assert_eq!(vec![1], page.items.iter().map(|tx| tx.id).collect::<Vec<i32>>());
+    assert_eq!(None, page.older_cursor);
+
+    let page = scroll(newer_cursor, ScrollDir::Newer).await?;
+    assert_eq!(vec![5], page.items.iter().map(|tx| tx.id).collect::<Vec<i32>>());
+    assert_eq!(Some(TransactionCursor { id: 5 }.encode()), page.newer_cursor);
+
+    let res = scroll(Some(String::from("not-a-cursor")), ScrollDir::Older).await;
+    assert!(matches!(res, Err(Error::InvalidCursor(_))));
+
+    for limit in [0, u64::MAX] {
+        let res = scroll_with_limit(None, ScrollDir::Older, limit).await;
+        assert!(matches!(res, Err(Error::InvalidField("limit", _))));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_import_csv_preview_and_commit() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let contents = "Date,Description,Amount,Currency\n\
+        11/01/2024,Grocery Store,\"1,250.40\",USD\n\
+        11/02/2024,Coffee,abc,USD\n\
+        11/03/2024,Maple Syrup,(20.00),cad\n";
+    let import_req = |preview: bool| ImportCsvModel {
+        account_id: *TEST_ACCOUNT_1_ID,
+        mapping: CsvMappingModel {
+            has_headers: true,
+            delimiter: None,
+            date_col: CsvColumn::Header(String::from("Date")),
+            date_format: String::from("%m/%d/%Y"),
+            amount_col: CsvColumn::Index(2),
+            description_col: Some(CsvColumn::Header(String::from("description"))),
+            currency_col: Some(CsvColumn::Header(String::from("Currency"))),
+            default_currency_type: None,
+            sign: SignConvention::Inverted,
+            category_id: Some(*TEST_CAT_1_ID),
+        },
+        preview,
+        contents: contents.as_bytes().to_vec(),
+    };
+
+    // Previewing reports each row without writing anything
+    let report = svc.import_csv(*TEST_USER_1_ID, import_req(true)).await?;
+    assert!(!report.committed);
+    assert_eq!(2, report.imported_count);
+    assert_eq!(3, report.rows.len());
+    assert_eq!(Some(-125040), report.rows[0].usd_am);
+    assert!(report.rows[1].error.is_some());
+    assert_eq!(Some(1000), report.rows[2].usd_am);
+    assert!(Transactions::find().all(&db).await?.is_empty());
+
+    let report = svc.import_csv(*TEST_USER_1_ID, import_req(false)).await?;
+    assert!(report.committed);
+
+    let txs = Transactions::find().all(&db).await?;
+    assert_eq!(2, txs.len());
+    assert_eq!(-125040, txs[0].amount);
+    assert_eq!(Some(String::from("Grocery Store")), txs[0].notes);
+    assert_eq!(Some(*TEST_CAT_1_ID), txs[0].category_id);
+    assert_eq!(1000, txs[1].amount);
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL - 125040 + 1000, cats[0].balance);
+
+    // Only single byte delimiters can be split on
+    let mut bad_delimiter = import_req(true);
+    bad_delimiter.mapping.delimiter = Some('§');
+    let res = svc.import_csv(*TEST_USER_1_ID, bad_delimiter).await;
+    assert!(matches!(res, Err(Error::InvalidMapping(_))));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_import_looks_up_each_rate_once() -> anyhow::Result<()> {
+    let (mut svc, _) = create_test_service().await?;
+
+    // Each currency and date is only looked up once per import
+    let mut mock_cc_provider = MockCurrencyConversionProvider::new();
+    mock_cc_provider.expect_get_historical_conversion()
+        .with(eq("CAD"), eq(USD_CURRENCY_TYPE), eq(NaiveDate::from_ymd_opt(2024, 11, 1).unwrap()))
+        .times(1)
+        .returning(|_, _, _| Ok(Some(0.4)));
+    mock_cc_provider.expect_get_historical_conversion()
+        .with(eq("CAD"), eq(USD_CURRENCY_TYPE), eq(NaiveDate::from_ymd_opt(2024, 11, 2).unwrap()))
+        .times(1)
+        .returning(|_, _, _| Ok(None));
+    mock_cc_provider.expect_convert_with_rate()
+        .with(eq("CAD"), eq(USD_CURRENCY_TYPE), always())
+        .times(1)
+        .returning(|_, _, am| Ok(((am as f64 * 0.5).floor() as i64, 0.5)));
+    svc.cc_provider = Arc::new(mock_cc_provider);
+
+    let statement = "<OFX><CURDEF>CAD\n\
+        <STMTTRN><DTPOSTED>20241101<TRNAMT>-20.00<FITID>B1<NAME>Hardware Store</STMTTRN>\n\
+        <STMTTRN><DTPOSTED>20241101<TRNAMT>-10.00<FITID>B2<NAME>Hardware Store</STMTTRN>\n\
+        <STMTTRN><DTPOSTED>20241102<TRNAMT>-4.50<FITID>B3<NAME>Coffee</STMTTRN>\n\
+        <STMTTRN><DTPOSTED>20241102<TRNAMT>-3.00<FITID>B4<NAME>Coffee</STMTTRN>\n\
+        </OFX>";
+    let report = svc.import_ofx(*TEST_USER_1_ID, ImportOfxModel {
+        account_id: *TEST_ACCOUNT_1_ID,
+        category_id: None,
+        preview: true,
+        contents: statement.as_bytes().to_vec(),
+    }).await?;
+
+    let usd_ams = report.rows.iter().map(|row| row.usd_am).collect::<Vec<_>>();
+    assert_eq!(vec![Some(-800), Some(-400), Some(-225), Some(-150)], usd_ams);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_import_ofx_skips_imported_fit_ids() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let statement_1 = "OFXHEADER:100\nDATA:OFXSGML\n\
+        <OFX><BANKMSGSRSV1><STMTTRNRS><STMTRS><CURDEF>CAD<BANKTRANLIST>\n\
+        <STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20241101120000[-5:EST]<TRNAMT>-20.00<FITID>A1<NAME>Hardware Store</STMTTRN>\n\
+        <STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20241102<TRNAMT>-4.50<FITID>A2<NAME>Coffee<MEMO>Latte</STMTTRN>\n\
+        </BANKTRANLIST></STMTRS></STMTTRNRS></BANKMSGSRSV1></OFX>";
+    // Overlaps the 1st statement by one transaction
+    let statement_2 = "<OFX><CURDEF>CAD\n\
+        <STMTTRN><DTPOSTED>20241102<TRNAMT>-4.50<FITID>A2<NAME>Coffee<MEMO>Latte</STMTTRN>\n\
+        <STMTTRN><DTPOSTED>20241103<TRNAMT>100.00<FITID>A3<NAME>Refund</STMTTRN>\n\
+        </OFX>";
+
+    let import_req = |contents: &str| ImportOfxModel {
+        account_id: *TEST_ACCOUNT_1_ID,
+        category_id: Some(*TEST_CAT_1_ID),
+        preview: false,
+        contents: contents.as_bytes().to_vec(),
+    };
+
+    let report = svc.import_ofx(*TEST_USER_1_ID, import_req(statement_1)).await?;
+    assert_eq!(2, report.imported_count);
+    let report = svc.import_ofx(*TEST_USER_1_ID, import_req(statement_2)).await?;
+    assert_eq!(1, report.imported_count);
+    assert!(report.rows[0].skipped);
+
+    let txs = Transactions::find().all(&db).await?;
+    assert_eq!(3, txs.len());
+    // Converted at the historical rate for Nov. 1st
+    assert_eq!(-800, txs[0].amount);
+    assert_eq!(DateTime::parse_from_rfc3339("2024-11-01T17:00:00Z")?.to_utc(), txs[0].timestamp);
+    assert_eq!(Some(String::from("A1")), txs[0].external_id);
+    assert_eq!(Some(String::from("Coffee - Latte")), txs[1].notes);
+    assert_eq!(5000, txs[2].amount);
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL - 800 - 225 + 5000, cats[0].balance);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_export_transactions() -> anyhow::Result<()> {
+    let (svc, _db) = create_test_service().await?;
+    test_transact_2(&svc).await?;
+
+    let export = |format: ExportFormat, filters: Option<Vec<TransactionFilter>>| {
+        let svc = &svc;
+        async move {
+            let chunks = svc.export_transactions(
+                *TEST_USER_1_ID,
+                ExportTransactionsReqModel { account_id: *TEST_ACCOUNT_1_ID, format, filters }
+            ).await?
+                .try_collect::<Vec<Vec<u8>>>().await?;
+            anyhow::Ok(String::from_utf8(chunks.concat())?)
+        }
+    };
+
+    let csv = export(ExportFormat::Csv, Some(vec![TransactionFilter::CategoryEq { id: *TEST_CAT_2_ID }])).await?;
+    let lines = csv.lines().collect::<Vec<&str>>();
+    assert_eq!(2, lines.len());
+    assert_eq!("id,timestamp_utc,am,category,user_id,notes,is_refill", lines[0]);
+    assert!(lines[1].starts_with("4,"));
+    assert!(lines[1].contains(",-300,Cat2,"));
+    assert!(lines[1].contains(",Notes5,false"));
+
+    let ndjson = export(ExportFormat::Ndjson, None).await?;
+    let txs = ndjson.lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(4, txs.len());
+    assert_eq!("Cat1", txs[0]["category"]);
+    assert_eq!(3000, txs[0]["am"]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_split_transaction() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let create = |splits: Vec<(Uuid, i64)>| {
+        let svc = &svc;
+        async move {
+            svc.create_transactions(
+                *TEST_USER_1_ID,
+                CreateTransactionsModel {
+                    account_id: *TEST_ACCOUNT_1_ID,
+                    txs: vec![
+                        CreateTransactionModel {
+                            category_id: None,
+                            currency_type: "CAD".to_string(),
+                            amount: -1000,
+                            notes: String::from("Groceries and pharmacy"),
+                            splits: Some(
+                                splits.into_iter()
+                                    .map(|(category_id, amount)| CreateSplitModel { category_id, amount })
+                                    .collect()
+                            ),
+                            timestamp_utc: None,
+                            tag_ids: None,
+                            payee_id: None,
+                        }
+                    ],
+                    idempotency_key: None,
+                    on_duplicate: None,
+                }
+            ).await
+        }
+    };
+
+    // Splits must sum to the transaction amount
+    let res = create(vec![(*TEST_CAT_1_ID, -600), (*TEST_CAT_2_ID, -300)]).await;
+    assert!(matches!(res, Err(Error::InvalidSplits(_))));
+
+    create(vec![(*TEST_CAT_1_ID, -600), (*TEST_CAT_2_ID, -400)]).await?;
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL - 300, cats[0].balance);
+    assert_eq!(*TEST_CAT_2_ORIG_BAL - 200, cats[1].balance);
+
+    // The transaction is found by either of its split categories, and is not uncategorized
+    let page = svc.get_transactions(
+        *TEST_USER_1_ID,
+        GetTransactionReqModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            page_size: None,
+            page_idx: None,
+            filters: Some(vec![TransactionFilter::CategoryEq { id: *TEST_CAT_2_ID }]),
+            sort: None,
+        }
+    ).await?;
+    assert_eq!(1, page.items.len());
+    assert_eq!(None, page.items[0].cat_id);
+    assert_eq!(-500, page.items[0].am);
+    assert_eq!(
+        vec![SplitModel { cat_id: *TEST_CAT_1_ID, am: -300 }, SplitModel { cat_id: *TEST_CAT_2_ID, am: -200 }],
+        page.items[0].splits
+    );
+
+    let page = svc.get_transactions(
+        *TEST_USER_1_ID,
+        GetTransactionReqModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            page_size: None,
+            page_idx: None,
+            filters: Some(vec![TransactionFilter::Uncategorized]),
+            sort: None,
+        }
+    ).await?;
+    assert_eq!(0, page.items.len());
+
+    // Moving the transaction into a single category removes its splits
+    let updated = svc.update_transaction(
+        *TEST_USER_1_ID,
+        UpdateTransactionModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            tx_id: 1,
+            new_amount: None,
+            currency_type: None,
+            new_category_id: Some(*TEST_CAT_2_ID),
+            new_notes: None,
+            new_timestamp_utc: None,
+            new_splits: None,
+            new_exchange_rate: None,
+            new_tag_ids: None,
+            new_payee_id: None,
+        }
+    ).await?;
+    assert_eq!(Some(*TEST_CAT_2_ID), updated.cat_id);
+    assert!(updated.splits.is_empty());
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL, cats[0].balance);
+    assert_eq!(*TEST_CAT_2_ORIG_BAL - 500, cats[1].balance);
+    assert_eq!(0, TransactionSplits::find().all(&db).await?.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_create_transactions_idempotency_key_replays() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let create = || {
+        let svc = &svc;
+        async move {
+            svc.create_transactions(
+                *TEST_USER_1_ID,
+                CreateTransactionsModel {
+                    account_id: *TEST_ACCOUNT_1_ID,
+                    txs: vec![
+                        CreateTransactionModel {
+                            category_id: Some(*TEST_CAT_1_ID),
+                            currency_type: USD_CURRENCY_TYPE.to_string(),
+                            amount: -700,
+                            notes: String::from("Coffee"),
+                            splits: None,
+                            timestamp_utc: None,
+                            tag_ids: None,
+                            payee_id: None,
+                        }
+                    ],
+                    idempotency_key: Some(String::from("retry-key-1")),
+                    on_duplicate: None,
+                }
+            ).await
+        }
+    };
+
+    let res = create().await?;
+    assert!(!res.replayed);
+    assert_eq!(1, res.txs.len());
+
+    // The retry replays the original transactions without inserting again
+    let retry = create().await?;
+    assert!(retry.replayed);
+    assert_eq!(res.txs[0].id, retry.txs[0].id);
+    assert_eq!(1, Transactions::find().all(&db).await?.len());
+
+    // Results stored before transactions had splits and tags still replay
+    let ex_key = IdempotencyKeys::find().one(&db).await?.unwrap();
+    let mut stored = serde_json::from_str::<serde_json::Value>(&ex_key.response)?;
+    for tx in stored.as_array_mut().unwrap() {
+        let tx = tx.as_object_mut().unwrap();
+        tx.remove("splits");
+        tx.remove("tag_ids");
+    }
+    let mut ex_key = ex_key.into_active_model();
+    ex_key.response = Set(stored.to_string());
+    IdempotencyKeys::update(ex_key).exec(&db).await?;
+
+    let retry = create().await?;
+    assert!(retry.replayed);
+    assert!(retry.txs[0].splits.is_empty());
+    assert!(retry.txs[0].tag_ids.is_empty());
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL - 700, cats[0].balance);
+
+    // The key is within its window, so it is not purged
+    assert_eq!(0, svc.purge_idempotency_keys().await?);
+    assert_eq!(1, IdempotencyKeys::find().all(&db).await?.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tagged_transactions() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let (vacation_id, reimbursable_id) = (Uuid::now_v7(), Uuid::now_v7());
+    Tags::insert_many([
+        tags::ActiveModel { id: Set(vacation_id), account_id: Set(*TEST_ACCOUNT_1_ID), name: Set(String::from("vacation-2026")) },
+        tags::ActiveModel { id: Set(reimbursable_id), account_id: Set(*TEST_ACCOUNT_1_ID), name: Set(String::from("reimbursable")) },
+    ]).exec(&db).await?;
+
+    let create_tx = |amount: i64, tag_ids: Vec<Uuid>| CreateTransactionModel {
+        category_id: Some(*TEST_CAT_1_ID),
+        currency_type: USD_CURRENCY_TYPE.to_string(),
+        amount,
+        notes: String::new(),
+        splits: None,
+        timestamp_utc: None,
+        tag_ids: Some(tag_ids),
+        payee_id: None,
+    };
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        CreateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            txs: vec![
+                create_tx(-1000, vec![vacation_id, vacation_id]),
+                create_tx(-2000, vec![vacation_id, reimbursable_id]),
+                create_tx(-3000, vec![]),
+            ],
+            idempotency_key: None,
+            on_duplicate: None,
+        }
+    ).await?;
+    assert_eq!(vec![vacation_id], res.txs[0].tag_ids);
+    assert_eq!(2, res.txs[1].tag_ids.len());
+    assert!(res.txs[2].tag_ids.is_empty());
+
+    let get_tagged = |ids: Vec<Uuid>| GetTransactionReqModel {
+        account_id: *TEST_ACCOUNT_1_ID,
+        page_size: Some(25),
+        page_idx: Some(0),
+        filters: Some(vec![TransactionFilter::HasTags { ids }]),
+        sort: None,
+    };
+    let page = svc.get_transactions(*TEST_USER_1_ID, get_tagged(vec![vacation_id])).await?;
+    assert_eq!(vec![1, 2], page.items.iter().map(|tx| tx.id).collect::<Vec<_>>());
+    let page = svc.get_transactions(*TEST_USER_1_ID, get_tagged(vec![vacation_id, reimbursable_id])).await?;
+    assert_eq!(vec![2], page.items.iter().map(|tx| tx.id).collect::<Vec<_>>());
+
+    // Tags are replaced on update, and must belong to the account
+    let updated = svc.update_transaction(
+        *TEST_USER_1_ID,
+        UpdateTransactionModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            tx_id: 3,
+            new_amount: None,
+            currency_type: None,
+            new_category_id: None,
+            new_notes: None,
+            new_timestamp_utc: None,
+            new_splits: None,
+            new_exchange_rate: None,
+            new_tag_ids: Some(vec![reimbursable_id]),
+            new_payee_id: None,
+        }
+    ).await?;
+    assert_eq!(vec![reimbursable_id], updated.tag_ids);
+
+    let test_id = Uuid::now_v7();
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        CreateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            txs: vec![create_tx(-1000, vec![test_id])],
+            idempotency_key: None,
+            on_duplicate: None,
+        }
+    ).await;
+    assert!(matches!(res, Err(Error::TagNotFound(id)) if id == test_id));
+
+    // Tags are kept in the trash, and removed once the transaction is purged
+    svc.delete_transactions(*TEST_USER_1_ID, DeleteTransactionsModel { account_id: *TEST_ACCOUNT_1_ID, tx_ids: vec![2] }).await?;
+    assert_eq!(4, TransactionTags::find().all(&db).await?.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_purge_trash_deletes_attachments() -> anyhow::Result<()> {
+    let (mut svc, db) = create_test_service().await?;
+    test_transact_1(&svc).await?;
+
+    let attachment_id = Uuid::now_v7();
+    Attachments::insert(
+        attachments::ActiveModel {
+            id: Set(attachment_id),
+            account_id: Set(*TEST_ACCOUNT_1_ID),
+            transaction_id: Set(1),
+            file_name: Set(String::from("receipt.pdf")),
+            content_type: Set(String::from("application/pdf")),
+            size_bytes: Set(1024),
+            created_on_utc: Set(*TEST_DT),
+        }
+    ).exec(&db).await?;
+
+    let mut mock_storage = MockAttachmentStorage::new();
+    mock_storage.expect_delete()
+        .with(eq(attachment_id.to_string()))
+        .times(1)
+        .returning(|_| Ok(()));
+    svc.storage = Arc::new(mock_storage);
+    svc.config.trash_retention_s = 0;
+
+    svc.delete_transactions(*TEST_USER_1_ID, DeleteTransactionsModel { account_id: *TEST_ACCOUNT_1_ID, tx_ids: vec![1] }).await?;
+    assert_eq!(1, Attachments::find().all(&db).await?.len());
+
+    assert_eq!(1, svc.purge_trash().await?);
+    assert!(Attachments::find().all(&db).await?.is_empty());
+    assert!(Transactions::find().all(&db).await?.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_payee_default_category() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let payee_id = Uuid::now_v7();
+    Payees::insert(
+        payees::ActiveModel {
+            id: Set(payee_id),
+            account_id: Set(*TEST_ACCOUNT_1_ID),
+            name: Set(String::from("Costco")),
+            default_category_id: Set(Some(*TEST_CAT_2_ID)),
+        }
+    ).exec(&db).await?;
+
+    let create_tx = |category_id: Option<Uuid>, payee_id: Option<Uuid>| CreateTransactionModel {
+        category_id,
+        currency_type: USD_CURRENCY_TYPE.to_string(),
+        amount: -1000,
+        notes: String::new(),
+        splits: None,
+        timestamp_utc: None,
+        tag_ids: None,
+        payee_id,
+    };
+
+    // The payee's default category only applies when no category is supplied
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        CreateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            txs: vec![
+                create_tx(None, Some(payee_id)),
+                create_tx(Some(*TEST_CAT_1_ID), Some(payee_id)),
+                create_tx(None, None),
+            ],
+            idempotency_key: None,
+            on_duplicate: None,
+        }
+    ).await?;
+    assert_eq!(Some(*TEST_CAT_2_ID), res.txs[0].cat_id);
+    assert_eq!(Some(payee_id), res.txs[0].payee_id);
+    assert_eq!(Some(*TEST_CAT_1_ID), res.txs[1].cat_id);
+    assert_eq!(None, res.txs[2].cat_id);
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL - 1000, cats[0].balance);
+    assert_eq!(*TEST_CAT_2_ORIG_BAL - 1000, cats[1].balance);
+
+    let test_id = Uuid::now_v7();
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        CreateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            txs: vec![create_tx(None, Some(test_id))],
+            idempotency_key: None,
+            on_duplicate: None,
+        }
+    ).await;
+    assert!(matches!(res, Err(Error::PayeeNotFound(id)) if id == test_id));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rules_applied_on_create() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let payee_id = Uuid::now_v7();
+    Payees::insert(
+        payees::ActiveModel {
+            id: Set(payee_id),
+            account_id: Set(*TEST_ACCOUNT_1_ID),
+            name: Set(String::from("Costco Wholesale")),
+            default_category_id: Set(Some(*TEST_CAT_2_ID)),
+        }
+    ).exec(&db).await?;
+    CategorizationRules::insert(
+        categorization_rules::ActiveModel {
+            id: Set(Uuid::now_v7()),
+            account_id: Set(*TEST_ACCOUNT_1_ID),
+            name: Set(String::from("Costco")),
+            priority: Set(0),
+            payee_pattern: Set(Some(String::from("^costco"))),
+            set_category_id: Set(Some(*TEST_CAT_1_ID)),
+            add_tag_ids: Set(String::from("[]")),
+            set_notes: Set(Some(String::from("Groceries"))),
+
+            ..Default::default()
+        }
+    ).exec(&db).await?;
+
+    let create_tx = |category_id: Option<Uuid>, payee_id: Option<Uuid>| CreateTransactionModel {
+        category_id,
+        currency_type: USD_CURRENCY_TYPE.to_string(),
+        amount: -1000,
+        notes: String::from("membership"),
+        splits: None,
+        timestamp_utc: None,
+        tag_ids: None,
+        payee_id,
+    };
+
+    // Rules take precedence over the payee's default category, but not an explicit category
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        CreateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            txs: vec![
+                create_tx(None, Some(payee_id)),
+                create_tx(Some(*TEST_CAT_2_ID), Some(payee_id)),
+                create_tx(None, None),
+            ],
+            idempotency_key: None,
+            on_duplicate: None,
+        }
+    ).await?;
+    assert_eq!(Some(*TEST_CAT_1_ID), res.txs[0].cat_id);
+    assert_eq!(Some(String::from("Groceries")), res.txs[0].notes);
+    assert_eq!(Some(*TEST_CAT_2_ID), res.txs[1].cat_id);
+    assert_eq!(Some(String::from("Groceries")), res.txs[1].notes);
+    assert_eq!(None, res.txs[2].cat_id);
+    assert_eq!(Some(String::from("membership")), res.txs[2].notes);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_duplicate_detection() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let create_tx = |amount: i64, notes: &str, hours_ago: i64| CreateTransactionModel {
+        category_id: Some(*TEST_CAT_1_ID),
+        currency_type: USD_CURRENCY_TYPE.to_string(),
+        amount,
+        notes: String::from(notes),
+        splits: None,
+        timestamp_utc: Some(*TEST_DT - Duration::hours(hours_ago)),
+        tag_ids: None,
+        payee_id: None,
+    };
+    let create_req = |txs: Vec<CreateTransactionModel>, on_duplicate: Option<DuplicatePolicy>| CreateTransactionsModel {
+        account_id: *TEST_ACCOUNT_1_ID,
+        txs,
+        idempotency_key: None,
+        on_duplicate,
+    };
+
+    let res = svc.create_transactions(*TEST_USER_1_ID, create_req(vec![create_tx(-5000, "Costco groceries", 3)], None)).await?;
+    assert!(res.duplicates.is_empty());
+    let orig_id = res.txs[0].id;
+
+    // Rejected duplicates create nothing
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        create_req(vec![create_tx(-2000, "Gas", 1), create_tx(-5050, "COSTCO", 1)], Some(DuplicatePolicy::Reject))
+    ).await;
+    let Err(Error::ProbableDuplicates(duplicates)) = res else { panic!("expected probable duplicates") };
+    assert_eq!(1, duplicates.len());
+    assert_eq!(1, duplicates[0].idx);
+    assert_eq!(orig_id, duplicates[0].duplicate_of[0].id);
+    assert_eq!(1, Transactions::find().all(&db).await?.len());
+
+    // By default they are created, with a warning
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        create_req(vec![create_tx(-2000, "Gas", 1), create_tx(-5050, "COSTCO", 1)], None)
+    ).await?;
+    assert_eq!(2, res.txs.len());
+    assert_eq!(1, res.duplicates.len());
+    let dup_id = res.txs[1].id;
+
+    // Different notes, a different amount, or a different day are not duplicates
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        create_req(vec![create_tx(-5000, "Hardware store", 3), create_tx(-6000, "Costco groceries", 3), create_tx(-5000, "Costco groceries", 72)], Some(DuplicatePolicy::Reject))
+    ).await?;
+    assert!(res.duplicates.is_empty());
+
+    let suspected = svc.find_duplicates(*TEST_USER_1_ID, FindDuplicatesReqModel { account_id: *TEST_ACCOUNT_1_ID, filters: None }).await?;
+    assert_eq!(1, suspected.len());
+    assert_eq!(dup_id, suspected[0].tx.id);
+    assert_eq!(vec![orig_id], suspected[0].duplicate_of.iter().map(|tx| tx.id).collect::<Vec<i32>>());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bulk_update() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+
+    let tag_id = Uuid::now_v7();
+    Tags::insert(
+        tags::ActiveModel { id: Set(tag_id), account_id: Set(*TEST_ACCOUNT_1_ID), name: Set(String::from("cleanup")) }
+    ).exec(&db).await?;
+
+    let create_tx = |category_id: Option<Uuid>, amount: i64, splits: Option<Vec<CreateSplitModel>>| CreateTransactionModel {
+        category_id,
+        currency_type: USD_CURRENCY_TYPE.to_string(),
+        amount,
+        notes: format!("tx {}", amount),
+        splits,
+        timestamp_utc: None,
+        tag_ids: None,
+        payee_id: None,
+    };
+    let res = svc.create_transactions(
+        *TEST_USER_1_ID,
+        CreateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            txs: vec![
+                create_tx(Some(*TEST_CAT_1_ID), -1000, None),
+                create_tx(None, -3000, Some(vec![
+                    CreateSplitModel { category_id: *TEST_CAT_1_ID, amount: -1000 },
+                    CreateSplitModel { category_id: *TEST_CAT_2_ID, amount: -2000 },
+                ])),
+                create_tx(Some(*TEST_CAT_2_ID), -500, None),
+            ],
+            idempotency_key: None,
+            on_duplicate: None,
+        }
+    ).await?;
+    let tx_ids = res.txs.iter().map(|tx| tx.id).collect::<Vec<i32>>();
+
+    // Every transaction with any part in Cat1 moves wholly into Cat2
+    let res = svc.bulk_update_transactions(
+        *TEST_USER_1_ID,
+        BulkUpdateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            tx_ids: None,
+            filters: Some(vec![TransactionFilter::CategoryEq { id: *TEST_CAT_1_ID }]),
+            new_category_id: Some(*TEST_CAT_2_ID),
+            add_tag_ids: Some(vec![tag_id]),
+            remove_tag_ids: None,
+            new_notes: None,
+        }
+    ).await?;
+    assert_eq!(vec![tx_ids[0], tx_ids[1]], res.tx_ids);
+
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(*TEST_CAT_1_ORIG_BAL, cats[0].balance);
+    assert_eq!(*TEST_CAT_2_ORIG_BAL - 4500, cats[1].balance);
+    assert!(TransactionSplits::find().all(&db).await?.is_empty());
+    assert_eq!(2, TransactionTags::find().all(&db).await?.len());
+
+    // Re-adding a tag is a no-op, while notes can be rewritten by ID
+    svc.bulk_update_transactions(
+        *TEST_USER_1_ID,
+        BulkUpdateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            tx_ids: Some(tx_ids.clone()),
+            filters: None,
+            new_category_id: None,
+            add_tag_ids: Some(vec![tag_id]),
+            remove_tag_ids: None,
+            new_notes: Some(String::from("Reorganized")),
+        }
+    ).await?;
+    assert_eq!(3, TransactionTags::find().all(&db).await?.len());
+    assert!(Transactions::find().all(&db).await?.iter().all(|tx| tx.notes == Some(String::from("Reorganized"))));
+
+    let res = svc.bulk_update_transactions(
+        *TEST_USER_1_ID,
+        BulkUpdateTransactionsModel {
+            account_id: *TEST_ACCOUNT_1_ID,
+            tx_ids: Some(vec![tx_ids[0], 9999]),
+            filters: None,
+            new_category_id: Some(*TEST_CAT_1_ID),
+            add_tag_ids: None,
+            remove_tag_ids: Some(vec![tag_id]),
+            new_notes: None,
+        }
+    ).await;
+    assert!(matches!(res, Err(Error::AccountDoesNotOwnTransaction(_, 9999))));
+    assert_eq!(3, TransactionTags::find().all(&db).await?.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_trash_and_restore() -> anyhow::Result<()> {
+    let (mut svc, db) = create_test_service().await?;
+    test_transact_1(&svc).await?;
+    test_transact_2(&svc).await?;
+
+    svc.delete_transactions(*TEST_USER_1_ID, DeleteTransactionsModel { account_id: *TEST_ACCOUNT_1_ID, tx_ids: vec![1, 2] }).await?;
+    let cats = Categories::find().all(&db).await?;
+    let deleted_bal = cats[0].balance;
+
+    // Trashed transactions are hidden from queries, and cannot be changed or deleted again
+    let page = svc.get_transactions(
+        *TEST_USER_1_ID,
+        GetTransactionReqModel { account_id: *TEST_ACCOUNT_1_ID, page_size: Some(25), page_idx: Some(0), filters: None, sort: None }
+    ).await?;
+    assert!(page.items.iter().all(|tx| tx.id > 2));
+    let res = svc.delete_transactions(*TEST_USER_1_ID, DeleteTransactionsModel { account_id: *TEST_ACCOUNT_1_ID, tx_ids: vec![1] }).await;
+    assert!(matches!(res, Err(Error::AccountDoesNotOwnTransaction(_, 1))));
+
+    let trash = svc.get_trash(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID).await?;
+    assert_eq!(vec![2, 1], trash.iter().map(|trashed| trashed.tx.id).collect::<Vec<i32>>());
+    assert!(trash.iter().all(|trashed| trashed.delete_on == *TEST_DT + Duration::hours(1)));
+
+    let restored = svc.restore_transactions(*TEST_USER_1_ID, RestoreTransactionsModel { account_id: *TEST_ACCOUNT_1_ID, tx_ids: vec![2] }).await?;
+    assert_eq!(1, restored.len());
+    assert_eq!(3000, restored[0].am);
+    let cats = Categories::find().all(&db).await?;
+    assert_eq!(deleted_bal + 3000, cats[0].balance);
+
+    let res = svc.restore_transactions(*TEST_USER_1_ID, RestoreTransactionsModel { account_id: *TEST_ACCOUNT_1_ID, tx_ids: vec![2] }).await;
+    assert!(matches!(res, Err(Error::TransactionNotInTrash(2))));
+
+    // Nothing is purged until the retention expires
+    assert_eq!(0, svc.purge_trash().await?);
+    svc.config.trash_retention_s = -1;
+    svc.delete_transactions(*TEST_USER_1_ID, DeleteTransactionsModel { account_id: *TEST_ACCOUNT_1_ID, tx_ids: vec![3] }).await?;
+    assert_eq!(1, svc.purge_trash().await?);
+    assert_eq!(1, svc.get_trash(*TEST_USER_1_ID, *TEST_ACCOUNT_1_ID).await?.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reconciled_transactions_are_locked() -> anyhow::Result<()> {
+    let (svc, db) = create_test_service().await?;
+    test_transact_1(&svc).await?;
+
+    let mut tx = Transactions::find_by_id(1).one(&db).await?.unwrap().into_active_model();
+    tx.is_cleared = Set(true);
+    tx.reconciliation_id = Set(Some(Uuid::now_v7()));
+    Transactions::update(tx).exec(&db).await?;
+
+    let update = |new_amount: Option<i64>, new_notes: Option<String>| UpdateTransactionModel {
+        account_id: *TEST_ACCOUNT_1_ID,
+        tx_id: 1,
+        new_amount,
+        currency_type: None,
+        new_category_id: None,
+        new_notes,
+        new_timestamp_utc: None,
+        new_splits: None,
+        new_exchange_rate: None,
+        new_tag_ids: None,
+        new_payee_id: None,
+    };
+    let res = svc.update_transaction(*TEST_USER_1_ID, update(Some(2000), None)).await;
+    assert!(matches!(res, Err(Error::ReconciledTransaction(1))));
+    let res = svc.delete_transactions(*TEST_USER_1_ID, DeleteTransactionsModel { account_id: *TEST_ACCOUNT_1_ID, tx_ids: vec![1] }).await;
+    assert!(matches!(res, Err(Error::ReconciledTransaction(1))));
+
+    // Deleting one side of a transfer would also delete its reconciled partner
+    let transfer_id = Uuid::now_v7();
+    let mut transfer_tx_ids = vec![];
+    for (cat_id, amount, reconciliation_id) in [(*TEST_CAT_1_ID, -300, None), (*TEST_CAT_2_ID, 300, Some(Uuid::now_v7()))] {
+        let transfer_tx = transactions::ActiveModel {
+            account_id: Set(*TEST_ACCOUNT_1_ID),
+            category_id: Set(Some(cat_id)),
+            timestamp: Set(*TEST_DT),
+            amount: Set(amount),
+            is_refill: Set(false),
+            transfer_id: Set(Some(transfer_id)),
+            reconciliation_id: Set(reconciliation_id),
+
+            ..Default::default()
+        };
+        transfer_tx_ids.push(Transactions::insert(transfer_tx).exec(&db).await?.last_insert_id);
+    }
+    let res = svc.delete_transactions(*TEST_USER_1_ID, DeleteTransactionsModel { account_id: *TEST_ACCOUNT_1_ID, tx_ids: vec![transfer_tx_ids[0]] }).await;
+    assert!(matches!(res, Err(Error::ReconciledTransaction(id)) if id == transfer_tx_ids[1]));
+
+    // Notes do not affect the reconciled balance, so they can still be changed
+    let updated = svc.update_transaction(*TEST_USER_1_ID, update(None, Some(String::from("Checked")))).await?;
+    assert_eq!(Some(String::from("Checked")), updated.notes);
+    assert!(updated.is_cleared);
+
+    Ok(())
+}